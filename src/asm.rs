@@ -0,0 +1,485 @@
+//! Assembler for the mnemonics printed by [`Instruction`]'s `Display`, so
+//! listings from [`crate::disasm`] assemble back to the same bytes.
+//!
+//! ```text
+//! ; comments run to the end of the line
+//! SPEED EQU 2
+//!     ORG $200
+//! start:
+//!     MOV I, ball
+//!     DRAW V0, V1, 4
+//!     ADD V0, SPEED
+//!     JUMP start
+//! ball:
+//!     db %01100000, $F0, $F0, 96
+//!     dw $1234, ball + 2
+//!     INCLUDE "more.asm"
+//! ```
+//!
+//! Numbers are decimal, `$` or `0x` hex, or `%` or `0b` binary, and can be
+//! added and subtracted along with labels and constants. Registers are
+//! `V0`-`V15` (or `VA`-`VF`), and `I`, `DT`, `ST`, `R` and `K0`-`K15` are
+//! reserved. The output starts at the first `ORG`, or 0x200 without one.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cpu::{Address, Instruction, Register};
+use crate::source_map::SourceMap;
+
+const DEFAULT_ORIGIN: u16 = 0x200;
+// Deep enough for any sensible program, and stops include cycles.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    /// 1 based.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Assembles source text. Includes are relative to the working directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut lines = Vec::new();
+    load(source, "<source>", Path::new("."), 0, &mut lines)?;
+    Assembler::default().run(&lines).map(|(rom, _)| rom)
+}
+
+/// Assembles a file. Includes are relative to the file that includes them.
+pub fn assemble_file(path: impl AsRef<Path>) -> Result<Vec<u8>, AsmError> {
+    assemble_file_with_map(path).map(|(rom, _)| rom)
+}
+
+/// Like [`assemble_file`], also returning where every byte came from. Files
+/// are named as they were found, the included ones relative to `path`.
+pub fn assemble_file_with_map(path: impl AsRef<Path>) -> Result<(Vec<u8>, SourceMap), AsmError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|err| AsmError {
+        file: path.display().to_string(),
+        line: 0,
+        message: err.to_string(),
+    })?;
+    let mut lines = Vec::new();
+    load(&source, &path.display().to_string(), path.parent().unwrap_or(Path::new(".")), 0, &mut lines)?;
+    Assembler::default().run(&lines)
+}
+
+#[derive(Debug)]
+struct SourceLine {
+    file: String,
+    line: usize,
+    text: String,
+}
+
+impl SourceLine {
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError { file: self.file.clone(), line: self.line, message: message.into() }
+    }
+}
+
+/// Splits a file into lines without comments, expanding includes.
+fn load(source: &str, file: &str, dir: &Path, depth: usize, out: &mut Vec<SourceLine>) -> Result<(), AsmError> {
+    for (i, text) in source.lines().enumerate() {
+        let line = SourceLine {
+            file: file.to_string(),
+            line: i + 1,
+            text: text.split(';').next().unwrap().trim().to_string(),
+        };
+
+        let Some(rest) = strip_keyword(&line.text, "INCLUDE") else {
+            out.push(line);
+            continue;
+        };
+
+        let name = rest.strip_prefix('"').and_then(|rest| rest.strip_suffix('"'))
+            .ok_or_else(|| line.error("expected INCLUDE \"file\""))?;
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(line.error("includes are nested too deeply"));
+        }
+        let path: PathBuf = dir.join(name);
+        let included = fs::read_to_string(&path)
+            .map_err(|err| line.error(format!("could not include {}: {}", path.display(), err)))?;
+        load(&included, &path.display().to_string(), path.parent().unwrap_or(dir), depth + 1, out)?;
+    }
+    Ok(())
+}
+
+/// The text after `keyword` if the line starts with it, in any case.
+fn strip_keyword<'a>(text: &'a str, keyword: &str) -> Option<&'a str> {
+    let (word, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    word.eq_ignore_ascii_case(keyword).then(|| rest.trim())
+}
+
+#[derive(Debug, Clone)]
+enum Atom {
+    Number(i64),
+    Name(String),
+}
+
+/// Numbers and names added or subtracted together.
+#[derive(Debug, Clone)]
+struct Expr {
+    terms: Vec<(bool, Atom)>,
+}
+
+impl Expr {
+    fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err("expected a value".to_string());
+        }
+
+        let mut terms = Vec::new();
+        let mut negative = false;
+        let mut rest = text;
+        if let Some(stripped) = rest.strip_prefix('-') {
+            negative = true;
+            rest = stripped;
+        }
+
+        loop {
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            let atom = rest[..end].trim();
+            terms.push((negative, Atom::parse(atom).ok_or_else(|| format!("invalid value '{}'", atom))?));
+            if end == rest.len() { break; }
+            negative = rest[end..].starts_with('-');
+            rest = &rest[end + 1..];
+        }
+
+        Ok(Expr { terms })
+    }
+}
+
+impl Atom {
+    fn parse(text: &str) -> Option<Self> {
+        let number = |digits: &str, radix| i64::from_str_radix(digits, radix).ok().map(Atom::Number);
+        let lower = text.to_ascii_lowercase();
+
+        if let Some(hex) = text.strip_prefix('$').or_else(|| lower.strip_prefix("0x")) {
+            number(hex, 16)
+        } else if let Some(bin) = text.strip_prefix('%').or_else(|| lower.strip_prefix("0b")) {
+            number(bin, 2)
+        } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+            number(text, 10)
+        } else if is_name(text) {
+            Some(Atom::Name(text.to_string()))
+        } else {
+            None
+        }
+    }
+}
+
+fn is_name(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    V(u8),
+    /// `Vx..Vy`
+    Range(u8, u8),
+    /// `..Vx`
+    UpTo(u8),
+    I,
+    /// `[I]`
+    AtI,
+    Dt,
+    St,
+    R,
+    Key(u8),
+    /// `LONG nnnn`
+    Long(Expr),
+    /// `V0 + nnn`
+    V0Plus(Expr),
+    Value(Expr),
+}
+
+impl Operand {
+    fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let upper = text.to_ascii_uppercase();
+        let expect_register = |text: &str| register(text).ok_or_else(|| format!("expected a register, found '{}'", text));
+
+        if upper == "[I]" {
+            return Ok(Operand::AtI);
+        }
+        if let Some(x) = text.strip_prefix("..") {
+            return Ok(Operand::UpTo(expect_register(x)?));
+        }
+        if let Some((x, y)) = text.split_once("..") {
+            return Ok(Operand::Range(expect_register(x)?, expect_register(y)?));
+        }
+        match upper.as_str() {
+            "I" => return Ok(Operand::I),
+            "DT" => return Ok(Operand::Dt),
+            "ST" => return Ok(Operand::St),
+            "R" => return Ok(Operand::R),
+            _ => {}
+        }
+        if let Some(key) = upper.strip_prefix('K').and_then(|key| register(&format!("V{}", key))) {
+            return Ok(Operand::Key(key));
+        }
+        if let Some(rest) = strip_keyword(text, "LONG") {
+            return Ok(Operand::Long(Expr::parse(rest)?));
+        }
+        if let Some((x, rest)) = text.split_once('+') {
+            if let Some(x) = register(x) {
+                if x != 0 { return Err("only V0 can be added to a jump".to_string()); }
+                return Ok(Operand::V0Plus(Expr::parse(rest)?));
+            }
+        }
+        if let Some(x) = register(text) {
+            return Ok(Operand::V(x));
+        }
+        Ok(Operand::Value(Expr::parse(text)?))
+    }
+}
+
+/// `V0`-`V15` or `VA`-`VF`.
+fn register(text: &str) -> Option<u8> {
+    let text = text.trim();
+    let digits = text.strip_prefix(['V', 'v'])?;
+    let x = if digits.len() == 1 {
+        u8::from_str_radix(digits, 16).ok()?
+    } else if digits.chars().all(|c| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    (x < 16).then_some(x)
+}
+
+#[derive(Debug)]
+enum Statement {
+    Instruction(String, Vec<Operand>),
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match self {
+            Self::Instruction(_, operands) if operands.iter().any(|op| matches!(op, Operand::Long(_))) => 4,
+            Self::Instruction(..) => 2,
+            Self::Bytes(values) => values.len(),
+            Self::Words(values) => values.len() * 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Symbol {
+    Label(u16),
+    Constant(Expr),
+}
+
+#[derive(Debug, Default)]
+struct Assembler {
+    symbols: HashMap<String, Symbol>,
+}
+
+impl Assembler {
+    fn run(mut self, lines: &[SourceLine]) -> Result<(Vec<u8>, SourceMap), AsmError> {
+        // First pass: give every label an address.
+        let mut origin = None;
+        let mut pc = DEFAULT_ORIGIN as usize;
+        let mut statements = Vec::new();
+
+        for line in lines {
+            let mut text = line.text.as_str();
+
+            while let Some((label, rest)) = text.split_once(':').filter(|(label, _)| is_name(label.trim())) {
+                self.define(line, label.trim(), Symbol::Label(pc as u16))?;
+                text = rest.trim();
+            }
+            if text.is_empty() { continue; }
+
+            let (word, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+            let rest = rest.trim();
+
+            if let Some(value) = strip_keyword(rest, "EQU") {
+                let expr = Expr::parse(value).map_err(|message| line.error(message))?;
+                self.define(line, word, Symbol::Constant(expr))?;
+            } else if word.eq_ignore_ascii_case("ORG") {
+                let expr = Expr::parse(rest).map_err(|message| line.error(message))?;
+                let addr = self.eval(&expr, 0)
+                    .and_then(|value| range(value, 0, 0xFFFF, "ORG"))
+                    .map_err(|message| line.error(message))? as usize;
+                match origin {
+                    None if statements.is_empty() => origin = Some(addr),
+                    _ if addr < pc => return Err(line.error("ORG can't move backwards")),
+                    _ => {}
+                }
+                pc = addr;
+            } else {
+                let values = || rest.split(',').map(Expr::parse).collect::<Result<Vec<_>, _>>();
+                let statement = if word.eq_ignore_ascii_case("db") {
+                    Statement::Bytes(values().map_err(|message| line.error(message))?)
+                } else if word.eq_ignore_ascii_case("dw") {
+                    Statement::Words(values().map_err(|message| line.error(message))?)
+                } else {
+                    let operands = if rest.is_empty() { Ok(Vec::new()) } else {
+                        rest.split(',').map(Operand::parse).collect::<Result<Vec<_>, _>>()
+                    };
+                    Statement::Instruction(word.to_ascii_uppercase(), operands.map_err(|message| line.error(message))?)
+                };
+
+                let size = statement.size();
+                statements.push((line, pc, statement));
+                pc += size;
+                if pc > 0x10000 {
+                    return Err(line.error("program runs past the end of memory"));
+                }
+            }
+        }
+
+        // Second pass: now that every name is known, emit the bytes.
+        let origin = origin.unwrap_or(DEFAULT_ORIGIN as usize);
+        let end = statements.last().map_or(origin, |(_, addr, statement)| addr + statement.size());
+        let mut rom = vec![0; end - origin];
+        let mut map = SourceMap::new();
+
+        for (line, addr, statement) in &statements {
+            let bytes = self.emit(statement).map_err(|message| line.error(message))?;
+            let start = addr - origin;
+            rom[start..start + bytes.len()].copy_from_slice(&bytes);
+            map.add(*addr as u16, bytes.len() as u16, &line.file, line.line);
+        }
+
+        Ok((rom, map))
+    }
+
+    fn define(&mut self, line: &SourceLine, name: &str, symbol: Symbol) -> Result<(), AsmError> {
+        if !is_name(name) || register(name).is_some() {
+            return Err(line.error(format!("invalid name '{}'", name)));
+        }
+        if self.symbols.insert(name.to_string(), symbol).is_some() {
+            return Err(line.error(format!("'{}' is already defined", name)));
+        }
+        Ok(())
+    }
+
+    fn eval(&self, expr: &Expr, depth: usize) -> Result<i64, String> {
+        if depth > self.symbols.len() {
+            return Err("constant refers to itself".to_string());
+        }
+
+        let mut total = 0i64;
+        for (negative, atom) in &expr.terms {
+            let value = match atom {
+                Atom::Number(n) => *n,
+                Atom::Name(name) => match self.symbols.get(name) {
+                    Some(Symbol::Label(addr)) => *addr as i64,
+                    Some(Symbol::Constant(expr)) => self.eval(expr, depth + 1)?,
+                    None => return Err(format!("'{}' is not defined", name)),
+                },
+            };
+            total = if *negative { total.wrapping_sub(value) } else { total.wrapping_add(value) };
+        }
+        Ok(total)
+    }
+
+    fn emit(&self, statement: &Statement) -> Result<Vec<u8>, String> {
+        match statement {
+            Statement::Bytes(values) => values.iter()
+                .map(|expr| Ok(range(self.eval(expr, 0)?, -0x80, 0xFF, "byte")? as u8))
+                .collect(),
+            Statement::Words(values) => values.iter()
+                .map(|expr| Ok(range(self.eval(expr, 0)?, -0x8000, 0xFFFF, "word")? as u16))
+                .collect::<Result<Vec<_>, String>>()
+                .map(|words| words.iter().flat_map(|word| word.to_be_bytes()).collect()),
+            Statement::Instruction(mnemonic, operands) => Ok(self.instruction(mnemonic, operands)?.to_bytes()),
+        }
+    }
+
+    fn instruction(&self, mnemonic: &str, operands: &[Operand]) -> Result<Instruction, String> {
+        use Operand::*;
+        use Instruction as In;
+
+        let r = Register::new;
+        let byte = |expr: &Expr| Ok::<_, String>(range(self.eval(expr, 0)?, -0x80, 0xFF, "byte")? as u8);
+        let nibble = |expr: &Expr| Ok::<_, String>(range(self.eval(expr, 0)?, 0, 0xF, "nibble")? as u8);
+        let addr = |expr: &Expr| Ok::<_, String>(Address::new(range(self.eval(expr, 0)?, 0, 0xFFF, "address")? as u16));
+        let long = |expr: &Expr| Ok::<_, String>(Address::new(range(self.eval(expr, 0)?, 0, 0xFFFF, "address")? as u16));
+
+        Ok(match (mnemonic, operands) {
+            ("SCD", [Value(n)])           => In::ScrollDown(nibble(n)?),
+            ("SCU", [Value(n)])           => In::ScrollUp(nibble(n)?),
+            ("CLS", [])                   => In::Clear,
+            ("RET", [])                   => In::Return,
+            ("SCR", [])                   => In::ScrollRight,
+            ("SCL", [])                   => In::ScrollLeft,
+            ("EXIT", [])                  => In::Exit,
+            ("LOW", [])                   => In::Lores,
+            ("HIGH", [])                  => In::Hires,
+            ("JUMP", [Value(a)])          => In::Jump(addr(a)?),
+            ("JUMP", [V0Plus(a)])         => In::JumpV0(addr(a)?),
+            ("CALL", [Value(a)])          => In::Call(addr(a)?),
+            ("SEQ", [V(x), Value(n)])     => In::EqNum(r(*x), byte(n)?),
+            ("SEQ", [V(x), V(y)])         => In::Eq(r(*x), r(*y)),
+            ("SNE", [V(x), Value(n)])     => In::NeqNum(r(*x), byte(n)?),
+            ("SNE", [V(x), V(y)])         => In::Neq(r(*x), r(*y)),
+            ("MOV", [V(x), Value(n)])     => In::SetNum(r(*x), byte(n)?),
+            ("MOV", [V(x), V(y)])         => In::Move(r(*x), r(*y)),
+            ("MOV", [V(x), Dt])           => In::GetDelay(r(*x)),
+            ("MOV", [Dt, V(x)])           => In::SetDelay(r(*x)),
+            ("MOV", [St, V(x)])           => In::SetSound(r(*x)),
+            ("MOV", [I, Value(a)])        => In::SetIdx(addr(a)?),
+            ("MOV", [I, Long(a)])         => In::LongIdx(long(a)?),
+            ("MOV", [AtI, UpTo(x)])       => In::Store(r(*x)),
+            ("MOV", [UpTo(x), AtI])       => In::Load(r(*x)),
+            ("MOV", [AtI, Range(x, y)])   => In::SaveRange(r(*x), r(*y)),
+            ("MOV", [Range(x, y), AtI])   => In::LoadRange(r(*x), r(*y)),
+            ("MOV", [R, UpTo(x)])         => In::SaveFlags(r(*x)),
+            ("MOV", [UpTo(x), R])         => In::LoadFlags(r(*x)),
+            ("ADD", [V(x), Value(n)])     => In::AddNum(r(*x), byte(n)?),
+            ("ADD", [V(x), V(y)])         => In::Add(r(*x), r(*y)),
+            ("ADD", [I, V(x)])            => In::AddIdx(r(*x)),
+            ("OR", [V(x), V(y)])          => In::Or(r(*x), r(*y)),
+            ("AND", [V(x), V(y)])         => In::And(r(*x), r(*y)),
+            ("XOR", [V(x), V(y)])         => In::Xor(r(*x), r(*y)),
+            ("SUB", [V(x), V(y)])         => In::Sub(r(*x), r(*y)),
+            ("SHR", [V(x), V(y)])         => In::Shr(r(*x), r(*y)),
+            ("SUBB", [V(x), V(y)])        => In::Subb(r(*x), r(*y)),
+            ("SHL", [V(x), V(y)])         => In::Shl(r(*x), r(*y)),
+            ("RAND", [V(x), Value(n)])    => In::Rand(r(*x), byte(n)?),
+            ("DRAW", [V(x), V(y), Value(n)]) => In::Draw(r(*x), r(*y), nibble(n)?),
+            ("SKD", [V(x)])               => In::KeyUp(r(*x)),
+            ("SKU", [V(x)])               => In::KeyDown(r(*x)),
+            ("WAIT", [Key(x) | V(x)])     => In::WaitKey(r(*x)),
+            ("CHAR", [V(x)])              => In::SetSprite(r(*x)),
+            ("HCHAR", [V(x)])             => In::SetBigSprite(r(*x)),
+            ("BCD", [V(x)])               => In::StoreBcd(r(*x)),
+            ("PITCH", [V(x)])             => In::Pitch(r(*x)),
+            ("PLANE", [Value(n)])         => In::Plane(nibble(n)?),
+            ("AUDIO", [])                 => In::Audio,
+            _ if MNEMONICS.contains(&mnemonic) => return Err(format!("invalid operands for {}", mnemonic)),
+            _ => return Err(format!("unknown instruction '{}'", mnemonic)),
+        })
+    }
+}
+
+const MNEMONICS: &[&str] = &[
+    "SCD", "SCU", "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JUMP", "CALL", "SEQ", "SNE", "MOV",
+    "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBB", "SHL", "RAND", "DRAW", "SKD", "SKU", "WAIT", "CHAR",
+    "HCHAR", "BCD", "PITCH", "PLANE", "AUDIO",
+];
+
+fn range(value: i64, min: i64, max: i64, what: &str) -> Result<i64, String> {
+    if value < min || value > max {
+        return Err(format!("{} {} is out of range", what, value));
+    }
+    Ok(value & max)
+}
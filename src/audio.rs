@@ -0,0 +1,271 @@
+//! Sound for the sound timer: a [`Beeper`] turns the [`Sound`] of a
+//! [`System`] into samples, and [`Audio`] feeds them to an [`AudioSink`].
+//!
+//! Samples are mono `f32`s between -1 and 1. The frontend plays them on the
+//! speakers, and [`WavSink`] keeps them for a WAV file, which works without
+//! any audio hardware.
+//!
+//! Timing follows emulated time, not wall time: the system logs every change
+//! to its sound with the [`System::get_time`] it happened at, and [`Audio`]
+//! starts and stops the beep on the sample of each change.
+
+use crate::system::System;
+
+/// Samples per second used unless a sink asks for something else.
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// Somewhere for samples to go.
+pub trait AudioSink {
+    /// Samples per second the sink expects.
+    fn sample_rate(&self) -> u32;
+
+    /// Queues `samples` after the ones before them.
+    fn play(&mut self, samples: &[f32]);
+}
+
+impl<S: AudioSink + ?Sized> AudioSink for Box<S> {
+    fn sample_rate(&self) -> u32 { (**self).sample_rate() }
+    fn play(&mut self, samples: &[f32]) { (**self).play(samples) }
+}
+
+/// `None` drops everything, like [`NullSink`].
+impl<S: AudioSink> AudioSink for Option<S> {
+    fn sample_rate(&self) -> u32 { self.as_ref().map_or(SAMPLE_RATE, S::sample_rate) }
+
+    fn play(&mut self, samples: &[f32]) {
+        if let Some(sink) = self { sink.play(samples); }
+    }
+}
+
+/// Plays into both sinks at the first one's sample rate, which the second
+/// should share.
+impl<A: AudioSink, B: AudioSink> AudioSink for (A, B) {
+    fn sample_rate(&self) -> u32 { self.0.sample_rate() }
+
+    fn play(&mut self, samples: &[f32]) {
+        self.0.play(samples);
+        self.1.play(samples);
+    }
+}
+
+/// A sink that drops everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn sample_rate(&self) -> u32 { SAMPLE_RATE }
+    fn play(&mut self, _samples: &[f32]) {}
+}
+
+/// A sink that keeps every sample, to be written out with [`WavSink::to_wav`].
+#[derive(Debug, Clone, Default)]
+pub struct WavSink {
+    sample_rate: u32,
+    samples: Vec<f32>,
+}
+
+impl WavSink {
+    pub fn new(sample_rate: u32) -> Self {
+        WavSink { sample_rate, samples: Vec::new() }
+    }
+
+    pub fn samples(&self) -> &[f32] { &self.samples }
+
+    pub fn to_wav(&self) -> Vec<u8> { wav(&self.samples, self.sample_rate) }
+}
+
+impl AudioSink for WavSink {
+    fn sample_rate(&self) -> u32 { self.sample_rate }
+    fn play(&mut self, samples: &[f32]) { self.samples.extend_from_slice(samples); }
+}
+
+/// A 16 bit mono PCM WAV file.
+// http://soundfile.sapp.org/doc/WaveFormat/
+pub fn wav(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let mut out = Vec::with_capacity(44 + data_len as usize);
+
+    out.extend(b"RIFF");
+    out.extend((36 + data_len).to_le_bytes());
+    out.extend(b"WAVE");
+
+    out.extend(b"fmt ");
+    out.extend(16u32.to_le_bytes());
+    out.extend(1u16.to_le_bytes()); // PCM
+    out.extend(1u16.to_le_bytes()); // mono
+    out.extend(sample_rate.to_le_bytes());
+    out.extend((sample_rate * 2).to_le_bytes()); // bytes per second
+    out.extend(2u16.to_le_bytes()); // bytes per sample
+    out.extend(16u16.to_le_bytes()); // bits per sample
+
+    out.extend(b"data");
+    out.extend(data_len.to_le_bytes());
+    for sample in samples {
+        out.extend(((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes());
+    }
+
+    out
+}
+
+/// What a system should sound like.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sound {
+    /// Whether the sound timer is running.
+    pub playing: bool,
+    /// The XO-CHIP audio pattern and its rate in bits per second, if the rom
+    /// has loaded one.
+    pub pattern: Option<([u8; 16], f64)>,
+}
+
+impl Sound {
+    pub fn of(system: &System) -> Self {
+        Sound {
+            playing: system.get_sound(),
+            pattern: system.get_audio_pattern().map(|pattern| (*pattern, system.get_audio_rate())),
+        }
+    }
+}
+
+/// The sound of a system changing, at [`System::get_time`] `time`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoundChange {
+    pub time: f64,
+    pub sound: Sound,
+}
+
+/// Shape of the tone the beeper plays.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl Waveform {
+    /// Names accepted by [`Waveform::from_name`].
+    pub const NAMES: [&'static str; 4] = ["square", "triangle", "sawtooth", "sine"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "square"           => Some(Self::Square),
+            "triangle"         => Some(Self::Triangle),
+            "sawtooth" | "saw" => Some(Self::Sawtooth),
+            "sine"             => Some(Self::Sine),
+            _ => None,
+        }
+    }
+
+    /// The level at `phase`, from 0 to 1 through one period.
+    fn sample(self, phase: f64) -> f32 {
+        let value = match self {
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (phase * std::f64::consts::TAU).sin(),
+        };
+        value as f32
+    }
+}
+
+/// Generates a [`Sound`]: silence unless it is playing, otherwise its
+/// XO-CHIP audio pattern if it has one, or a tone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Beeper {
+    /// Pitch of the tone in hertz. The XO-CHIP pattern has its own.
+    pub frequency: f64,
+    /// From 0 to 1.
+    pub volume: f32,
+    pub waveform: Waveform,
+    // Position in the tone's period, or the pattern's 128 bits, from 0 to 1.
+    phase: f64,
+}
+
+impl Beeper {
+    pub const DEFAULT_FREQUENCY: f64 = 440.0;
+    pub const DEFAULT_VOLUME: f32 = 0.25;
+
+    pub fn new(frequency: f64, volume: f32, waveform: Waveform) -> Self {
+        Beeper { frequency, volume, waveform, phase: 0.0 }
+    }
+
+    /// Fills `samples` with `sound`, carrying on from the last call.
+    pub fn fill(&mut self, sound: &Sound, samples: &mut [f32], sample_rate: u32) {
+        if !sound.playing {
+            // Starting the next beep from the top of the wave avoids a click.
+            self.phase = 0.0;
+            samples.fill(0.0);
+            return;
+        }
+
+        let step = match sound.pattern {
+            Some((_, rate)) => rate / 128.0 / sample_rate as f64,
+            None => self.frequency / sample_rate as f64,
+        };
+        for sample in samples {
+            let level = match &sound.pattern {
+                Some((pattern, _)) => {
+                    let bit = (self.phase * 128.0) as usize;
+                    if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 { 1.0 } else { -1.0 }
+                }
+                None => self.waveform.sample(self.phase),
+            };
+            *sample = level * self.volume;
+            self.phase = (self.phase + step).fract();
+        }
+    }
+}
+
+impl Default for Beeper {
+    fn default() -> Self {
+        Beeper::new(Beeper::DEFAULT_FREQUENCY, Beeper::DEFAULT_VOLUME, Waveform::default())
+    }
+}
+
+/// Plays a system's sound into a sink as the system runs.
+#[derive(Debug)]
+pub struct Audio<S> {
+    pub beeper: Beeper,
+    sink: S,
+    sound: Sound,
+    // System time of the first sample, and samples played since.
+    start: f64,
+    played: u64,
+    buffer: Vec<f32>,
+}
+
+impl<S: AudioSink> Audio<S> {
+    /// Starts listening to `system`, which logs its sound from now on.
+    pub fn new(beeper: Beeper, sink: S, system: &mut System) -> Self {
+        system.record_sound(true);
+        Audio { beeper, sink, sound: Sound::of(system), start: system.get_time(), played: 0, buffer: Vec::new() }
+    }
+
+    /// Plays what `system` sounded like since the last update, up to its
+    /// current time.
+    pub fn update(&mut self, system: &mut System) {
+        for change in system.take_sound_changes() {
+            self.play_until(change.time);
+            self.sound = change.sound;
+        }
+        self.play_until(system.get_time());
+    }
+
+    /// Plays the current sound up to the sample at system time `time`.
+    fn play_until(&mut self, time: f64) {
+        let sample_rate = self.sink.sample_rate();
+        let end = ((time - self.start) * sample_rate as f64).round().max(0.0) as u64;
+        let count = end.saturating_sub(self.played) as usize;
+        if count == 0 { return; }
+
+        self.buffer.resize(count, 0.0);
+        self.beeper.fill(&self.sound, &mut self.buffer, sample_rate);
+        self.sink.play(&self.buffer);
+        self.played = end;
+    }
+
+    pub fn sink(&self) -> &S { &self.sink }
+
+    pub fn into_sink(self) -> S { self.sink }
+}
@@ -0,0 +1,333 @@
+use chip8::{Quirks, Timing, Watchpoint};
+use chip8::audio::{Beeper, Waveform};
+use chip8::headless::RunLength;
+use chip8::movie::Schedule;
+use chip8::system::DEFAULT_IPS;
+use chip8::trace::Filter;
+
+pub const USAGE: &str = "\
+Usage: chip8 [options] <rom>
+       chip8 headless [options] [headless options] <rom>
+       chip8 gdb [--port <n>] [options] <rom>
+       chip8 dap
+       chip8 trace [trace filters] <trace>
+       chip8 diff [options] [diff options] <rom or trace> [<rom or trace>]
+       chip8 disasm <rom>
+       chip8 asm [--output <file>] <source>
+
+A rom can be a .ch8 image or .8o Octo source.
+
+Options:
+    --quirks <name>    Interpreter to emulate: vip, chip48, schip, xochip (default: xochip)
+    --timing <name>    How long instructions take: fixed (--ips a second) or vip (as on the
+                       COSMAC VIP, best with --quirks vip) (default: fixed)
+    --ips <n>          Instructions per second with fixed timing (default: 500)
+    --fast-forward <n> Speed multiple while Tab is held (default: as fast as possible)
+    --seed <number>    Seed for the random number generator (default: random)
+    --schedule <name>  How each 1/60s frame runs: frame (a 60th of --ips instructions,
+                       then a timer tick) or dt (the frame's length in seconds), stored
+                       in recorded movies (default: frame)
+    --record <file>    Record keypad input to a movie file
+    --play <file>      Play back a movie file instead of reading the keyboard
+    --break <addr>     Pause the debugger when the PC reaches a hex address, can be repeated
+    --watch <spec>     Pause the debugger after read:ADDR[+LEN], write:ADDR[+LEN], vX[=VALUE]
+                       or i[=VALUE] (all hex), can be repeated
+    --trace <file>     Write every executed instruction to a binary trace, read it with 'chip8 trace'
+    --volume <0-100>   Loudness of the beeper in percent (default: 25, 0 mutes it)
+    --tone <hz>        Pitch of the beeper (default: 440)
+    --waveform <name>  Shape of the beeper's tone: square, triangle, sawtooth, sine (default: square)
+    --wav <file>       Also write the sound to a WAV file, timed by emulated time
+
+Trace filters, for --trace and 'chip8 trace':
+    --trace-pc <from>-<to>      Only instructions at these hex addresses
+    --trace-opcodes <classes>   Only opcodes starting with these hex digits, e.g. D,F
+    --trace-cycles <from>-<to>  Only this window of instructions, counted from 0
+
+Diff options, comparing two traces, or running two roms (or one rom twice) in lockstep:
+    --quirks-b <name>  Interpreter for the second rom (default: the same as --quirks)
+    --cycles <n>       Compare for n instructions (default: 10000)
+    --context <n>      Instructions to show either side of the divergence (default: 5)
+
+Headless options:
+    --frames <n>       Run for n frames of 1/60s (default: 600, or the length of the movie)
+    --cycles <n>       Run for n instructions instead of frames
+    --input <file>     Script of '<frame> <key> <0|1>' lines pressing and releasing keys
+    --screen <file>    Write the final screen as .png, .pbm or .txt (ascii art)
+    --registers <file> Write the final registers as JSON
+";
+
+const DEFAULT_GDB_PORT: u16 = 1234;
+const DEFAULT_DIFF_CYCLES: u64 = 10000;
+
+#[derive(Debug)]
+pub enum Command {
+    Window,
+    Headless(HeadlessOptions),
+    /// Waits for a gdb client on localhost and serves it the rom.
+    Gdb { port: u16 },
+    /// Serves the Debug Adapter Protocol on stdin and stdout. The rom comes
+    /// from the client's launch request, so `rom` is empty.
+    Dap,
+    /// Prints the binary trace in `rom` as text.
+    Trace,
+    /// Compares `rom` with another rom or trace.
+    Diff(DiffOptions),
+    Disasm,
+    /// Assembles the source in `rom`, by default to the same name with a
+    /// .ch8 extension.
+    Asm { output: Option<String> },
+}
+
+#[derive(Debug, Default)]
+pub struct HeadlessOptions {
+    pub length: Option<RunLength>,
+    pub input: Option<String>,
+    pub screen: Option<String>,
+    pub registers: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct DiffOptions {
+    /// The second file, or `None` to run `rom` against itself.
+    pub other: Option<String>,
+    /// Quirks for the second rom, when they differ from the first.
+    pub quirks: Option<Quirks>,
+    pub cycles: u64,
+    pub context: usize,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions { other: None, quirks: None, cycles: DEFAULT_DIFF_CYCLES, context: chip8::diff::DEFAULT_CONTEXT }
+    }
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    pub rom: String,
+    pub quirks: Quirks,
+    pub timing: Timing,
+    pub ips: u32,
+    pub schedule: Schedule,
+    /// Speed multiple while fast-forwarding, or `None` for as fast as possible.
+    pub fast_forward: Option<f64>,
+    pub seed: Option<u64>,
+    pub record: Option<String>,
+    pub play: Option<String>,
+    pub breakpoints: Vec<u16>,
+    pub watchpoints: Vec<Watchpoint>,
+    pub trace: Option<String>,
+    pub trace_filter: Filter,
+    pub beeper: Beeper,
+    pub wav: Option<String>,
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.into_iter().peekable();
+        let mut rom = None;
+        let mut quirks = Quirks::default();
+        let mut timing = Timing::default();
+        let mut ips = DEFAULT_IPS;
+        let mut schedule = Schedule::default();
+        let mut fast_forward = None;
+        let mut seed = None;
+        let mut record = None;
+        let mut play = None;
+        let mut breakpoints = Vec::new();
+        let mut watchpoints = Vec::new();
+        let mut trace = None;
+        let mut trace_filter = Filter::default();
+        let mut beeper = Beeper::default();
+        let mut wav = None;
+
+        let mut headless = None;
+        let mut gdb = None;
+        let mut dap = false;
+        let mut render_trace = false;
+        let mut diff = None;
+        let mut disasm = false;
+        let mut asm = None;
+        match args.peek().map(String::as_str) {
+            Some("headless") => { args.next(); headless = Some(HeadlessOptions::default()); }
+            Some("gdb") => { args.next(); gdb = Some(DEFAULT_GDB_PORT); }
+            Some("dap") => { args.next(); dap = true; }
+            Some("trace") => { args.next(); render_trace = true; }
+            Some("diff") => { args.next(); diff = Some(DiffOptions::default()); }
+            Some("disasm") => { args.next(); disasm = true; }
+            Some("asm") => { args.next(); asm = Some(None); }
+            _ => {}
+        }
+
+        while let Some(arg) = args.next() {
+            if let Some(diff) = &mut diff {
+                match arg.as_str() {
+                    "--quirks-b" => { diff.quirks = Some(quirks_value(&mut args, &arg)?); continue; }
+                    "--cycles" => { diff.cycles = number(&mut args, &arg)?; continue; }
+                    "--context" => { diff.context = number(&mut args, &arg)?; continue; }
+                    _ => {}
+                }
+            }
+
+            match (arg.as_str(), &mut headless) {
+                ("--quirks", _) => quirks = quirks_value(&mut args, &arg)?,
+                ("--timing", _) => {
+                    let name = value(&mut args, &arg)?;
+                    timing = Timing::from_name(&name).ok_or_else(|| {
+                        format!("Unknown timing '{}', expected one of: {}", name, Timing::NAMES.join(", "))
+                    })?;
+                }
+                ("--ips", _) => {
+                    ips = number(&mut args, &arg)?;
+                    if ips == 0 {
+                        return Err(format!("'{}' needs at least 1 instruction per second", arg));
+                    }
+                }
+                ("--fast-forward", _) => {
+                    let rate: f64 = number(&mut args, &arg)?;
+                    if rate <= 1.0 {
+                        return Err(format!("Speed {} for '{}' is not faster than normal", rate, arg));
+                    }
+                    fast_forward = Some(rate);
+                }
+                ("--schedule", _) => {
+                    let name = value(&mut args, &arg)?;
+                    schedule = Schedule::from_name(&name).ok_or_else(|| {
+                        format!("Unknown schedule '{}', expected one of: {}", name, Schedule::NAMES.join(", "))
+                    })?;
+                }
+                ("--seed", _) => seed = Some(number(&mut args, &arg)?),
+                ("--record", _) => record = Some(value(&mut args, &arg)?),
+                ("--play", _) => play = Some(value(&mut args, &arg)?),
+                ("--break", None) => {
+                    let addr = value(&mut args, &arg)?;
+                    let digits = addr.trim_start_matches('$').trim_start_matches("0x");
+                    breakpoints.push(u16::from_str_radix(digits, 16)
+                        .map_err(|_| format!("Invalid address '{}' for '{}'", addr, arg))?);
+                }
+                ("--watch", None) => {
+                    let spec = value(&mut args, &arg)?;
+                    watchpoints.push(spec.parse().map_err(|err| format!("Invalid watchpoint '{}' for '{}': {}", spec, arg, err))?);
+                }
+                ("--volume", _) => {
+                    let percent: f32 = number(&mut args, &arg)?;
+                    if !(0.0..=100.0).contains(&percent) {
+                        return Err(format!("Volume {} for '{}' is not between 0 and 100", percent, arg));
+                    }
+                    beeper.volume = percent / 100.0;
+                }
+                ("--tone", _) => beeper.frequency = number(&mut args, &arg)?,
+                ("--waveform", _) => {
+                    let name = value(&mut args, &arg)?;
+                    beeper.waveform = Waveform::from_name(&name).ok_or_else(|| {
+                        format!("Unknown waveform '{}', expected one of: {}", name, Waveform::NAMES.join(", "))
+                    })?;
+                }
+                ("--wav", _) => wav = Some(value(&mut args, &arg)?),
+                ("--trace", _) => trace = Some(value(&mut args, &arg)?),
+                ("--trace-pc", _) => {
+                    let (from, to) = range(&value(&mut args, &arg)?, &arg, |text| {
+                        u16::from_str_radix(text.trim_start_matches('$').trim_start_matches("0x"), 16).ok()
+                    })?;
+                    trace_filter.addresses = Some(from..=to);
+                }
+                ("--trace-opcodes", _) => {
+                    let classes = value(&mut args, &arg)?;
+                    let mask = classes.split(',').try_fold(0u16, |mask, digit| {
+                        u8::from_str_radix(digit.trim(), 16).ok().filter(|&digit| digit < 16).map(|digit| mask | 1 << digit)
+                    });
+                    trace_filter.opcode_classes = Some(mask.ok_or_else(|| format!("Invalid opcode classes '{}' for '{}'", classes, arg))?);
+                }
+                ("--trace-cycles", _) => {
+                    let (from, to) = range(&value(&mut args, &arg)?, &arg, |text| text.parse().ok())?;
+                    trace_filter.cycles = Some(from..=to);
+                }
+                ("--frames", Some(headless)) => headless.length = Some(RunLength::Frames(number(&mut args, &arg)?)),
+                ("--cycles", Some(headless)) => headless.length = Some(RunLength::Cycles(number(&mut args, &arg)?)),
+                ("--input", Some(headless)) => headless.input = Some(value(&mut args, &arg)?),
+                ("--screen", Some(headless)) => headless.screen = Some(value(&mut args, &arg)?),
+                ("--registers", Some(headless)) => headless.registers = Some(value(&mut args, &arg)?),
+                ("--port", _) if gdb.is_some() => gdb = Some(number(&mut args, &arg)?),
+                ("--output", _) if asm.is_some() => asm = Some(Some(value(&mut args, &arg)?)),
+                _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'", arg)),
+                _ if rom.is_none() => rom = Some(arg),
+                _ => match &mut diff {
+                    Some(diff) if diff.other.is_none() => diff.other = Some(arg),
+                    _ => return Err(format!("Unexpected argument '{}'", arg)),
+                },
+            }
+        }
+
+        if record.is_some() && headless.is_some() {
+            return Err("--record is only available in the window".to_string());
+        }
+        if record.is_some() && play.is_some() {
+            return Err("--record and --play can't be used together".to_string());
+        }
+
+        if (record.is_some() || play.is_some()) && gdb.is_some() {
+            return Err("--record and --play aren't available with gdb".to_string());
+        }
+        if (record.is_some() || play.is_some()) && diff.is_some() {
+            return Err("--record and --play aren't available with diff".to_string());
+        }
+
+        Ok(Options {
+            command: match (headless, gdb, diff) {
+                (Some(headless), _, _) => Command::Headless(headless),
+                (None, Some(port), _) => Command::Gdb { port },
+                (None, None, Some(diff)) => Command::Diff(diff),
+                (None, None, None) if dap => Command::Dap,
+                (None, None, None) if render_trace => Command::Trace,
+                (None, None, None) if disasm => Command::Disasm,
+                (None, None, None) if asm.is_some() => Command::Asm { output: asm.flatten() },
+                (None, None, None) => Command::Window,
+            },
+            rom: match rom {
+                Some(rom) => rom,
+                None if dap => String::new(),
+                None => return Err("No file provided".to_string()),
+            },
+            quirks,
+            timing,
+            ips,
+            schedule,
+            fast_forward,
+            seed,
+            record,
+            play,
+            breakpoints,
+            watchpoints,
+            trace,
+            trace_filter,
+            beeper,
+            wav,
+        })
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("Missing value for '{}'", option))
+}
+
+fn quirks_value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<Quirks, String> {
+    let name = value(args, option)?;
+    Quirks::from_name(&name).ok_or_else(|| {
+        format!("Unknown quirks '{}', expected one of: {}", name, Quirks::NAMES.join(", "))
+    })
+}
+
+fn number<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>, option: &str) -> Result<T, String> {
+    let value = value(args, option)?;
+    value.parse().map_err(|_| format!("Invalid number '{}' for '{}'", value, option))
+}
+
+/// Parses `from-to`, or a single value as both ends.
+fn range<T: PartialOrd + Copy>(text: &str, option: &str, parse: impl Fn(&str) -> Option<T>) -> Result<(T, T), String> {
+    let (from, to) = text.split_once('-').unwrap_or((text, text));
+    match (parse(from.trim()), parse(to.trim())) {
+        (Some(from), Some(to)) if from <= to => Ok((from, to)),
+        _ => Err(format!("Invalid range '{}' for '{}'", text, option)),
+    }
+}
@@ -1,25 +1,26 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Address { x: u16 }
-
-impl Address {
-    pub fn new(x: u16) -> Self {
-        assert!(x < 4096);
-        Address { x }
-    }
-
-    pub fn add(&self, y: u16) -> Self {
-        Address::new(self.x + y)
-    }
-
-    pub fn sub(&self, y: u16) -> Self {
-        Address::new(self.x - y)
-    }
-
-    pub fn get(&self) -> usize { self.x as usize }
-}
-
-impl From<u16> for Address {
-    fn from(x: u16) -> Self {
-        Address::new(x)
-    }
-}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address { x: u16 }
+
+impl Address {
+    pub fn new(x: u16) -> Self {
+        Address { x }
+    }
+
+    /// Wraps around at the end of memory.
+    pub fn add(&self, y: u16) -> Self {
+        Address::new(self.x.wrapping_add(y))
+    }
+
+    /// Wraps around at the start of memory.
+    pub fn sub(&self, y: u16) -> Self {
+        Address::new(self.x.wrapping_sub(y))
+    }
+
+    pub fn get(&self) -> usize { self.x as usize }
+}
+
+impl From<u16> for Address {
+    fn from(x: u16) -> Self {
+        Address::new(x)
+    }
+}
@@ -1,133 +1,269 @@
-use crate::cpu::{Address, Register};
-
-#[derive(Debug, Clone, Copy)]
-pub enum Instruction {
-    Clear,                        // 00E0
-    Return,                       // 00EE
-    Jump(Address),                // 1NNN
-    Call(Address),                // 2NNN
-    EqNum(Register, u8),          // 3XNN
-    NeqNum(Register, u8),         // 4XNN
-    Eq(Register, Register),       // 5XY0
-    SetNum(Register, u8),         // 6XNN
-    AddNum(Register, u8),         // 7XNN
-    Move(Register, Register),     // 8XY0
-    Or(Register, Register),       // 8XY1
-    And(Register, Register),      // 8XY2
-    Xor(Register, Register),      // 8XY3
-    Add(Register, Register),      // 8XY4
-    Sub(Register, Register),      // 8XY5
-    Shr(Register, Register),      // 8XY6
-    Subb(Register, Register),     // 8XY7
-    Shl(Register, Register),      // 8XYE
-    Neq(Register, Register),      // 9XY0
-    SetIdx(Address),              // ANNN
-    JumpV0(Address),              // BNNN
-    Rand(Register, u8),           // CXNN
-    Draw(Register, Register, u8), // DXYN
-    KeyUp(Register),              // EX9E
-    KeyDown(Register),            // EXA1
-    GetDelay(Register),           // FX07
-    WaitKey(Register),            // FX0A
-    SetDelay(Register),           // FX15
-    SetSound(Register),           // FX18
-    AddIdx(Register),             // FX1E
-    SetSprite(Register),          // FX29
-    StoreBcd(Register),           // FX33
-    Store(Register),              // FX55
-    Load(Register),               // FX65
-}
-
-impl Instruction {
-    pub fn new(data: u16) -> Option<Self> {
-        let addr = Address::new(data & 0x0FFF);
-        let reg_x = Register::new(((data & 0x0F00) >> 8) as u8);
-        let reg_y = Register::new(((data & 0x00F0) >> 4) as u8);
-        let num = (data & 0x00FF) as u8;
-
-        let nib1 = ((data & 0xF000) >> 12) as u8;
-        let nib2 = ((data & 0x0F00) >> 8) as u8;
-        let nib3 = ((data & 0x00F0) >> 4) as u8;
-        let nib4 = (data & 0x000F) as u8;
-
-        match (nib1, nib2, nib3, nib4) {
-            (0x0,0x0,0xE,0x0) => Some(Self::Clear),
-            (0x0,0x0,0xE,0xE) => Some(Self::Return),
-            (0x0, _ , _ , _ ) => None, // TODO?
-            (0x1, _ , _ , _ ) => Some(Self::Jump(addr)),
-            (0x2, _ , _ , _ ) => Some(Self::Call(addr)),
-            (0x3, _ , _ , _ ) => Some(Self::EqNum(reg_x, num)),
-            (0x4, _ , _ , _ ) => Some(Self::NeqNum(reg_x, num)),
-            (0x5, _ , _ ,0x0) => Some(Self::Eq(reg_x, reg_y)),
-            (0x6, _ , _ , _ ) => Some(Self::SetNum(reg_x, num)),
-            (0x7, _ , _ , _ ) => Some(Self::AddNum(reg_x, num)),
-            (0x8, _ , _ ,0x0) => Some(Self::Move(reg_x, reg_y)),
-            (0x8, _ , _ ,0x1) => Some(Self::Or(reg_x, reg_y)),
-            (0x8, _ , _ ,0x2) => Some(Self::And(reg_x, reg_y)),
-            (0x8, _ , _ ,0x3) => Some(Self::Xor(reg_x, reg_y)),
-            (0x8, _ , _ ,0x4) => Some(Self::Add(reg_x, reg_y)),
-            (0x8, _ , _ ,0x5) => Some(Self::Sub(reg_x, reg_y)),
-            (0x8, _ , _ ,0x6) => Some(Self::Shr(reg_x, reg_y)),
-            (0x8, _ , _ ,0x7) => Some(Self::Subb(reg_x, reg_y)),
-            (0x8, _ , _ ,0xE) => Some(Self::Shl(reg_x, reg_y)),
-            (0x9, _ , _ ,0x0) => Some(Self::Neq(reg_x, reg_y)),
-            (0xA, _ , _ , _ ) => Some(Self::SetIdx(addr)),
-            (0xB, _ , _ , _ ) => Some(Self::JumpV0(addr)),
-            (0xC, _ , _ , _ ) => Some(Self::Rand(reg_x, num)),
-            (0xD, _ , _ , _ ) => Some(Self::Draw(reg_x, reg_y, nib4)),
-            (0xE, _ ,0x9,0xE) => Some(Self::KeyUp(reg_x)),
-            (0xE, _ ,0xA,0x1) => Some(Self::KeyDown(reg_x)),
-            (0xF, _ ,0x0,0x7) => Some(Self::GetDelay(reg_x)),
-            (0xF, _ ,0x0,0xA) => Some(Self::WaitKey(reg_x)),
-            (0xF, _ ,0x1,0x5) => Some(Self::SetDelay(reg_x)),
-            (0xF, _ ,0x1,0x8) => Some(Self::SetSound(reg_x)),
-            (0xF, _ ,0x1,0xE) => Some(Self::AddIdx(reg_x)),
-            (0xF, _ ,0x2,0x9) => Some(Self::SetSprite(reg_x)),
-            (0xF, _ ,0x3,0x3) => Some(Self::StoreBcd(reg_x)),
-            (0xF, _ ,0x5,0x5) => Some(Self::Store(reg_x)),
-            (0xF, _ ,0x6,0x5) => Some(Self::Load(reg_x)),
-            _ => None
-        }
-    }
-}
-
-impl std::fmt::Display for Instruction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Clear         => write!(f, "CLS"),
-            Self::Return        => write!(f, "RET"),
-            Self::Jump(a)       => write!(f, "JUMP ${:03X}", a.get()),
-            Self::Call(a)       => write!(f, "CALL ${:03X}", a.get()),
-            Self::EqNum(x, n)   => write!(f, "SEQ V{}, {}", x.get(), n),
-            Self::NeqNum(x, n)  => write!(f, "SNE V{}, {}", x.get(), n),
-            Self::Eq(x, y)      => write!(f, "SEQ V{}, V{}", x.get(), y.get()),
-            Self::SetNum(x, n)  => write!(f, "MOV V{}, {}", x.get(), n),
-            Self::AddNum(x, n)  => write!(f, "ADD V{}, {}", x.get(), n),
-            Self::Move(x, y)    => write!(f, "MOV V{}, V{}", x.get(), y.get()),
-            Self::Or(x, y)      => write!(f, "OR V{}, V{}", x.get(), y.get()),
-            Self::And(x, y)     => write!(f, "AND V{}, V{}", x.get(), y.get()),
-            Self::Xor(x, y)     => write!(f, "XOR V{}, V{}", x.get(), y.get()),
-            Self::Add(x, y)     => write!(f, "ADD V{}, V{}", x.get(), y.get()),
-            Self::Sub(x, y)     => write!(f, "SUB V{}, V{}", x.get(), y.get()),
-            Self::Shr(x, y)     => write!(f, "SHR V{}, V{}", x.get(), y.get()),
-            Self::Subb(x, y)    => write!(f, "SUBB V{}, V{}", x.get(), y.get()),
-            Self::Shl(x, y)     => write!(f, "SHL V{}, V{}", x.get(), y.get()),
-            Self::Neq(x, y)     => write!(f, "SNE V{}, V{}", x.get(), y.get()),
-            Self::SetIdx(a)     => write!(f, "MOV I, ${:03X}", a.get()),
-            Self::JumpV0(a)     => write!(f, "JUMP V0 + ${:03X}", a.get()),
-            Self::Rand(x, n)    => write!(f, "RAND V{}, {}", x.get(), n),
-            Self::Draw(x, y, n) => write!(f, "DRAW V{}, V{}, {}", x.get(), y.get(), n),
-            Self::KeyUp(x)      => write!(f, "SKD V{}", x.get()),
-            Self::KeyDown(x)    => write!(f, "SKU V{}", x.get()),
-            Self::GetDelay(x)   => write!(f, "MOV V{}, DT", x.get()),
-            Self::WaitKey(k)    => write!(f, "WAIT K{}", k.get()),
-            Self::SetDelay(x)   => write!(f, "MOV DT, V{}", x.get()),
-            Self::SetSound(x)   => write!(f, "MOV ST, V{}", x.get()),
-            Self::AddIdx(x)     => write!(f, "ADD I, V{}", x.get()),
-            Self::SetSprite(x)  => write!(f, "CHAR V{}", x.get()),
-            Self::StoreBcd(x)   => write!(f, "BCD V{}", x.get()),
-            Self::Store(x)      => write!(f, "MOV [I], ..V{}", x.get()),
-            Self::Load(x)       => write!(f, "MOV ..V{}, [I]", x.get()),
-        }
-    }
-}
+use crate::cpu::{Address, Register};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Instruction {
+    ScrollDown(u8),               // 00CN
+    ScrollUp(u8),                 // 00DN
+    Clear,                        // 00E0
+    Return,                       // 00EE
+    ScrollRight,                  // 00FB
+    ScrollLeft,                   // 00FC
+    Exit,                         // 00FD
+    Lores,                        // 00FE
+    Hires,                        // 00FF
+    Jump(Address),                // 1NNN
+    Call(Address),                // 2NNN
+    EqNum(Register, u8),          // 3XNN
+    NeqNum(Register, u8),         // 4XNN
+    Eq(Register, Register),       // 5XY0
+    SaveRange(Register, Register), // 5XY2
+    LoadRange(Register, Register), // 5XY3
+    SetNum(Register, u8),         // 6XNN
+    AddNum(Register, u8),         // 7XNN
+    Move(Register, Register),     // 8XY0
+    Or(Register, Register),       // 8XY1
+    And(Register, Register),      // 8XY2
+    Xor(Register, Register),      // 8XY3
+    Add(Register, Register),      // 8XY4
+    Sub(Register, Register),      // 8XY5
+    Shr(Register, Register),      // 8XY6
+    Subb(Register, Register),     // 8XY7
+    Shl(Register, Register),      // 8XYE
+    Neq(Register, Register),      // 9XY0
+    SetIdx(Address),              // ANNN
+    JumpV0(Address),              // BNNN
+    Rand(Register, u8),           // CXNN
+    Draw(Register, Register, u8), // DXYN
+    KeyUp(Register),              // EX9E
+    KeyDown(Register),            // EXA1
+    LongIdx(Address),             // F000 NNNN
+    Plane(u8),                    // FN01
+    Audio,                        // F002
+    GetDelay(Register),           // FX07
+    WaitKey(Register),            // FX0A
+    SetDelay(Register),           // FX15
+    SetSound(Register),           // FX18
+    AddIdx(Register),             // FX1E
+    SetSprite(Register),          // FX29
+    SetBigSprite(Register),       // FX30
+    StoreBcd(Register),           // FX33
+    Pitch(Register),              // FX3A
+    Store(Register),              // FX55
+    Load(Register),               // FX65
+    SaveFlags(Register),          // FX75
+    LoadFlags(Register),          // FX85
+}
+
+impl Instruction {
+    /// Decodes an instruction that may be followed by a second word, which
+    /// only `F000 NNNN` uses.
+    pub fn decode(data: u16, next: u16) -> Option<Self> {
+        match data {
+            0xF000 => Some(Self::LongIdx(Address::new(next))),
+            _ => Self::new(data),
+        }
+    }
+
+    /// Decodes a single word instruction.
+    pub fn new(data: u16) -> Option<Self> {
+        let addr = Address::new(data & 0x0FFF);
+        let reg_x = Register::new(((data & 0x0F00) >> 8) as u8);
+        let reg_y = Register::new(((data & 0x00F0) >> 4) as u8);
+        let num = (data & 0x00FF) as u8;
+
+        let nib1 = ((data & 0xF000) >> 12) as u8;
+        let nib2 = ((data & 0x0F00) >> 8) as u8;
+        let nib3 = ((data & 0x00F0) >> 4) as u8;
+        let nib4 = (data & 0x000F) as u8;
+
+        match (nib1, nib2, nib3, nib4) {
+            (0x0,0x0,0xC, _ ) => Some(Self::ScrollDown(nib4)),
+            (0x0,0x0,0xD, _ ) => Some(Self::ScrollUp(nib4)),
+            (0x0,0x0,0xE,0x0) => Some(Self::Clear),
+            (0x0,0x0,0xE,0xE) => Some(Self::Return),
+            (0x0,0x0,0xF,0xB) => Some(Self::ScrollRight),
+            (0x0,0x0,0xF,0xC) => Some(Self::ScrollLeft),
+            (0x0,0x0,0xF,0xD) => Some(Self::Exit),
+            (0x0,0x0,0xF,0xE) => Some(Self::Lores),
+            (0x0,0x0,0xF,0xF) => Some(Self::Hires),
+            (0x0, _ , _ , _ ) => None, // 0NNN machine code routines
+            (0x1, _ , _ , _ ) => Some(Self::Jump(addr)),
+            (0x2, _ , _ , _ ) => Some(Self::Call(addr)),
+            (0x3, _ , _ , _ ) => Some(Self::EqNum(reg_x, num)),
+            (0x4, _ , _ , _ ) => Some(Self::NeqNum(reg_x, num)),
+            (0x5, _ , _ ,0x0) => Some(Self::Eq(reg_x, reg_y)),
+            (0x5, _ , _ ,0x2) => Some(Self::SaveRange(reg_x, reg_y)),
+            (0x5, _ , _ ,0x3) => Some(Self::LoadRange(reg_x, reg_y)),
+            (0x6, _ , _ , _ ) => Some(Self::SetNum(reg_x, num)),
+            (0x7, _ , _ , _ ) => Some(Self::AddNum(reg_x, num)),
+            (0x8, _ , _ ,0x0) => Some(Self::Move(reg_x, reg_y)),
+            (0x8, _ , _ ,0x1) => Some(Self::Or(reg_x, reg_y)),
+            (0x8, _ , _ ,0x2) => Some(Self::And(reg_x, reg_y)),
+            (0x8, _ , _ ,0x3) => Some(Self::Xor(reg_x, reg_y)),
+            (0x8, _ , _ ,0x4) => Some(Self::Add(reg_x, reg_y)),
+            (0x8, _ , _ ,0x5) => Some(Self::Sub(reg_x, reg_y)),
+            (0x8, _ , _ ,0x6) => Some(Self::Shr(reg_x, reg_y)),
+            (0x8, _ , _ ,0x7) => Some(Self::Subb(reg_x, reg_y)),
+            (0x8, _ , _ ,0xE) => Some(Self::Shl(reg_x, reg_y)),
+            (0x9, _ , _ ,0x0) => Some(Self::Neq(reg_x, reg_y)),
+            (0xA, _ , _ , _ ) => Some(Self::SetIdx(addr)),
+            (0xB, _ , _ , _ ) => Some(Self::JumpV0(addr)),
+            (0xC, _ , _ , _ ) => Some(Self::Rand(reg_x, num)),
+            (0xD, _ , _ , _ ) => Some(Self::Draw(reg_x, reg_y, nib4)),
+            (0xE, _ ,0x9,0xE) => Some(Self::KeyUp(reg_x)),
+            (0xE, _ ,0xA,0x1) => Some(Self::KeyDown(reg_x)),
+            (0xF, _ ,0x0,0x1) => Some(Self::Plane(nib2)),
+            (0xF,0x0,0x0,0x2) => Some(Self::Audio),
+            (0xF, _ ,0x0,0x7) => Some(Self::GetDelay(reg_x)),
+            (0xF, _ ,0x0,0xA) => Some(Self::WaitKey(reg_x)),
+            (0xF, _ ,0x1,0x5) => Some(Self::SetDelay(reg_x)),
+            (0xF, _ ,0x1,0x8) => Some(Self::SetSound(reg_x)),
+            (0xF, _ ,0x1,0xE) => Some(Self::AddIdx(reg_x)),
+            (0xF, _ ,0x2,0x9) => Some(Self::SetSprite(reg_x)),
+            (0xF, _ ,0x3,0x0) => Some(Self::SetBigSprite(reg_x)),
+            (0xF, _ ,0x3,0x3) => Some(Self::StoreBcd(reg_x)),
+            (0xF, _ ,0x3,0xA) => Some(Self::Pitch(reg_x)),
+            (0xF, _ ,0x5,0x5) => Some(Self::Store(reg_x)),
+            (0xF, _ ,0x6,0x5) => Some(Self::Load(reg_x)),
+            (0xF, _ ,0x7,0x5) => Some(Self::SaveFlags(reg_x)),
+            (0xF, _ ,0x8,0x5) => Some(Self::LoadFlags(reg_x)),
+            _ => None
+        }
+    }
+
+    /// Encodes the instruction, the inverse of [`Instruction::new`]. For
+    /// `F000 NNNN` this is the first word, the address follows it.
+    pub fn encode(&self) -> u16 {
+        let xy = |x: &Register, y: &Register| (x.get() as u16) << 8 | (y.get() as u16) << 4;
+        let xn = |x: &Register, n: &u8| (x.get() as u16) << 8 | *n as u16;
+        let x = |x: &Register| (x.get() as u16) << 8;
+        let a = |a: &Address| a.get() as u16 & 0x0FFF;
+
+        match self {
+            Self::ScrollDown(n)   => 0x00C0 | (*n as u16 & 0xF),
+            Self::ScrollUp(n)     => 0x00D0 | (*n as u16 & 0xF),
+            Self::Clear           => 0x00E0,
+            Self::Return          => 0x00EE,
+            Self::ScrollRight     => 0x00FB,
+            Self::ScrollLeft      => 0x00FC,
+            Self::Exit            => 0x00FD,
+            Self::Lores           => 0x00FE,
+            Self::Hires           => 0x00FF,
+            Self::Jump(addr)      => 0x1000 | a(addr),
+            Self::Call(addr)      => 0x2000 | a(addr),
+            Self::EqNum(r, n)     => 0x3000 | xn(r, n),
+            Self::NeqNum(r, n)    => 0x4000 | xn(r, n),
+            Self::Eq(r, s)        => 0x5000 | xy(r, s),
+            Self::SaveRange(r, s) => 0x5002 | xy(r, s),
+            Self::LoadRange(r, s) => 0x5003 | xy(r, s),
+            Self::SetNum(r, n)    => 0x6000 | xn(r, n),
+            Self::AddNum(r, n)    => 0x7000 | xn(r, n),
+            Self::Move(r, s)      => 0x8000 | xy(r, s),
+            Self::Or(r, s)        => 0x8001 | xy(r, s),
+            Self::And(r, s)       => 0x8002 | xy(r, s),
+            Self::Xor(r, s)       => 0x8003 | xy(r, s),
+            Self::Add(r, s)       => 0x8004 | xy(r, s),
+            Self::Sub(r, s)       => 0x8005 | xy(r, s),
+            Self::Shr(r, s)       => 0x8006 | xy(r, s),
+            Self::Subb(r, s)      => 0x8007 | xy(r, s),
+            Self::Shl(r, s)       => 0x800E | xy(r, s),
+            Self::Neq(r, s)       => 0x9000 | xy(r, s),
+            Self::SetIdx(addr)    => 0xA000 | a(addr),
+            Self::JumpV0(addr)    => 0xB000 | a(addr),
+            Self::Rand(r, n)      => 0xC000 | xn(r, n),
+            Self::Draw(r, s, n)   => 0xD000 | xy(r, s) | (*n as u16 & 0xF),
+            Self::KeyUp(r)        => 0xE09E | x(r),
+            Self::KeyDown(r)      => 0xE0A1 | x(r),
+            Self::LongIdx(_)      => 0xF000,
+            Self::Plane(n)        => 0xF001 | (*n as u16 & 0xF) << 8,
+            Self::Audio           => 0xF002,
+            Self::GetDelay(r)     => 0xF007 | x(r),
+            Self::WaitKey(r)      => 0xF00A | x(r),
+            Self::SetDelay(r)     => 0xF015 | x(r),
+            Self::SetSound(r)     => 0xF018 | x(r),
+            Self::AddIdx(r)       => 0xF01E | x(r),
+            Self::SetSprite(r)    => 0xF029 | x(r),
+            Self::SetBigSprite(r) => 0xF030 | x(r),
+            Self::StoreBcd(r)     => 0xF033 | x(r),
+            Self::Pitch(r)        => 0xF03A | x(r),
+            Self::Store(r)        => 0xF055 | x(r),
+            Self::Load(r)         => 0xF065 | x(r),
+            Self::SaveFlags(r)    => 0xF075 | x(r),
+            Self::LoadFlags(r)    => 0xF085 | x(r),
+        }
+    }
+
+    /// The encoded instruction as it is stored in memory, 2 or 4 bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.encode().to_be_bytes().to_vec();
+        if let Self::LongIdx(addr) = self {
+            bytes.extend_from_slice(&(addr.get() as u16).to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Size of the instruction in bytes.
+    pub fn size(&self) -> u16 {
+        match self {
+            Self::LongIdx(_) => 4,
+            _ => 2,
+        }
+    }
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ScrollDown(n) => write!(f, "SCD {}", n),
+            Self::ScrollUp(n)   => write!(f, "SCU {}", n),
+            Self::Clear         => write!(f, "CLS"),
+            Self::Return        => write!(f, "RET"),
+            Self::ScrollRight   => write!(f, "SCR"),
+            Self::ScrollLeft    => write!(f, "SCL"),
+            Self::Exit          => write!(f, "EXIT"),
+            Self::Lores         => write!(f, "LOW"),
+            Self::Hires         => write!(f, "HIGH"),
+            Self::Jump(a)       => write!(f, "JUMP ${:03X}", a.get()),
+            Self::Call(a)       => write!(f, "CALL ${:03X}", a.get()),
+            Self::EqNum(x, n)   => write!(f, "SEQ V{}, {}", x.get(), n),
+            Self::NeqNum(x, n)  => write!(f, "SNE V{}, {}", x.get(), n),
+            Self::Eq(x, y)      => write!(f, "SEQ V{}, V{}", x.get(), y.get()),
+            Self::SaveRange(x, y) => write!(f, "MOV [I], V{}..V{}", x.get(), y.get()),
+            Self::LoadRange(x, y) => write!(f, "MOV V{}..V{}, [I]", x.get(), y.get()),
+            Self::SetNum(x, n)  => write!(f, "MOV V{}, {}", x.get(), n),
+            Self::AddNum(x, n)  => write!(f, "ADD V{}, {}", x.get(), n),
+            Self::Move(x, y)    => write!(f, "MOV V{}, V{}", x.get(), y.get()),
+            Self::Or(x, y)      => write!(f, "OR V{}, V{}", x.get(), y.get()),
+            Self::And(x, y)     => write!(f, "AND V{}, V{}", x.get(), y.get()),
+            Self::Xor(x, y)     => write!(f, "XOR V{}, V{}", x.get(), y.get()),
+            Self::Add(x, y)     => write!(f, "ADD V{}, V{}", x.get(), y.get()),
+            Self::Sub(x, y)     => write!(f, "SUB V{}, V{}", x.get(), y.get()),
+            Self::Shr(x, y)     => write!(f, "SHR V{}, V{}", x.get(), y.get()),
+            Self::Subb(x, y)    => write!(f, "SUBB V{}, V{}", x.get(), y.get()),
+            Self::Shl(x, y)     => write!(f, "SHL V{}, V{}", x.get(), y.get()),
+            Self::Neq(x, y)     => write!(f, "SNE V{}, V{}", x.get(), y.get()),
+            Self::SetIdx(a)     => write!(f, "MOV I, ${:03X}", a.get()),
+            Self::JumpV0(a)     => write!(f, "JUMP V0 + ${:03X}", a.get()),
+            Self::Rand(x, n)    => write!(f, "RAND V{}, {}", x.get(), n),
+            Self::Draw(x, y, n) => write!(f, "DRAW V{}, V{}, {}", x.get(), y.get(), n),
+            Self::KeyUp(x)      => write!(f, "SKD V{}", x.get()),
+            Self::KeyDown(x)    => write!(f, "SKU V{}", x.get()),
+            Self::LongIdx(a)    => write!(f, "MOV I, LONG ${:04X}", a.get()),
+            Self::Plane(n)      => write!(f, "PLANE {}", n),
+            Self::Audio         => write!(f, "AUDIO"),
+            Self::GetDelay(x)   => write!(f, "MOV V{}, DT", x.get()),
+            Self::WaitKey(k)    => write!(f, "WAIT K{}", k.get()),
+            Self::SetDelay(x)   => write!(f, "MOV DT, V{}", x.get()),
+            Self::SetSound(x)   => write!(f, "MOV ST, V{}", x.get()),
+            Self::AddIdx(x)     => write!(f, "ADD I, V{}", x.get()),
+            Self::SetSprite(x)  => write!(f, "CHAR V{}", x.get()),
+            Self::SetBigSprite(x) => write!(f, "HCHAR V{}", x.get()),
+            Self::StoreBcd(x)   => write!(f, "BCD V{}", x.get()),
+            Self::Pitch(x)      => write!(f, "PITCH V{}", x.get()),
+            Self::Store(x)      => write!(f, "MOV [I], ..V{}", x.get()),
+            Self::Load(x)       => write!(f, "MOV ..V{}, [I]", x.get()),
+            Self::SaveFlags(x)  => write!(f, "MOV R, ..V{}", x.get()),
+            Self::LoadFlags(x)  => write!(f, "MOV ..V{}, R", x.get()),
+        }
+    }
+}
@@ -1,46 +1,79 @@
-use crate::cpu::Address;
-
-#[derive(Debug)]
-pub struct Memory {
-    data: [u8; 4096]
-}
-
-impl Memory {
-    pub fn new() -> Self {
-        Memory { data: [0; 4096] }
-    }
-
-    pub fn write(&mut self, addr: Address, data: &[u8]) {
-        self.data[addr.get()..=addr.add(data.len() as u16 - 1).get()].copy_from_slice(data);
-    }
-
-    #[allow(dead_code)]
-    pub fn write8(&mut self, addr: Address, value: u8) {
-        self.data[addr.get()] = value;
-    }
-
-    #[allow(dead_code)]
-    pub fn write16(&mut self, addr: Address, value: u16) {
-        self.write8(addr, (value >> 8) as u8);
-        self.write8(addr.add(1), value as u8);
-    }
-
-    pub fn read(&self, addr: Address, len: u16) -> &[u8] {
-        &self.data[addr.get()..=addr.add(len-1).get()]
-    }
-
-    pub fn read8(&self, addr: Address) -> u8 {
-        self.data[addr.get()]
-    }
-
-    pub fn read16(&self, addr: Address) -> u16 {
-        ((self.read8(addr) as u16) << 8) |
-        (self.read8(addr.add(1)) as u16)
-    }
-}
-
-impl Default for Memory {
-    fn default() -> Self {
-        Self::new()
-    }
-}
+use crate::cpu::Address;
+use crate::state::{Reader, StateError, Writer};
+
+#[derive(Debug)]
+pub struct Memory {
+    data: Box<[u8; Memory::SIZE]>,
+    // Bytes that can be addressed, the rest of `data` is unused.
+    size: usize,
+}
+
+impl Memory {
+    /// XO-CHIP extends the original 4 KiB to the full 16 bit address space.
+    pub const SIZE: usize = 0x10000;
+    /// The original 4 KiB of every interpreter before XO-CHIP.
+    pub const CLASSIC_SIZE: usize = 0x1000;
+
+    /// `size` must be a power of two, at most [`Memory::SIZE`].
+    pub fn new(size: usize) -> Self {
+        assert!(size.is_power_of_two() && size <= Self::SIZE);
+        Memory { data: Box::new([0; Self::SIZE]), size }
+    }
+
+    pub fn size(&self) -> usize { self.size }
+
+    pub(crate) fn set_size(&mut self, size: usize) {
+        assert!(size.is_power_of_two() && size <= Self::SIZE);
+        self.size = size;
+    }
+
+    /// Returns `None`, leaving memory untouched, if `data` runs past the end.
+    pub fn write(&mut self, addr: Address, data: &[u8]) -> Option<()> {
+        self.data[..self.size].get_mut(addr.get()..addr.get() + data.len())?.copy_from_slice(data);
+        Some(())
+    }
+
+    #[allow(dead_code)]
+    pub fn write8(&mut self, addr: Address, value: u8) {
+        self.data[addr.get() % self.size] = value;
+    }
+
+    #[allow(dead_code)]
+    pub fn write16(&mut self, addr: Address, value: u16) {
+        self.write8(addr, (value >> 8) as u8);
+        self.write8(addr.add(1), value as u8);
+    }
+
+    /// Returns `None` if the range runs past the end.
+    pub fn read(&self, addr: Address, len: u16) -> Option<&[u8]> {
+        self.data[..self.size].get(addr.get()..addr.get() + len as usize)
+    }
+
+    pub(crate) fn save_state(&self, w: &mut Writer) {
+        w.bytes(&self.data[..]);
+    }
+
+    /// Loads all 64 KiB, which are saved whatever the size. The size is
+    /// restored with [`Memory::set_size`].
+    pub(crate) fn load_state(r: &mut Reader) -> Result<Self, StateError> {
+        let mut memory = Memory::new(Self::SIZE);
+        memory.data.copy_from_slice(r.bytes(Self::SIZE)?);
+        Ok(memory)
+    }
+
+    /// Wraps around at the end of memory.
+    pub fn read8(&self, addr: Address) -> u8 {
+        self.data[addr.get() % self.size]
+    }
+
+    pub fn read16(&self, addr: Address) -> u16 {
+        ((self.read8(addr) as u16) << 8) |
+        (self.read8(addr.add(1)) as u16)
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new(Self::SIZE)
+    }
+}
@@ -1,10 +1,10 @@
-mod address;
-mod instruction;
-mod memory;
-mod register;
-
-pub use address::Address;
-pub use register::Register;
-pub use memory::Memory;
-pub use instruction::Instruction;
-
+mod address;
+mod instruction;
+mod memory;
+mod register;
+
+pub use address::Address;
+pub use register::Register;
+pub use memory::Memory;
+pub use instruction::Instruction;
+
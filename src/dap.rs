@@ -0,0 +1,588 @@
+//! A Debug Adapter Protocol server, so editors like VS Code can debug roms.
+//!
+//! The `launch` request takes a `program` (a .ch8 image, .8o Octo source or
+//! .asm source), and optionally `stopOnEntry`, `quirks`, `timing`, `ips` and
+//! `seed`. Source breakpoints work in Octo and assembler sources through their
+//! [`SourceMap`], and instruction breakpoints work anywhere. The variables
+//! view shows the registers and timers, and I and PC link to the memory view.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::debugger::{Debugger, PauseReason};
+use crate::json::Json;
+use crate::movie::FRAME_DT;
+use crate::quirks::Quirks;
+use crate::timing::Timing;
+use crate::source_map::SourceMap;
+use crate::system::System;
+use crate::{asm, octo};
+
+// There is only ever the one cpu.
+const THREAD_ID: i64 = 1;
+const REGISTERS: i64 = 1;
+const TIMERS: i64 = 2;
+
+/// Serves one client, reading requests from `input` and writing responses
+/// and events to `output`, until it disconnects or `input` ends.
+pub fn serve(input: impl Read + Send + 'static, output: impl Write) -> io::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || read_messages(input, sender));
+
+    let mut session = Session {
+        output,
+        seq: 0,
+        program: None,
+        running: false,
+        next_frame: Instant::now(),
+    };
+    session.run(receiver)
+}
+
+/// Sends every message from `input` until it ends or has an error.
+fn read_messages(input: impl Read, sender: Sender<Json>) {
+    let mut reader = BufReader::new(input);
+    loop {
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+            let line = line.trim_end();
+            if line.is_empty() { break; }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("Content-Length") {
+                    length = value.trim().parse::<usize>().ok();
+                }
+            }
+        }
+
+        let Some(length) = length else { continue };
+        let mut body = vec![0; length];
+        if reader.read_exact(&mut body).is_err() { return; }
+        let message = String::from_utf8(body).ok().and_then(|text| Json::parse(&text).ok());
+        if let Some(message) = message {
+            if sender.send(message).is_err() { return; }
+        }
+    }
+}
+
+struct Program {
+    system: System,
+    debugger: Debugger,
+    map: SourceMap,
+    stop_on_entry: bool,
+    /// Addresses of the breakpoints set in each source file.
+    source_breakpoints: HashMap<String, Vec<u16>>,
+    instruction_breakpoints: Vec<u16>,
+}
+
+impl Program {
+    fn launch(args: &Json) -> Result<Self, String> {
+        let path = args.get("program").and_then(Json::as_str).ok_or("launch needs a 'program'")?;
+        let quirks = match args.get("quirks").and_then(Json::as_str) {
+            Some(name) => Quirks::from_name(name).ok_or_else(|| format!("unknown quirks '{}'", name))?,
+            None => Quirks::default(),
+        };
+        let timing = match args.get("timing").and_then(Json::as_str) {
+            Some(name) => Timing::from_name(name).ok_or_else(|| format!("unknown timing '{}'", name))?,
+            None => Timing::default(),
+        };
+
+        let extension = Path::new(path).extension().and_then(|extension| extension.to_str()).unwrap_or("");
+        let (rom, map) = match extension.to_ascii_lowercase().as_str() {
+            "8o" => {
+                let source = fs::read_to_string(path).map_err(|err| format!("could not read {}: {}", path, err))?;
+                octo::compile_with_map(&source, path).map_err(|err| format!("{}:{}", path, err))?
+            }
+            "asm" => asm::assemble_file_with_map(path).map_err(|err| err.to_string())?,
+            _ => (fs::read(path).map_err(|err| format!("could not read {}: {}", path, err))?, SourceMap::new()),
+        };
+
+        let mut system = System::with_quirks(&rom, quirks).map_err(|err| err.to_string())?;
+        system.set_timing(timing);
+        if let Some(ips) = args.get("ips").and_then(Json::as_i64) {
+            system.set_ips(ips.clamp(1, u32::MAX as i64) as u32);
+        }
+        if let Some(seed) = args.get("seed").and_then(Json::as_i64) {
+            system.set_seed(seed as u64);
+        }
+
+        let mut debugger = Debugger::new();
+        debugger.pause();
+        Ok(Program {
+            system,
+            debugger,
+            map,
+            stop_on_entry: args.get("stopOnEntry").and_then(Json::as_bool).unwrap_or(false),
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: Vec::new(),
+        })
+    }
+
+    /// The name `path` has in the source map, if it is one of its files.
+    fn map_file(&self, path: &str) -> Option<String> {
+        let mut files: Vec<&str> = self.map.spans().iter().map(|span| span.file.as_str()).collect();
+        files.dedup();
+        files.into_iter().find(|file| same_file(file, path)).map(str::to_string)
+    }
+
+    /// Makes the debugger's breakpoints those of every source plus the
+    /// instruction breakpoints.
+    fn sync_breakpoints(&mut self) {
+        for addr in self.debugger.breakpoints().clone() {
+            self.debugger.remove_breakpoint(addr);
+        }
+        for &addr in self.source_breakpoints.values().flatten().chain(&self.instruction_breakpoints) {
+            self.debugger.add_breakpoint(addr);
+        }
+    }
+
+    fn source(&self, addr: u16) -> Option<(Json, usize)> {
+        let (file, line) = self.map.location(addr)?;
+        let name = Path::new(file).file_name().map_or(file.to_string(), |name| name.to_string_lossy().into_owned());
+        Some((Json::object([("name", name.into()), ("path", file.into())]), line))
+    }
+}
+
+struct Session<W> {
+    output: W,
+    seq: i64,
+    program: Option<Program>,
+    running: bool,
+    next_frame: Instant,
+}
+
+impl<W: Write> Session<W> {
+    fn run(&mut self, receiver: Receiver<Json>) -> io::Result<()> {
+        loop {
+            let message = if self.running {
+                match receiver.recv_timeout(self.next_frame.saturating_duration_since(Instant::now())) {
+                    Ok(message) => Some(message),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match receiver.recv() {
+                    Ok(message) => Some(message),
+                    Err(_) => return Ok(()),
+                }
+            };
+
+            match message {
+                Some(message) => if !self.handle(&message)? { return Ok(()); },
+                None => {
+                    self.next_frame += Duration::from_secs_f64(FRAME_DT);
+                    self.run_frame()?;
+                }
+            }
+        }
+    }
+
+    /// Answers one request. Returns false once the client disconnects.
+    fn handle(&mut self, request: &Json) -> io::Result<bool> {
+        if request.get("type").and_then(Json::as_str) != Some("request") { return Ok(true); }
+        let command = request.get("command").and_then(Json::as_str).unwrap_or("");
+        let args = request.get("arguments").cloned().unwrap_or(Json::Object(Vec::new()));
+
+        let result = match command {
+            "initialize" => Ok(capabilities()),
+            "launch" => Program::launch(&args).map(|program| {
+                self.program = Some(program);
+                Json::Null
+            }),
+            "disconnect" => {
+                self.respond(request, Ok(Json::Null))?;
+                return Ok(false);
+            }
+            "terminate" => {
+                self.running = false;
+                self.respond(request, Ok(Json::Null))?;
+                self.event("terminated", Json::Null)?;
+                return Ok(true);
+            }
+            "threads" => Ok(Json::object([("threads", vec![
+                Json::object([("id", THREAD_ID.into()), ("name", "CHIP-8".into())]),
+            ].into())])),
+            _ => self.program_request(command, &args),
+        };
+        let launched = command == "launch" && result.is_ok();
+        self.respond(request, result)?;
+
+        match command {
+            "launch" if launched => self.event("initialized", Json::Null)?,
+            "configurationDone" => match &self.program {
+                Some(program) if program.stop_on_entry => self.stopped("entry", None)?,
+                Some(_) => self.start(),
+                None => {}
+            },
+            "continue" => self.start(),
+            "next" | "stepIn" | "stepOut" => self.after_step()?,
+            "pause" => self.stopped("pause", None)?,
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    /// Requests that need a launched program.
+    fn program_request(&mut self, command: &str, args: &Json) -> Result<Json, String> {
+        let program = self.program.as_mut().ok_or("no program has been launched")?;
+
+        match command {
+            "configurationDone" => {
+                if !program.stop_on_entry {
+                    program.debugger.resume(&program.system);
+                }
+                Ok(Json::Null)
+            }
+            "setBreakpoints" => {
+                let path = args.get("source").and_then(|source| source.get("path")).and_then(Json::as_str)
+                    .ok_or("setBreakpoints needs a source path")?;
+                let lines = args.get("breakpoints").and_then(Json::as_array).unwrap_or(&[]);
+                let file = program.map_file(path);
+
+                let mut addrs = Vec::new();
+                let breakpoints = lines.iter().map(|breakpoint| {
+                    let line = breakpoint.get("line").and_then(Json::as_i64).unwrap_or(0).max(0) as usize;
+                    match file.as_deref().and_then(|file| program.map.address(file, line)) {
+                        Some((addr, line)) => {
+                            addrs.push(addr);
+                            Json::object([
+                                ("verified", true.into()),
+                                ("line", line.into()),
+                                ("instructionReference", format!("0x{:03X}", addr).into()),
+                            ])
+                        }
+                        None => Json::object([
+                            ("verified", false.into()),
+                            ("line", line.into()),
+                            ("message", "no code at or after this line".into()),
+                        ]),
+                    }
+                }).collect::<Vec<_>>();
+
+                program.source_breakpoints.insert(path.to_string(), addrs);
+                program.sync_breakpoints();
+                Ok(Json::object([("breakpoints", breakpoints.into())]))
+            }
+            "setInstructionBreakpoints" => {
+                let requested = args.get("breakpoints").and_then(Json::as_array).unwrap_or(&[]);
+                let addrs = requested.iter().map(|breakpoint| {
+                    let reference = breakpoint.get("instructionReference").and_then(Json::as_str).and_then(parse_address);
+                    let offset = breakpoint.get("offset").and_then(Json::as_i64).unwrap_or(0);
+                    reference.map(|addr| (addr as i64 + offset) as u16)
+                }).collect::<Vec<_>>();
+
+                program.instruction_breakpoints = addrs.iter().flatten().copied().collect();
+                program.sync_breakpoints();
+                Ok(Json::object([("breakpoints", addrs.iter().map(|addr| match addr {
+                    Some(addr) => Json::object([
+                        ("verified", true.into()),
+                        ("instructionReference", format!("0x{:03X}", addr).into()),
+                    ]),
+                    None => Json::object([("verified", false.into()), ("message", "invalid address".into())]),
+                }).collect::<Vec<_>>().into())]))
+            }
+            "stackTrace" => {
+                // The PC, then the CALLs on the stack, innermost first.
+                let system = &program.system;
+                let calls = system.get_stack().into_iter().rev();
+                let frames = std::iter::once(system.get_pc()).chain(calls).enumerate().map(|(id, addr)| {
+                    let mut frame = vec![
+                        ("id", id.into()),
+                        ("name", match Debugger::instruction_at(system, addr) {
+                            Some(instruction) => format!("${:03X} {}", addr, instruction),
+                            None => format!("${:03X}", addr),
+                        }.into()),
+                        ("instructionPointerReference", format!("0x{:03X}", addr).into()),
+                        ("column", 1usize.into()),
+                    ];
+                    match program.source(addr) {
+                        Some((source, line)) => frame.extend([("source", source), ("line", line.into())]),
+                        None => frame.push(("line", 0usize.into())),
+                    }
+                    Json::object(frame)
+                }).collect::<Vec<_>>();
+
+                Ok(Json::object([("totalFrames", frames.len().into()), ("stackFrames", frames.into())]))
+            }
+            "scopes" => Ok(Json::object([("scopes", vec![
+                Json::object([("name", "Registers".into()), ("variablesReference", REGISTERS.into()), ("expensive", false.into())]),
+                Json::object([("name", "Timers".into()), ("variablesReference", TIMERS.into()), ("expensive", false.into())]),
+            ].into())])),
+            "variables" => {
+                let system = &program.system;
+                let variable = |name: String, value: String, memory: Option<u16>| {
+                    let mut fields = vec![("name", name.into()), ("value", value.into()), ("variablesReference", 0i64.into())];
+                    if let Some(addr) = memory {
+                        fields.push(("memoryReference", format!("0x{:03X}", addr).into()));
+                    }
+                    Json::object(fields)
+                };
+
+                let variables = match args.get("variablesReference").and_then(Json::as_i64) {
+                    Some(REGISTERS) => {
+                        let mut variables: Vec<Json> = system.get_registers().iter().enumerate()
+                            .map(|(x, value)| variable(format!("V{:X}", x), format!("0x{:02X}", value), None))
+                            .collect();
+                        variables.push(variable("I".to_string(), format!("0x{:03X}", system.get_i()), Some(system.get_i())));
+                        variables.push(variable("PC".to_string(), format!("0x{:03X}", system.get_pc()), Some(system.get_pc())));
+                        variables
+                    }
+                    Some(TIMERS) => vec![
+                        variable("DT".to_string(), system.get_delay_timer().to_string(), None),
+                        variable("ST".to_string(), system.get_sound_timer().to_string(), None),
+                    ],
+                    _ => Vec::new(),
+                };
+                Ok(Json::object([("variables", variables.into())]))
+            }
+            "setVariable" => {
+                let name = args.get("name").and_then(Json::as_str).ok_or("setVariable needs a name")?;
+                let text = args.get("value").and_then(Json::as_str).ok_or("setVariable needs a value")?;
+                let value = parse_value(text).ok_or_else(|| format!("invalid value '{}'", text))?;
+                let byte = || u8::try_from(value).map_err(|_| format!("{} doesn't fit in {}", text, name));
+                let unknown = || format!("unknown variable '{}'", name);
+
+                let system = &mut program.system;
+                let value = match (args.get("variablesReference").and_then(Json::as_i64), name) {
+                    (Some(REGISTERS), "I") => { system.set_i(value); format!("0x{:03X}", value) }
+                    (Some(REGISTERS), "PC") => { system.set_pc(value); format!("0x{:03X}", value) }
+                    (Some(REGISTERS), _) => {
+                        let x = name.strip_prefix('V').and_then(|x| u8::from_str_radix(x, 16).ok()).ok_or_else(unknown)?;
+                        system.set_register(x, byte()?).ok_or_else(unknown)?;
+                        format!("0x{:02X}", value)
+                    }
+                    (Some(TIMERS), "DT") => { system.set_delay_timer(byte()?); value.to_string() }
+                    (Some(TIMERS), "ST") => { system.set_sound_timer(byte()?); value.to_string() }
+                    _ => return Err(unknown()),
+                };
+                Ok(Json::object([("value", value.into())]))
+            }
+            "readMemory" => {
+                let reference = args.get("memoryReference").and_then(Json::as_str).and_then(parse_address)
+                    .ok_or("invalid memoryReference")?;
+                let start = reference as i64 + args.get("offset").and_then(Json::as_i64).unwrap_or(0);
+                let count = args.get("count").and_then(Json::as_i64).unwrap_or(0).max(0);
+
+                // Only the part inside memory can be read, and a read's length
+                // is a u16, so all 64 KiB takes two.
+                let size = program.system.memory_size() as i64;
+                let first = start.clamp(0, size);
+                let last = (start + count).clamp(0, size);
+                let middle = first + (last - first) / 2;
+                let data: Vec<u8> = [(first, middle), (middle, last)].into_iter()
+                    .flat_map(|(from, to)| program.system.read_memory(from as u16, (to - from) as u16).unwrap_or(&[]))
+                    .copied()
+                    .collect();
+                Ok(Json::object([
+                    ("address", format!("0x{:03X}", first).into()),
+                    ("data", base64(&data).into()),
+                    ("unreadableBytes", ((count - data.len() as i64) as usize).into()),
+                ]))
+            }
+            "disassemble" => {
+                let reference = args.get("memoryReference").and_then(Json::as_str).and_then(parse_address)
+                    .ok_or("invalid memoryReference")?;
+                let offset = args.get("offset").and_then(Json::as_i64).unwrap_or(0);
+                let instruction_offset = args.get("instructionOffset").and_then(Json::as_i64).unwrap_or(0);
+                let count = args.get("instructionCount").and_then(Json::as_i64).unwrap_or(0).max(0);
+
+                // Instructions before the reference are taken to be two bytes.
+                let mut addr = reference as i64 + offset + instruction_offset * 2;
+                let mut instructions = Vec::new();
+                for _ in 0..count {
+                    instructions.push(match u16::try_from(addr).ok() {
+                        Some(at) => {
+                            let instruction = Debugger::instruction_at(&program.system, at);
+                            let size = instruction.map_or(2, |instruction| instruction.size());
+                            let bytes = program.system.read_memory(at, size).unwrap_or(&[]);
+                            let mut fields = vec![
+                                ("address", format!("0x{:03X}", at).into()),
+                                ("instructionBytes", bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(" ").into()),
+                                ("instruction", instruction.map_or("??".to_string(), |instruction| instruction.to_string()).into()),
+                            ];
+                            if let Some((source, line)) = program.source(at) {
+                                fields.extend([("location", source), ("line", line.into())]);
+                            }
+                            addr += size as i64;
+                            Json::object(fields)
+                        }
+                        None => {
+                            addr += 2;
+                            Json::object([("address", format!("0x{:X}", addr - 2).into()), ("instruction", "??".into())])
+                        }
+                    });
+                }
+                Ok(Json::object([("instructions", instructions.into())]))
+            }
+            "continue" => {
+                program.debugger.resume(&program.system);
+                Ok(Json::object([("allThreadsContinued", true.into())]))
+            }
+            "next" => program.debugger.step_over(&mut program.system).map(|_| Json::Null).or_else(fault_is_a_stop),
+            "stepIn" => program.debugger.step(&mut program.system).map(|_| Json::Null).or_else(fault_is_a_stop),
+            "stepOut" => program.debugger.step_out(&mut program.system).map(|_| Json::Null).or_else(fault_is_a_stop),
+            "pause" => {
+                program.debugger.pause();
+                self.running = false;
+                Ok(Json::Null)
+            }
+            _ => Err(format!("unsupported request '{}'", command)),
+        }
+    }
+
+    fn start(&mut self) {
+        self.running = true;
+        self.next_frame = Instant::now();
+    }
+
+    /// Reports a finished step, or keeps running a step over or out.
+    fn after_step(&mut self) -> io::Result<()> {
+        let Some(program) = &self.program else { return Ok(()) };
+        match program.system.fault() {
+            Some(fault) => {
+                let description = fault.to_string();
+                self.stopped("exception", Some(description))
+            }
+            None if program.debugger.is_paused() => self.stopped("step", None),
+            None => {
+                self.start();
+                Ok(())
+            }
+        }
+    }
+
+    fn run_frame(&mut self) -> io::Result<()> {
+        let Some(program) = &mut self.program else {
+            self.running = false;
+            return Ok(());
+        };
+
+        match program.debugger.update(&mut program.system, FRAME_DT) {
+            Err(err) => self.stopped("exception", Some(err.to_string())),
+            Ok(true) => {
+                let reason = match program.debugger.pause_reason() {
+                    Some(PauseReason::Breakpoint(_)) => "breakpoint",
+                    Some(PauseReason::Watchpoint(..)) => "data breakpoint",
+                    _ => "step",
+                };
+                self.stopped(reason, None)
+            }
+            Ok(false) if program.system.is_halted() => {
+                self.running = false;
+                self.event("exited", Json::object([("exitCode", 0i64.into())]))?;
+                self.event("terminated", Json::Null)
+            }
+            Ok(false) => Ok(()),
+        }
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) -> io::Result<()> {
+        self.running = false;
+        if let Some(program) = &mut self.program {
+            program.debugger.pause();
+        }
+
+        let mut body = vec![
+            ("reason", reason.into()),
+            ("threadId", THREAD_ID.into()),
+            ("allThreadsStopped", true.into()),
+        ];
+        if let Some(text) = text {
+            body.push(("text", text.into()));
+        }
+        self.event("stopped", Json::object(body))
+    }
+
+    fn respond(&mut self, request: &Json, result: Result<Json, String>) -> io::Result<()> {
+        let mut fields = vec![
+            ("type", "response".into()),
+            ("request_seq", request.get("seq").cloned().unwrap_or(Json::Null)),
+            ("command", request.get("command").cloned().unwrap_or(Json::Null)),
+            ("success", result.is_ok().into()),
+        ];
+        match result {
+            Ok(Json::Null) => {}
+            Ok(body) => fields.push(("body", body)),
+            Err(message) => fields.push(("message", message.into())),
+        }
+        self.send(fields)
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        let mut fields = vec![("type", "event".into()), ("event", event.into())];
+        if body != Json::Null {
+            fields.push(("body", body));
+        }
+        self.send(fields)
+    }
+
+    fn send(&mut self, mut fields: Vec<(&str, Json)>) -> io::Result<()> {
+        self.seq += 1;
+        fields.insert(0, ("seq", self.seq.into()));
+        let text = Json::object(fields).to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", text.len(), text)?;
+        self.output.flush()
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object([
+        ("supportsConfigurationDoneRequest", true.into()),
+        ("supportsTerminateRequest", true.into()),
+        ("supportsReadMemoryRequest", true.into()),
+        ("supportsDisassembleRequest", true.into()),
+        ("supportsInstructionBreakpoints", true.into()),
+        ("supportsSetVariable", true.into()),
+    ])
+}
+
+/// A step that faults is still answered, the fault is reported as a stop.
+fn fault_is_a_stop(_: crate::error::EmulatorError) -> Result<Json, String> {
+    Ok(Json::Null)
+}
+
+/// Parses `0x42` or `$42` as hex, and `66` as decimal.
+fn parse_value(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).or_else(|| text.strip_prefix('$')) {
+        Some(digits) => u16::from_str_radix(digits, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Parses `0x206`, `$206` or `206` as hex.
+fn parse_address(text: &str) -> Option<u16> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X").trim_start_matches('$');
+    u16::from_str_radix(digits, 16).ok()
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            text.push(match i <= chunk.len() {
+                true => ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char,
+                false => '=',
+            });
+        }
+    }
+    text
+}
@@ -0,0 +1,315 @@
+//! Pausing, single stepping, PC breakpoints and watchpoints, independent of
+//! any frontend.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::cpu::Instruction;
+use crate::error::EmulatorError;
+use crate::system::{Hooks, System};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Running,
+    Paused,
+    /// Running until the `CALL` being stepped over returns to `pc`.
+    StepOver { pc: u16, depth: usize },
+    /// Running until the stack is shallower than `depth`.
+    StepOut { depth: usize },
+}
+
+/// Something to pause on after the instruction that causes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watchpoint {
+    /// An instruction reading any of the `len` bytes from `addr` as data.
+    Read { addr: u16, len: u16 },
+    /// An instruction writing any of the `len` bytes from `addr`.
+    Write { addr: u16, len: u16 },
+    /// Vx changing, or with a value, changing to it.
+    Register { x: u8, value: Option<u8> },
+    /// I changing, or with a value, changing to it.
+    Index { value: Option<u16> },
+}
+
+impl Watchpoint {
+    fn overlaps(addr: u16, len: u16, access_addr: u16, access_len: u16) -> bool {
+        let (start, end) = (addr as u32, addr as u32 + len as u32);
+        let (access_start, access_end) = (access_addr as u32, access_addr as u32 + access_len as u32);
+        access_start < end && start < access_end
+    }
+}
+
+/// Parses `read:ADDR[+LEN]`, `write:ADDR[+LEN]`, `vX[=VALUE]` or
+/// `i[=VALUE]`, with every number in hex.
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let hex = |digits: &str| u16::from_str_radix(digits.trim_start_matches('$'), 16)
+            .map_err(|_| format!("invalid hex number '{}'", digits));
+        let range = |range: &str| -> Result<(u16, u16), String> {
+            match range.split_once('+') {
+                Some((addr, len)) => Ok((hex(addr)?, hex(len)?.max(1))),
+                None => Ok((hex(range)?, 1)),
+            }
+        };
+
+        let lower = text.to_ascii_lowercase();
+        let (target, value) = match lower.split_once('=') {
+            Some((target, value)) => (target, Some(hex(value)?)),
+            None => (lower.as_str(), None),
+        };
+
+        if let Some(range_text) = target.strip_prefix("read:") {
+            let (addr, len) = range(range_text)?;
+            return Ok(Watchpoint::Read { addr, len });
+        }
+        if let Some(range_text) = target.strip_prefix("write:") {
+            let (addr, len) = range(range_text)?;
+            return Ok(Watchpoint::Write { addr, len });
+        }
+        if target == "i" {
+            return Ok(Watchpoint::Index { value });
+        }
+        if let Some(x) = target.strip_prefix('v').and_then(|x| u8::from_str_radix(x, 16).ok()).filter(|&x| x < 16) {
+            let value = value.map(|value| u8::try_from(value).map_err(|_| "register values are one byte".to_string()))
+                .transpose()?;
+            return Ok(Watchpoint::Register { x, value });
+        }
+        Err("expected read:ADDR, write:ADDR, vX or i".to_string())
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read { addr, len: 1 } => write!(f, "read ${:03X}", addr),
+            Self::Read { addr, len } => write!(f, "read ${:03X}+{:X}", addr, len),
+            Self::Write { addr, len: 1 } => write!(f, "write ${:03X}", addr),
+            Self::Write { addr, len } => write!(f, "write ${:03X}+{:X}", addr, len),
+            Self::Register { x, value: None } => write!(f, "V{:X} changed", x),
+            Self::Register { x, value: Some(value) } => write!(f, "V{:X} = {:02X}", x, value),
+            Self::Index { value: None } => write!(f, "I changed"),
+            Self::Index { value: Some(value) } => write!(f, "I = ${:03X}", value),
+        }
+    }
+}
+
+/// Why the debugger paused on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    Breakpoint(u16),
+    /// The watchpoint, and the address of the instruction that set it off.
+    Watchpoint(Watchpoint, u16),
+    /// A step over or out finished.
+    Step,
+}
+
+impl fmt::Display for PauseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Breakpoint(pc) => write!(f, "breakpoint at ${:03X}", pc),
+            Self::Watchpoint(watchpoint, pc) => write!(f, "{} by ${:03X}", watchpoint, pc),
+            Self::Step => write!(f, "step finished"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Debugger {
+    mode: Mode,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    reason: Option<PauseReason>,
+    // The PC at the last check, so a breakpoint only stops the cpu when it
+    // arrives there and not again when resuming from it.
+    last_pc: Option<u16>,
+    // V and I at the last check, to see what the last instruction changed.
+    last_registers: Option<([u8; 16], u16)>,
+    // A memory watchpoint hit by the instruction running now.
+    hit: Option<Watchpoint>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            mode: Mode::Running,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            reason: None,
+            last_pc: None,
+            last_registers: None,
+            hit: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool { self.mode == Mode::Paused }
+
+    /// Why the debugger last paused by itself, or `None` if it was paused by
+    /// hand or is running.
+    pub fn pause_reason(&self) -> Option<PauseReason> { self.reason }
+
+    pub fn pause(&mut self) {
+        self.mode = Mode::Paused;
+        self.reason = None;
+    }
+
+    pub fn resume(&mut self, system: &System) {
+        self.start(Mode::Running, system);
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<u16> { &self.breakpoints }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    /// Returns whether there is now a breakpoint at `addr`.
+    pub fn toggle_breakpoint(&mut self, addr: u16) -> bool {
+        if !self.breakpoints.remove(&addr) {
+            self.breakpoints.insert(addr);
+        }
+        self.breakpoints.contains(&addr)
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] { &self.watchpoints }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.retain(|&other| other != watchpoint);
+    }
+
+    /// Executes one instruction and pauses. A cpu waiting for the display is
+    /// let through to the next timer tick first.
+    pub fn step(&mut self, system: &mut System) -> Result<(), EmulatorError> {
+        self.pause();
+        if system.is_waiting_for_display() {
+            system.tick_timers();
+        }
+        system.step()
+    }
+
+    /// Runs a `CALL` until it returns, or steps any other instruction.
+    pub fn step_over(&mut self, system: &mut System) -> Result<(), EmulatorError> {
+        let pc = system.get_pc();
+        match Self::instruction_at(system, pc) {
+            Some(call @ Instruction::Call(_)) => {
+                let mode = Mode::StepOver { pc: pc.wrapping_add(call.size()), depth: system.stack_depth() };
+                self.start(mode, system);
+                Ok(())
+            }
+            _ => self.step(system),
+        }
+    }
+
+    /// Runs until the current subroutine returns, or steps outside of one.
+    pub fn step_out(&mut self, system: &mut System) -> Result<(), EmulatorError> {
+        match system.stack_depth() {
+            0 => self.step(system),
+            depth => {
+                self.start(Mode::StepOut { depth }, system);
+                Ok(())
+            }
+        }
+    }
+
+    fn start(&mut self, mode: Mode, system: &System) {
+        self.mode = mode;
+        self.reason = None;
+        self.last_pc = Some(system.get_pc());
+        self.last_registers = Some((*system.get_registers(), system.get_i()));
+        self.hit = None;
+    }
+
+    /// Runs the system for `dt` unless paused, pausing at a breakpoint, a
+    /// watchpoint, or when a step over or out finishes. Returns whether it
+    /// paused.
+    pub fn update(&mut self, system: &mut System, dt: f64) -> Result<bool, EmulatorError> {
+        if self.mode == Mode::Paused { return Ok(false); }
+
+        let stopped = system.update_with(dt, self)?;
+        if stopped { self.mode = Mode::Paused; }
+        Ok(stopped)
+    }
+
+    /// Like [`Debugger::update`], running a frame of `instructions` with
+    /// [`System::run_frame`] instead.
+    pub fn run_frame(&mut self, system: &mut System, instructions: u32) -> Result<bool, EmulatorError> {
+        if self.mode == Mode::Paused { return Ok(false); }
+
+        let stopped = system.run_frame_with(instructions, self)?;
+        if stopped { self.mode = Mode::Paused; }
+        Ok(stopped)
+    }
+
+    /// The first register watchpoint set off since the last check.
+    fn register_hit(&self, system: &System) -> Option<Watchpoint> {
+        let (last_v, last_i) = self.last_registers?;
+        let (v, i) = (system.get_registers(), system.get_i());
+
+        self.watchpoints.iter().copied().find(|watchpoint| match *watchpoint {
+            Watchpoint::Register { x, value } => {
+                let now = v[x as usize];
+                now != last_v[x as usize] && value.is_none_or(|value| value == now)
+            }
+            Watchpoint::Index { value } => i != last_i && value.is_none_or(|value| value == i),
+            _ => false,
+        })
+    }
+
+    /// Decodes the instruction at `addr`, if there is a valid one.
+    pub fn instruction_at(system: &System, addr: u16) -> Option<Instruction> {
+        let bytes = system.read_memory(addr, 4).or_else(|| system.read_memory(addr, 2))?;
+        let word = |i: usize| bytes.get(i..i + 2).map_or(0, |word| u16::from_be_bytes([word[0], word[1]]));
+        Instruction::decode(word(0), word(2))
+    }
+}
+
+/// Checks breakpoints and watchpoints while [`Debugger::update`] runs.
+impl Hooks for Debugger {
+    fn before_step(&mut self, system: &System) -> bool {
+        let pc = system.get_pc();
+        let arrived = self.last_pc != Some(pc);
+        let last_pc = self.last_pc.replace(pc);
+
+        // Kept up to date without watchpoints too, so one added while
+        // running compares against the last instruction only.
+        let watchpoint = self.hit.take().or_else(|| self.register_hit(system));
+        self.last_registers = Some((*system.get_registers(), system.get_i()));
+
+        self.reason = match (watchpoint, self.mode) {
+            (Some(watchpoint), _) => Some(PauseReason::Watchpoint(watchpoint, last_pc.unwrap_or(pc))),
+            (_, Mode::StepOver { pc: target, depth }) if pc == target && system.stack_depth() <= depth => Some(PauseReason::Step),
+            (_, Mode::StepOut { depth }) if system.stack_depth() < depth => Some(PauseReason::Step),
+            _ if arrived && self.breakpoints.contains(&pc) => Some(PauseReason::Breakpoint(pc)),
+            _ => None,
+        };
+        self.reason.is_some()
+    }
+
+    fn read(&mut self, addr: u16, len: u16) {
+        self.hit = self.hit.or_else(|| self.watchpoints.iter().copied().find(|watchpoint| matches!(*watchpoint,
+            Watchpoint::Read { addr: start, len: size } if Watchpoint::overlaps(start, size, addr, len))));
+    }
+
+    fn write(&mut self, addr: u16, len: u16) {
+        self.hit = self.hit.or_else(|| self.watchpoints.iter().copied().find(|watchpoint| matches!(*watchpoint,
+            Watchpoint::Write { addr: start, len: size } if Watchpoint::overlaps(start, size, addr, len))));
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,300 @@
+//! Finding the first instruction after which two runs of a rom disagree, to
+//! check quirk settings or changes to the core against a known good run.
+//!
+//! [`traces`] compares two traces recorded with [`crate::trace`], which only
+//! hold registers. [`lockstep`] runs two systems side by side and also
+//! compares the stack, memory and the screen.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufReader, Read};
+
+use crate::cpu::Instruction;
+use crate::error::EmulatorError;
+use crate::system::{Hooks, System, TIMER_HZ};
+use crate::timing::Timing;
+use crate::trace::{Record, Registers, TraceReader};
+
+/// Instructions shown either side of a divergence by default.
+pub const DEFAULT_CONTEXT: usize = 5;
+
+/// One of the two runs being compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Run {
+    A,
+    B,
+}
+
+impl fmt::Display for Run {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self { Run::A => "A", Run::B => "B" })
+    }
+}
+
+/// Something that differs between the runs, as (A, B) pairs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// The PC of the instruction.
+    Pc(u16, u16),
+    /// The opcode, with the second word of long instructions.
+    Opcode(u32, u32),
+    Register { x: u8, a: u8, b: u8 },
+    I(u16, u16),
+    DelayTimer(u8, u8),
+    SoundTimer(u8, u8),
+    Stack(Vec<u16>, Vec<u16>),
+    /// Waiting for the display, under the `display_wait` quirk.
+    Waiting(bool, bool),
+    Halted(bool, bool),
+    /// The first differing byte of memory the instruction read or wrote.
+    Memory { addr: u16, a: u8, b: u8 },
+    Resolution((usize, usize), (usize, usize)),
+    /// The first differing pixel, by column.
+    Pixel { x: usize, y: usize, a: u8, b: u8 },
+    Fault(Option<EmulatorError>, Option<EmulatorError>),
+    /// One trace has no more records.
+    Ended(Run),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fault = |fault: &Option<EmulatorError>| fault.as_ref().map_or("none".to_string(), ToString::to_string);
+        match self {
+            Difference::Pc(a, b) => write!(f, "PC: {:03X} vs {:03X}", a, b),
+            Difference::Opcode(a, b) => write!(f, "opcode: {:04X} vs {:04X}", a, b),
+            Difference::Register { x, a, b } => write!(f, "V{:X}: {:02X} vs {:02X}", x, a, b),
+            Difference::I(a, b) => write!(f, "I: {:03X} vs {:03X}", a, b),
+            Difference::DelayTimer(a, b) => write!(f, "DT: {:02X} vs {:02X}", a, b),
+            Difference::SoundTimer(a, b) => write!(f, "ST: {:02X} vs {:02X}", a, b),
+            Difference::Stack(a, b) => write!(f, "stack: {:03X?} vs {:03X?}", a, b),
+            Difference::Waiting(a, b) => write!(f, "waiting for the display: {} vs {}", a, b),
+            Difference::Halted(a, b) => write!(f, "halted: {} vs {}", a, b),
+            Difference::Memory { addr, a, b } => write!(f, "memory at ${:03X}: {:02X} vs {:02X}", addr, a, b),
+            Difference::Resolution(a, b) => write!(f, "resolution: {}x{} vs {}x{}", a.0, a.1, b.0, b.1),
+            Difference::Pixel { x, y, a, b } => write!(f, "pixel {},{}: {} vs {}", x, y, a, b),
+            Difference::Fault(a, b) => write!(f, "fault: {} vs {}", fault(a), fault(b)),
+            Difference::Ended(run) => write!(f, "trace {} ended", run),
+        }
+    }
+}
+
+/// Where two runs stop agreeing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The cycle of the first instruction after which the runs differ.
+    pub cycle: u64,
+    pub differences: Vec<Difference>,
+    /// The instructions around the divergence from each run, including the
+    /// one at `cycle` unless that run had ended.
+    pub a: Vec<Record>,
+    pub b: Vec<Record>,
+}
+
+/// Lists the differences, then each run's instructions with `>` marking the
+/// one where they diverge.
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Runs diverge at cycle {}:", self.cycle)?;
+        for difference in &self.differences {
+            writeln!(f, "    {}", difference)?;
+        }
+        for (run, records) in [(Run::A, &self.a), (Run::B, &self.b)] {
+            writeln!(f, "{}:", run)?;
+            for record in records {
+                let marker = if record.cycle == self.cycle { '>' } else { ' ' };
+                writeln!(f, "{}{}", marker, record)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compares two traces record by record, and returns the first pair that
+/// differs with up to `context` records either side. `Ok(None)` means the
+/// traces match.
+pub fn traces(a: impl Read, b: impl Read, context: usize) -> io::Result<Option<Divergence>> {
+    let mut a = TraceReader::new(BufReader::new(a))?;
+    let mut b = TraceReader::new(BufReader::new(b))?;
+    let mut history = VecDeque::with_capacity(context + 1);
+
+    loop {
+        let (record_a, record_b) = (a.next_record()?, b.next_record()?);
+        let differences = match (&record_a, &record_b) {
+            (None, None) => return Ok(None),
+            (Some(_), None) => vec![Difference::Ended(Run::B)],
+            (None, Some(_)) => vec![Difference::Ended(Run::A)],
+            (Some(record_a), Some(record_b)) => compare_records(record_a, record_b),
+        };
+
+        if differences.is_empty() {
+            push(&mut history, context, (record_a.unwrap(), record_b.unwrap()));
+            continue;
+        }
+
+        let cycle = record_a.or(record_b).map_or(0, |record| record.cycle);
+        let (mut records_a, mut records_b): (Vec<_>, Vec<_>) = history.into_iter().unzip();
+        records_a.extend(record_a);
+        records_a.extend(a.take(context).collect::<io::Result<Vec<_>>>()?);
+        records_b.extend(record_b);
+        records_b.extend(b.take(context).collect::<io::Result<Vec<_>>>()?);
+        return Ok(Some(Divergence { cycle, differences, a: records_a, b: records_b }));
+    }
+}
+
+/// Runs `a` and `b` one instruction at a time for up to `cycles`
+/// instructions, ticking the timers as [`crate::headless::run`] does at
+/// `a`'s [`System::ips`], unless a system runs them itself under
+/// [`Timing::Vip`], and returns the first instruction after which they
+/// differ. Memory is compared where either instruction read or wrote it, and
+/// the screen after `0` and `D` opcodes. Both systems are left `context`
+/// instructions past the divergence.
+/// Cycles count steps like `--cycles` does, so a cpu waiting for the display
+/// uses them up without leaving records.
+///
+/// Faults are differences when only one run has one, or they differ. When
+/// both runs fault the same way, that fault is returned.
+pub fn lockstep(a: &mut System, b: &mut System, cycles: u64, context: usize) -> Result<Option<Divergence>, EmulatorError> {
+    let mut history = VecDeque::with_capacity(context + 1);
+    let mut divergence: Option<Divergence> = None;
+    let mut frame = 0;
+
+    for cycle in 0.. {
+        match &divergence {
+            Some(divergence) if cycle > divergence.cycle + context as u64 => break,
+            None if cycle == cycles => break,
+            _ => {}
+        }
+
+        let mut accesses = Accesses::default();
+        let (record_a, result_a) = step(a, cycle, &mut accesses);
+        let (record_b, result_b) = step(b, cycle, &mut accesses);
+        if ((cycle + 1) as f64 * TIMER_HZ / a.ips() as f64) as u64 > frame {
+            if a.timing() == Timing::Fixed { a.tick_timers(); }
+            if b.timing() == Timing::Fixed { b.tick_timers(); }
+            frame += 1;
+        }
+
+        if let Some(divergence) = &mut divergence {
+            divergence.a.extend(record_a);
+            divergence.b.extend(record_b);
+            continue;
+        }
+
+        let mut differences = match (&record_a, &record_b) {
+            (Some(record_a), Some(record_b)) => compare_records(record_a, record_b),
+            _ => Vec::new(),
+        };
+        match (result_a, result_b) {
+            (Ok(()), Ok(())) => {}
+            (Err(a), Err(b)) if a == b => return Err(a),
+            (a, b) => differences.push(Difference::Fault(a.err(), b.err())),
+        }
+        compare_machines(a, b, [&record_a, &record_b], &accesses, &mut differences);
+
+        if differences.is_empty() {
+            push(&mut history, context, (record_a, record_b));
+            continue;
+        }
+
+        history.push_back((record_a, record_b));
+        let records_a = history.iter().filter_map(|(record, _)| *record).collect();
+        let records_b = history.iter().filter_map(|(_, record)| *record).collect();
+        divergence = Some(Divergence { cycle, differences, a: records_a, b: records_b });
+    }
+
+    Ok(divergence)
+}
+
+fn push<T>(history: &mut VecDeque<T>, context: usize, records: T) {
+    if context == 0 { return; }
+    if history.len() == context {
+        history.pop_front();
+    }
+    history.push_back(records);
+}
+
+/// Memory either run read or wrote in the last instruction.
+#[derive(Debug, Default)]
+struct Accesses(Vec<(u16, u16)>);
+
+impl Hooks for Accesses {
+    fn read(&mut self, addr: u16, len: u16) { self.0.push((addr, len)); }
+    fn write(&mut self, addr: u16, len: u16) { self.0.push((addr, len)); }
+}
+
+/// Steps the system, describing the instruction the way a trace would. There
+/// is no record when nothing ran, because the cpu was halted or waiting for
+/// the display, or when the instruction faulted.
+fn step(system: &mut System, cycle: u64, accesses: &mut Accesses) -> (Option<Record>, Result<(), EmulatorError>) {
+    let idle = system.is_halted() || system.is_waiting_for_display();
+    let pc = system.get_pc();
+    let word = |addr: u16| system.read_memory(addr, 2).map_or(0, |bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+    let (opcode, next) = (word(pc), word(pc.wrapping_add(2)));
+    let operand = Instruction::decode(opcode, next).filter(|instruction| instruction.size() == 4).map(|_| next);
+
+    let before = Registers::of(system);
+    let result = system.step_with(accesses);
+    let record = Record { cycle, pc, opcode, operand, before, after: Registers::of(system) };
+    (result.is_ok().then_some(record).filter(|_| !idle), result)
+}
+
+fn compare_records(a: &Record, b: &Record) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    if a.pc != b.pc {
+        differences.push(Difference::Pc(a.pc, b.pc));
+    }
+    let opcode = |record: &Record| match record.operand {
+        Some(operand) => (record.opcode as u32) << 16 | operand as u32,
+        None => record.opcode as u32,
+    };
+    if opcode(a) != opcode(b) {
+        differences.push(Difference::Opcode(opcode(a), opcode(b)));
+    }
+
+    // Filtered traces can differ going into an instruction, which is
+    // reported over what came out of it.
+    let (a, b) = if a.before != b.before { (&a.before, &b.before) } else { (&a.after, &b.after) };
+    for x in 0..16 {
+        if a.v[x] != b.v[x] {
+            differences.push(Difference::Register { x: x as u8, a: a.v[x], b: b.v[x] });
+        }
+    }
+    if a.i != b.i { differences.push(Difference::I(a.i, b.i)); }
+    if a.delay_timer != b.delay_timer { differences.push(Difference::DelayTimer(a.delay_timer, b.delay_timer)); }
+    if a.sound_timer != b.sound_timer { differences.push(Difference::SoundTimer(a.sound_timer, b.sound_timer)); }
+    differences
+}
+
+fn compare_machines(a: &System, b: &System, records: [&Option<Record>; 2], accesses: &Accesses, differences: &mut Vec<Difference>) {
+    if a.is_waiting_for_display() != b.is_waiting_for_display() {
+        differences.push(Difference::Waiting(a.is_waiting_for_display(), b.is_waiting_for_display()));
+    }
+    if a.is_halted() != b.is_halted() {
+        differences.push(Difference::Halted(a.is_halted(), b.is_halted()));
+    }
+    if a.get_stack() != b.get_stack() {
+        differences.push(Difference::Stack(a.get_stack(), b.get_stack()));
+    }
+
+    let memory = accesses.0.iter()
+        .flat_map(|&(addr, len)| (0..len).map(move |offset| addr.wrapping_add(offset)))
+        .filter_map(|addr| Some((addr, *a.read_memory(addr, 1)?.first()?, *b.read_memory(addr, 1)?.first()?)))
+        .find(|(_, a, b)| a != b);
+    if let Some((addr, a, b)) = memory {
+        differences.push(Difference::Memory { addr, a, b });
+    }
+
+    let touches_screen = |record: &Option<Record>| record.is_some_and(|record| matches!(record.opcode >> 12, 0x0 | 0xD));
+    if !records.into_iter().any(touches_screen) { return; }
+    if a.get_resolution() != b.get_resolution() {
+        differences.push(Difference::Resolution(a.get_resolution(), b.get_resolution()));
+        return;
+    }
+    let (pixels_a, pixels_b) = (a.get_pixels(), b.get_pixels());
+    let pixel = pixels_a.iter().zip(&pixels_b).enumerate()
+        .flat_map(|(x, (a, b))| a.iter().zip(b.iter()).enumerate().map(move |(y, (&a, &b))| (x, y, a, b)))
+        .find(|(_, _, a, b)| a != b);
+    if let Some((x, y, a, b)) = pixel {
+        differences.push(Difference::Pixel { x, y, a, b });
+    }
+}
@@ -0,0 +1,227 @@
+//! Disassembler that separates code from data by following control flow.
+//!
+//! Starting at the load address, every reachable instruction is decoded,
+//! following jumps, calls and both sides of skips. Whatever is never reached
+//! is data. Sprites are found by tracking `MOV I` in straight line code up to
+//! the `DRAW` that uses it.
+//!
+//! The listing is valid input for the assembler: addresses and raw bytes are
+//! in `;` comments.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::cpu::Instruction;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Start of an instruction of this many bytes.
+    Code(u16),
+    /// Part of a sprite.
+    Sprite,
+    Data,
+}
+
+/// Name of the label for an address, by the reason it was referenced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Label {
+    Sub,
+    Jump,
+    Sprite,
+}
+
+#[derive(Debug)]
+pub struct Disassembly {
+    origin: u16,
+    rom: Vec<u8>,
+    kinds: Vec<Kind>,
+    labels: BTreeMap<u16, Label>,
+}
+
+/// Disassembles a rom loaded at `origin`, normally 0x200.
+pub fn disassemble(rom: &[u8], origin: u16) -> Disassembly {
+    let mut disasm = Disassembly {
+        origin,
+        rom: rom.to_vec(),
+        kinds: vec![Kind::Data; rom.len()],
+        labels: BTreeMap::new(),
+    };
+    disasm.trace();
+    disasm
+}
+
+impl Disassembly {
+    fn offset(&self, addr: u16) -> Option<usize> {
+        let offset = addr.checked_sub(self.origin)? as usize;
+        if offset < self.rom.len() { Some(offset) } else { None }
+    }
+
+    fn word(&self, addr: u16) -> Option<u16> {
+        let offset = self.offset(addr)?;
+        let bytes = self.rom.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn decode(&self, addr: u16) -> Option<Instruction> {
+        let instruction = Instruction::decode(self.word(addr)?, self.word(addr.wrapping_add(2)).unwrap_or(0))?;
+        // F000 NNNN needs both words inside the rom.
+        self.offset(addr.wrapping_add(instruction.size() - 1))?;
+        Some(instruction)
+    }
+
+    fn add_label(&mut self, addr: u16, label: Label) {
+        if self.offset(addr).is_none() { return; }
+        let entry = self.labels.entry(addr).or_insert(label);
+        *entry = (*entry).min(label);
+    }
+
+    fn trace(&mut self) {
+        // Each entry is an address to decode and the value of I there, if known.
+        let mut work = vec![(self.origin, None)];
+        let mut visited = BTreeSet::new();
+
+        while let Some((addr, mut i)) = work.pop() {
+            if !visited.insert(addr) { continue; }
+            let Some(instruction) = self.decode(addr) else { continue };
+
+            let offset = self.offset(addr).unwrap();
+            self.kinds[offset] = Kind::Code(instruction.size());
+            let next = addr.wrapping_add(instruction.size());
+
+            match instruction {
+                Instruction::Return | Instruction::Exit => continue,
+                Instruction::Jump(target) => {
+                    let target = target.get() as u16;
+                    self.add_label(target, Label::Jump);
+                    work.push((target, i));
+                    continue;
+                }
+                Instruction::JumpV0(_) => continue,
+                Instruction::Call(target) => {
+                    let target = target.get() as u16;
+                    self.add_label(target, Label::Sub);
+                    work.push((target, None));
+                    i = None;
+                }
+                Instruction::EqNum(..) | Instruction::NeqNum(..) | Instruction::Eq(..)
+                | Instruction::Neq(..) | Instruction::KeyUp(_) | Instruction::KeyDown(_) => {
+                    let size = self.decode(next).map_or(2, |skipped| skipped.size());
+                    let target = next.wrapping_add(size);
+                    self.add_label(target, Label::Jump);
+                    work.push((target, i));
+                }
+                Instruction::SetIdx(addr) | Instruction::LongIdx(addr) => i = Some(addr.get() as u16),
+                Instruction::Draw(_, _, size) => {
+                    if let Some(sprite) = i {
+                        let len = if size == 0 { 32 } else { size as u16 };
+                        self.add_label(sprite, Label::Sprite);
+                        for n in 0..len {
+                            let Some(offset) = self.offset(sprite.wrapping_add(n)) else { break };
+                            if self.kinds[offset] == Kind::Data { self.kinds[offset] = Kind::Sprite; }
+                        }
+                    }
+                }
+                Instruction::AddIdx(_) | Instruction::SetSprite(_) | Instruction::SetBigSprite(_)
+                | Instruction::Store(_) | Instruction::Load(_) => i = None,
+                _ => {}
+            }
+
+            work.push((next, i));
+        }
+
+        // Sprites found before the code that overlaps them lose to the code.
+        for offset in 0..self.kinds.len() {
+            if let Kind::Code(size) = self.kinds[offset] {
+                for covered in offset + 1..(offset + size as usize).min(self.kinds.len()) {
+                    if self.kinds[covered] == Kind::Sprite { self.kinds[covered] = Kind::Data; }
+                }
+            }
+        }
+    }
+
+    fn label_name(&self, addr: u16) -> Option<String> {
+        let prefix = match self.labels.get(&addr)? {
+            Label::Sub => "sub",
+            Label::Jump => "label",
+            Label::Sprite => "sprite",
+        };
+        Some(format!("{}_{:03X}", prefix, addr))
+    }
+
+    /// An address operand, by label if there is one.
+    fn target(&self, addr: u16, digits: usize) -> String {
+        self.label_name(addr).unwrap_or_else(|| format!("${:0digits$X}", addr, digits = digits))
+    }
+
+    fn mnemonic(&self, instruction: Instruction) -> String {
+        match instruction {
+            Instruction::Jump(a)    => format!("JUMP {}", self.target(a.get() as u16, 3)),
+            Instruction::Call(a)    => format!("CALL {}", self.target(a.get() as u16, 3)),
+            Instruction::SetIdx(a)  => format!("MOV I, {}", self.target(a.get() as u16, 3)),
+            Instruction::JumpV0(a)  => format!("JUMP V0 + {}", self.target(a.get() as u16, 3)),
+            Instruction::LongIdx(a) => format!("MOV I, LONG {}", self.target(a.get() as u16, 4)),
+            _ => instruction.to_string(),
+        }
+    }
+
+    /// Whether an instruction at `offset` can be printed without hiding a
+    /// label or another instruction inside it.
+    fn fits(&self, offset: usize, size: u16) -> bool {
+        (1..size as usize).all(|n| {
+            let addr = self.origin.wrapping_add((offset + n) as u16);
+            !self.labels.contains_key(&addr) && !matches!(self.kinds.get(offset + n), Some(Kind::Code(_)))
+        })
+    }
+}
+
+impl std::fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "ORG ${:03X}", self.origin)?;
+
+        let mut offset = 0;
+        while offset < self.rom.len() {
+            let addr = self.origin.wrapping_add(offset as u16);
+            if let Some(label) = self.label_name(addr) {
+                writeln!(f, "\n{}:", label)?;
+            }
+
+            let (text, len, note) = match self.kinds[offset] {
+                Kind::Code(size) if self.fits(offset, size) => {
+                    (self.mnemonic(self.decode(addr).unwrap()), size as usize, String::new())
+                }
+                Kind::Sprite => {
+                    let byte = self.rom[offset];
+                    let bits = (0..8).map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' }).collect();
+                    (format!("db ${:02X}", byte), 1, bits)
+                }
+                _ => {
+                    // Runs of data up to 8 bytes, stopping at a label or
+                    // anything that isn't plain data.
+                    let len = (offset..self.rom.len().min(offset + 8))
+                        .take_while(|&o| o == offset || (
+                            !self.labels.contains_key(&self.origin.wrapping_add(o as u16))
+                            && match self.kinds[o] {
+                                Kind::Data => true,
+                                Kind::Code(size) => !self.fits(o, size),
+                                Kind::Sprite => false,
+                            }
+                        ))
+                        .count();
+                    let mut text = String::from("db ");
+                    for (n, byte) in self.rom[offset..offset + len].iter().enumerate() {
+                        if n > 0 { text.push_str(", "); }
+                        write!(text, "${:02X}", byte)?;
+                    }
+                    (text, len, String::new())
+                }
+            };
+
+            let bytes: String = self.rom[offset..offset + len].iter().map(|byte| format!("{:02X}", byte)).collect();
+            let line = format!("    {:<24}; {:03X}: {:<8} {}", text, addr, bytes, note);
+            writeln!(f, "{}", line.trim_end())?;
+            offset += len;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use std::fmt;
+
+/// Why the emulated cpu stopped. Every runtime fault carries the address and
+/// opcode of the instruction that caused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmulatorError {
+    /// The opcode at `pc` does not decode to any instruction.
+    InvalidOpcode { pc: u16, opcode: u16 },
+    /// `RET` with nothing on the stack.
+    StackUnderflow { pc: u16, opcode: u16 },
+    /// `CALL` with a full stack.
+    StackOverflow { pc: u16, opcode: u16 },
+    /// A read or write of `len` bytes at `addr` runs past the end of memory.
+    MemoryOutOfRange { pc: u16, opcode: u16, addr: u16, len: u16 },
+    /// The rom does not fit in memory after 0x200.
+    RomTooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidOpcode { pc, opcode } =>
+                write!(f, "[{:03X}]: {:04X} => invalid opcode", pc, opcode),
+            Self::StackUnderflow { pc, opcode } =>
+                write!(f, "[{:03X}]: {:04X} => return with empty stack", pc, opcode),
+            Self::StackOverflow { pc, opcode } =>
+                write!(f, "[{:03X}]: {:04X} => call with full stack", pc, opcode),
+            Self::MemoryOutOfRange { pc, opcode, addr, len } =>
+                write!(f, "[{:03X}]: {:04X} => access of {} bytes at ${:03X} is out of range", pc, opcode, len, addr),
+            Self::RomTooLarge { size, max } =>
+                write!(f, "rom is {} bytes, but at most {} bytes fit in memory", size, max),
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
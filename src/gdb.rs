@@ -0,0 +1,326 @@
+//! A GDB remote serial protocol stub, so gdb or any other RSP client can
+//! debug a rom over TCP.
+//!
+//! Registers are numbered V0-VF (0-15, one byte each), I (16) and PC (17)
+//! as two big endian bytes, then SP (18, the stack depth), DT (19) and ST
+//! (20) as one byte each. SP is read only. Memory is the 4 KiB or, with the
+//! `extended_memory` quirk, 64 KiB the cpu can address. `Z0` and `Z1` both
+//! set PC breakpoints, and `Z2`, `Z3` and `Z4` set write, read and access
+//! watchpoints.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::debugger::{Debugger, PauseReason, Watchpoint};
+use crate::error::EmulatorError;
+use crate::movie::FRAME_DT;
+use crate::system::System;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// Size in bytes of each register, in gdb's numbering.
+const REGISTER_SIZES: [usize; 21] = [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 1, 1, 1];
+
+// The client gets an empty reply to anything it asks for that isn't supported.
+const UNSUPPORTED: &str = "";
+const ERROR: &str = "E01";
+
+/// Serves one client on `stream` until it detaches, kills the rom or hangs
+/// up. The rom starts paused, and `debugger` may already hold breakpoints.
+pub fn serve(stream: TcpStream, system: &mut System, debugger: &mut Debugger) -> io::Result<()> {
+    // Packets are tiny and every one waits for a reply.
+    stream.set_nodelay(true)?;
+    debugger.pause();
+    let mut stub = Stub {
+        connection: Connection { stream, buffer: Vec::new(), acks: true },
+        system,
+        debugger,
+        stop: signal(SIGTRAP),
+    };
+
+    while let Some(packet) = stub.connection.receive()? {
+        match stub.handle(&packet)? {
+            Some(reply) => stub.connection.send(&reply)?,
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+struct Stub<'a> {
+    connection: Connection,
+    system: &'a mut System,
+    debugger: &'a mut Debugger,
+    // Reply to '?', why the rom last stopped.
+    stop: String,
+}
+
+impl Stub<'_> {
+    /// The reply to `packet`, or `None` to end the session.
+    fn handle(&mut self, packet: &str) -> io::Result<Option<String>> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let reply = match command {
+            "?" => self.stop.clone(),
+            "g" => hex(&self.registers()),
+            "G" => reply(unhex(args).and_then(|bytes| self.set_registers(&bytes))),
+            "p" => {
+                let register = usize::from_str_radix(args, 16).ok();
+                register.and_then(|n| self.register(n)).map_or(ERROR.to_string(), |bytes| hex(&bytes))
+            }
+            "P" => reply(args.split_once('=').and_then(|(n, value)| {
+                self.set_register(usize::from_str_radix(n, 16).ok()?, &unhex(value)?)
+            })),
+            "m" => range(args)
+                .and_then(|(addr, len)| self.system.read_memory(addr, len))
+                .map_or(ERROR.to_string(), hex),
+            "M" => reply(args.split_once(':').and_then(|(range_text, data)| {
+                let (addr, len) = range(range_text)?;
+                let data = unhex(data).filter(|data| data.len() == len as usize)?;
+                self.system.write_memory(addr, &data)
+            })),
+            "s" | "c" => {
+                if !args.is_empty() {
+                    match u16::from_str_radix(args, 16) {
+                        Ok(addr) => self.system.set_pc(addr),
+                        Err(_) => return Ok(Some(ERROR.to_string())),
+                    }
+                }
+                self.stop = if command == "s" { self.step() } else { self.resume()? };
+                self.stop.clone()
+            }
+            "Z" | "z" => reply(self.set_point(command == "Z", args)),
+            "H" | "T" => "OK".to_string(),
+            "D" => {
+                self.connection.send("OK")?;
+                return Ok(None);
+            }
+            "k" => return Ok(None),
+            "q" | "Q" => self.query(packet),
+            _ => UNSUPPORTED.to_string(),
+        };
+        Ok(Some(reply))
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        match packet.split(':').next().unwrap_or(packet) {
+            "qSupported" => "PacketSize=1000;QStartNoAckMode+".to_string(),
+            "QStartNoAckMode" => {
+                self.connection.acks = false;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => UNSUPPORTED.to_string(),
+        }
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        let mut bytes = self.system.get_registers().to_vec();
+        bytes.extend(self.system.get_i().to_be_bytes());
+        bytes.extend(self.system.get_pc().to_be_bytes());
+        bytes.extend([self.system.stack_depth() as u8, self.system.get_delay_timer(), self.system.get_sound_timer()]);
+        bytes
+    }
+
+    fn register(&self, n: usize) -> Option<Vec<u8>> {
+        let start = REGISTER_SIZES.get(..n)?.iter().sum();
+        Some(self.registers()[start..start + REGISTER_SIZES.get(n)?].to_vec())
+    }
+
+    fn set_registers(&mut self, bytes: &[u8]) -> Option<()> {
+        if bytes.len() != REGISTER_SIZES.iter().sum() { return None; }
+
+        let mut start = 0;
+        for (n, size) in REGISTER_SIZES.iter().enumerate() {
+            self.set_register(n, &bytes[start..start + size])?;
+            start += size;
+        }
+        Some(())
+    }
+
+    fn set_register(&mut self, n: usize, bytes: &[u8]) -> Option<()> {
+        if bytes.len() != *REGISTER_SIZES.get(n)? { return None; }
+
+        let word = || u16::from_be_bytes([bytes[0], bytes[1]]);
+        match n {
+            0..=15 => self.system.set_register(n as u8, bytes[0])?,
+            16 => self.system.set_i(word()),
+            17 => self.system.set_pc(word()),
+            // The stack can't be resized, only written back unchanged.
+            18 if bytes[0] as usize == self.system.stack_depth() => {}
+            18 => return None,
+            19 => self.system.set_delay_timer(bytes[0]),
+            _ => self.system.set_sound_timer(bytes[0]),
+        }
+        Some(())
+    }
+
+    /// Handles `Z`/`z` packets, `type,addr,kind`.
+    fn set_point(&mut self, insert: bool, args: &str) -> Option<()> {
+        let mut fields = args.split(',');
+        let kind = fields.next()?;
+        let addr = u16::from_str_radix(fields.next()?, 16).ok()?;
+        let len = u16::from_str_radix(fields.next()?, 16).ok()?.max(1);
+
+        let watchpoints = match kind {
+            "0" | "1" => {
+                if insert { self.debugger.add_breakpoint(addr) } else { self.debugger.remove_breakpoint(addr) }
+                return Some(());
+            }
+            "2" => vec![Watchpoint::Write { addr, len }],
+            "3" => vec![Watchpoint::Read { addr, len }],
+            "4" => vec![Watchpoint::Write { addr, len }, Watchpoint::Read { addr, len }],
+            _ => return None,
+        };
+        for watchpoint in watchpoints {
+            if insert { self.debugger.add_watchpoint(watchpoint) } else { self.debugger.remove_watchpoint(watchpoint) }
+        }
+        Some(())
+    }
+
+    fn step(&mut self) -> String {
+        match self.debugger.step(self.system) {
+            Err(err) => fault(&err),
+            Ok(()) if self.system.is_halted() => "W00".to_string(),
+            Ok(()) => signal(SIGTRAP),
+        }
+    }
+
+    /// Runs the rom in real time until it pauses, faults, exits or the
+    /// client sends a break.
+    fn resume(&mut self) -> io::Result<String> {
+        self.debugger.resume(self.system);
+        let mut next_frame = Instant::now();
+
+        loop {
+            if self.system.is_halted() { return Ok("W00".to_string()); }
+
+            match self.debugger.update(self.system, FRAME_DT) {
+                Err(err) => return Ok(fault(&err)),
+                Ok(true) => return Ok(self.stop_reply()),
+                Ok(false) => {}
+            }
+
+            if self.connection.interrupted()? {
+                self.debugger.pause();
+                return Ok(signal(SIGINT));
+            }
+
+            next_frame += Duration::from_secs_f64(FRAME_DT);
+            if let Some(wait) = next_frame.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+        }
+    }
+
+    fn stop_reply(&self) -> String {
+        match self.debugger.pause_reason() {
+            Some(PauseReason::Watchpoint(Watchpoint::Write { addr, .. }, _)) => format!("T{:02x}watch:{:x};", SIGTRAP, addr),
+            Some(PauseReason::Watchpoint(Watchpoint::Read { addr, .. }, _)) => format!("T{:02x}rwatch:{:x};", SIGTRAP, addr),
+            _ => signal(SIGTRAP),
+        }
+    }
+}
+
+struct Connection {
+    stream: TcpStream,
+    // Bytes received but not yet parsed.
+    buffer: Vec<u8>,
+    acks: bool,
+}
+
+impl Connection {
+    /// The next packet with a valid checksum, or `None` once the client
+    /// hangs up. Acks and breaks outside of packets are dropped.
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.buffer.iter().position(|&byte| byte == b'$') {
+                Some(start) => {
+                    self.buffer.drain(..start);
+                    if let Some(end) = self.buffer.iter().position(|&byte| byte == b'#').filter(|end| end + 3 <= self.buffer.len()) {
+                        let data = self.buffer[1..end].to_vec();
+                        let checksum = std::str::from_utf8(&self.buffer[end + 1..end + 3]).ok()
+                            .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+                        self.buffer.drain(..end + 3);
+
+                        let valid = checksum == Some(checksum_of(&data));
+                        if self.acks { self.stream.write_all(if valid { b"+" } else { b"-" })?; }
+                        if valid { return Ok(Some(String::from_utf8_lossy(&data).into_owned())); }
+                        continue;
+                    }
+                }
+                None => self.buffer.clear(),
+            }
+
+            let mut chunk = [0; 1024];
+            match self.stream.read(&mut chunk)? {
+                0 => return Ok(None),
+                len => self.buffer.extend_from_slice(&chunk[..len]),
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        write!(self.stream, "${}#{:02x}", data, checksum_of(data.as_bytes()))
+    }
+
+    /// Whether the client has sent a break (0x03), without waiting. A client
+    /// that hung up counts as one.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 1024];
+        self.stream.set_nonblocking(true)?;
+        let result = self.stream.read(&mut chunk);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(0) => Ok(true),
+            Ok(len) => {
+                self.buffer.extend(chunk[..len].iter().filter(|&&byte| byte != 0x03));
+                Ok(chunk[..len].contains(&0x03))
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn signal(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn fault(err: &EmulatorError) -> String {
+    match err {
+        EmulatorError::MemoryOutOfRange { .. } => signal(SIGSEGV),
+        _ => signal(SIGILL),
+    }
+}
+
+fn reply(result: Option<()>) -> String {
+    result.map_or(ERROR, |()| "OK").to_string()
+}
+
+/// Parses `addr,len`.
+fn range(text: &str) -> Option<(u16, u16)> {
+    let (addr, len) = text.split_once(',')?;
+    Some((u16::from_str_radix(addr, 16).ok()?, u16::from_str_radix(len, 16).ok()?))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) { return None; }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
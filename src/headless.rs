@@ -0,0 +1,73 @@
+//! Running a rom without a window, for automated tests.
+
+use crate::error::EmulatorError;
+use crate::json::Json;
+use crate::movie::{MovieEvent, Schedule};
+use crate::system::{System, TIMER_HZ};
+use crate::timing::Timing;
+
+/// How long to run for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunLength {
+    /// 60hz frames, each run by the [`Schedule`] given to [`run`].
+    Frames(u64),
+    /// Single instructions, with the timers ticking every `ips / TIMER_HZ` of
+    /// them, or by themselves under [`Timing::Vip`].
+    Cycles(u64),
+}
+
+/// Runs `system`, pressing and releasing keys as the script says. Event
+/// frames count 60hz timer ticks in both modes. `schedule` only applies to
+/// [`RunLength::Frames`].
+pub fn run(system: &mut System, length: RunLength, schedule: Schedule, script: &[MovieEvent]) -> Result<(), EmulatorError> {
+    let mut events = script.iter().peekable();
+    let mut press_keys = |system: &mut System, frame: u64| {
+        while let Some(event) = events.next_if(|event| event.frame <= frame) {
+            system.update_keypad(event.key, event.pressed);
+        }
+    };
+
+    match length {
+        RunLength::Frames(frames) => {
+            for frame in 0..frames {
+                press_keys(system, frame);
+                schedule.run_frame(system, frame)?;
+            }
+        }
+        RunLength::Cycles(cycles) => {
+            let start = system.get_time();
+            let mut frame = 0;
+            for cycle in 1..=cycles {
+                press_keys(system, frame);
+                system.step()?;
+
+                match system.timing() {
+                    Timing::Fixed => if (cycle as f64 * TIMER_HZ / system.ips() as f64) as u64 > frame {
+                        system.tick_timers();
+                        frame += 1;
+                    },
+                    Timing::Vip => frame = ((system.get_time() - start) * TIMER_HZ) as u64,
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The cpu registers as a JSON object.
+pub fn registers_json(system: &System) -> String {
+    let numbers = |values: &mut dyn Iterator<Item = i64>| Json::Array(values.map(Json::from).collect());
+
+    let registers = Json::object([
+        ("pc", i64::from(system.get_pc()).into()),
+        ("i", i64::from(system.get_i()).into()),
+        ("v", numbers(&mut system.get_registers().iter().map(|&v| v.into()))),
+        ("stack", numbers(&mut system.get_stack().into_iter().map(i64::from))),
+        ("delay_timer", i64::from(system.get_delay_timer()).into()),
+        ("sound_timer", i64::from(system.get_sound_timer()).into()),
+        ("halted", system.is_halted().into()),
+        ("fault", system.fault().map(ToString::to_string).into()),
+    ]);
+    format!("{}\n", registers)
+}
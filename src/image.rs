@@ -0,0 +1,108 @@
+//! Encoders for dumping the screen to a file.
+//!
+//! All of them take the output of [`crate::System::get_pixels`]: columns of
+//! colour indices, where 0 is off and 1-3 are the XO-CHIP plane colours.
+
+// Grey level of each colour index in the greyscale formats.
+const GREYS: [u8; 4] = [0, 255, 85, 170];
+// Character for each colour index in the ascii art format.
+const CHARS: [char; 4] = ['.', '#', '+', '@'];
+
+/// Plain text, one line per row.
+pub fn ascii(pixels: &[&[u8]]) -> String {
+    let height = pixels.first().map_or(0, |column| column.len());
+    let mut out = String::new();
+
+    for y in 0..height {
+        out.extend(pixels.iter().map(|column| CHARS[column[y] as usize & 3]));
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Binary PBM (`P4`), with every lit pixel black regardless of its colour.
+// http://netpbm.sourceforge.net/doc/pbm.html
+pub fn pbm(pixels: &[&[u8]]) -> Vec<u8> {
+    let (width, height) = size(pixels);
+    let mut out = format!("P4\n{} {}\n", width, height).into_bytes();
+
+    for y in 0..height {
+        for chunk in pixels.chunks(8) {
+            let byte = chunk.iter().enumerate()
+                .fold(0u8, |byte, (i, column)| byte | (((column[y] != 0) as u8) << (7 - i)));
+            out.push(byte);
+        }
+    }
+
+    out
+}
+
+/// 8 bit greyscale PNG, stored without compression.
+// https://www.w3.org/TR/png/
+pub fn png(pixels: &[&[u8]]) -> Vec<u8> {
+    let (width, height) = size(pixels);
+
+    let mut raw = Vec::with_capacity((width + 1) * height);
+    for y in 0..height {
+        raw.push(0); // no filter
+        raw.extend(pixels.iter().map(|column| GREYS[column[y] as usize & 3]));
+    }
+
+    let mut ihdr = Vec::new();
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[8, 0, 0, 0, 0]); // 8 bit greyscale, no interlace
+
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    png_chunk(&mut out, b"IHDR", &ihdr);
+    png_chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+    png_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn size(pixels: &[&[u8]]) -> (usize, usize) {
+    (pixels.len(), pixels.first().map_or(0, |column| column.len()))
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// https://www.rfc-editor.org/rfc/rfc1950 and rfc1951, using only stored blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        out.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| if crc & 1 == 1 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 })
+    })
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
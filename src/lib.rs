@@ -0,0 +1,42 @@
+//! Core of the chip8 emulator, independent of any frontend.
+//!
+//! Load a rom with [`System::new`], feed it time with [`System::update`] and
+//! key presses with [`System::update_keypad`], and read back the screen with
+//! [`System::get_pixels`] and the buzzer with [`System::get_sound`].
+
+pub mod input;
+pub mod output;
+pub mod audio;
+pub mod cpu;
+pub mod system;
+pub mod quirks;
+pub mod timing;
+pub mod error;
+pub mod state;
+pub mod rewind;
+pub mod rng;
+pub mod movie;
+pub mod headless;
+pub mod image;
+pub mod disasm;
+pub mod asm;
+pub mod octo;
+pub mod source_map;
+pub mod debugger;
+pub mod trace;
+pub mod diff;
+pub mod gdb;
+pub mod json;
+pub mod dap;
+
+pub use input::{Key, Keypad};
+pub use output::Screen;
+pub use cpu::Instruction;
+pub use system::System;
+pub use quirks::Quirks;
+pub use timing::Timing;
+pub use error::EmulatorError;
+pub use state::StateError;
+pub use rewind::Rewind;
+pub use movie::{Movie, Schedule};
+pub use debugger::{Debugger, PauseReason, Watchpoint};
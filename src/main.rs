@@ -1,31 +1,226 @@
 extern crate piston_window;
 
-use piston_window::{PistonWindow, WindowSettings, Event, Loop, Window, Input, Button};
-use std::{fs,env};
+use piston_window::{PistonWindow, WindowSettings, Event, Loop, Window, AdvancedWindow, EventLoop, Input, Button, ButtonState};
+use std::{fs,env,io,process};
+use std::io::Read;
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::{Path, PathBuf};
 
-pub mod input;
-pub mod output;
-pub mod cpu;
-pub mod system;
+use chip8::{Debugger, Key, Movie, PauseReason, Rewind, System};
+use chip8::audio::{Audio, AudioSink, NullSink, WavSink, SAMPLE_RATE};
+use chip8::movie::{Player, Recorder, Schedule, FRAME_DT};
+use chip8::system::instructions_in_frame;
+use chip8::trace::Tracer;
 
-use input::*;
-use system::*;
+mod cli;
+mod overlay;
+mod runner;
+mod speed;
+#[cfg(feature = "audio")]
+mod speaker;
+use cli::{Command, DiffOptions, Options};
+use speed::Speed;
 
-const DEBUG: bool = false;
+const UPDATES_PER_SECOND: u64 = 120;
+const REWIND_SECONDS: f64 = 30.0;
 
 fn main() {
-    let file = env::args().nth(1).expect("No file provided");
-    let rom = fs::read(file).unwrap();
-    let mut system = System::new(&rom);
+    let options = Options::parse(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, cli::USAGE);
+        process::exit(2);
+    });
+
+    if let Command::Dap = options.command {
+        if let Err(err) = chip8::dap::serve(io::stdin(), io::stdout()) {
+            eprintln!("Debug adapter failed: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::Trace = options.command {
+        let result = fs::File::open(&options.rom)
+            .and_then(|file| chip8::trace::render(file, io::stdout().lock(), &options.trace_filter));
+        if let Err(err) = result {
+            eprintln!("Could not read trace {}: {}", options.rom, err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::Diff(diff) = &options.command {
+        compare(&options, diff);
+        return;
+    }
+
+    if let Command::Asm { output } = &options.command {
+        assemble(&options.rom, output.as_deref());
+        return;
+    }
+
+    let rom = read_rom(&options.rom).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    if let Command::Disasm = options.command {
+        print!("{}", chip8::disasm::disassemble(&rom, 0x200));
+        return;
+    }
+
+    let movie = options.play.as_ref().map(|path| {
+        let movie = fs::read_to_string(path).map_err(|err| err.to_string())
+            .and_then(|text| Movie::parse(&text).map_err(|err| err.to_string()))
+            .unwrap_or_else(|err| {
+                eprintln!("Could not read movie {}: {}", path, err);
+                process::exit(1);
+            });
+        if let Err(err) = movie.check_rom(&rom) {
+            eprintln!("Warning: {}", err);
+        }
+        movie
+    });
+
+    let booted = match &movie {
+        Some(movie) => movie.boot(&rom),
+        None => System::with_quirks(&rom, options.quirks),
+    };
+    let mut system = booted.unwrap_or_else(|err| {
+        eprintln!("Could not load {}: {}", options.rom, err);
+        process::exit(1);
+    });
+    if let Some(path) = &options.trace {
+        let tracer = Tracer::create(path, options.trace_filter.clone()).unwrap_or_else(|err| {
+            eprintln!("Could not create {}: {}", path, err);
+            process::exit(1);
+        });
+        system.set_tracer(Some(tracer));
+    }
+    if let (Some(seed), None) = (options.seed, &movie) {
+        system.set_seed(seed);
+    }
+    if movie.is_none() {
+        system.set_timing(options.timing);
+        system.set_ips(options.ips);
+    }
+
+    if let Command::Headless(headless) = &options.command {
+        let mut audio = options.wav.as_ref().map(|_| Audio::new(options.beeper.clone(), WavSink::new(SAMPLE_RATE), &mut system));
+        let result = runner::run(headless, &mut system, movie.as_ref(), options.schedule);
+        finish_trace(&mut system);
+        if let Some(audio) = &mut audio { audio.update(&mut system); }
+        finish_wav(audio.as_ref().map(Audio::sink), options.wav.as_deref());
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::Gdb { port } = options.command {
+        let mut audio = options.wav.as_ref().map(|_| Audio::new(options.beeper.clone(), WavSink::new(SAMPLE_RATE), &mut system));
+        serve_gdb(port, &mut system, &mut new_debugger(&options));
+        finish_trace(&mut system);
+        if let Some(audio) = &mut audio { audio.update(&mut system); }
+        finish_wav(audio.as_ref().map(Audio::sink), options.wav.as_deref());
+        return;
+    }
+
+    let mut player = movie.map(Player::new);
+    let mut recorder = options.record.as_ref().map(|_| Recorder::new(&rom, &system, options.schedule));
 
     let mut window: PistonWindow = WindowSettings::new("Chip8", [640, 320])
         .exit_on_esc(true).build().unwrap();
+    window.set_ups(UPDATES_PER_SECOND);
+
+    let mut speed = Speed::new(options.fast_forward, &system);
+    // Frames run by the window, and whether to run one while paused.
+    let mut frame = 0;
+    let mut advance_frame = false;
+    let mut title = String::new();
+    let mut rewind = Rewind::new((REWIND_SECONDS / FRAME_DT) as usize);
+    let mut rewinding = false;
+
+    let mut debugger = new_debugger(&options);
+    let speaker = open_speaker();
+    let wav = options.wav.as_ref().map(|_| WavSink::new(speaker.sample_rate()));
+    let mut audio = Audio::new(options.beeper.clone(), (speaker, wav), &mut system);
+    // Selected line of the disassembly while paused.
+    let mut cursor = system.get_pc();
 
     while let Some(event) = window.next() {
+        // Save states and rewinding would make a movie desync.
+        let movie_active = player.is_some() || recorder.is_some();
+
         match event {
-            Event::Loop(Loop::Render(_)) => {}
-            Event::Loop(Loop::Update(args)) => system.update(args.dt),
+            Event::Loop(Loop::Render(_)) => speed.drawn(&system),
+            Event::Loop(Loop::Update(args)) => {
+                let running = rewinding || (system.fault().is_none() && !debugger.is_paused());
+                let due = speed.due(args.dt, running);
+                let mut advance = std::mem::take(&mut advance_frame) && debugger.is_paused();
+
+                let mut run = 0;
+                while due.more(run) || advance {
+                    run += 1;
+                    let advancing = std::mem::take(&mut advance);
+
+                    if rewinding {
+                        rewind.rewind(&mut system);
+                        continue;
+                    }
+                    if system.fault().is_some() || (debugger.is_paused() && !advancing) { break; }
+
+                    let result = if let Some(player) = &mut player {
+                        player.run_frame(&mut system)
+                    } else if let Some(recorder) = &mut recorder {
+                        recorder.run_frame(&mut system)
+                    } else {
+                        if advancing { debugger.resume(&system); }
+                        let instructions = instructions_in_frame(system.ips(), frame);
+                        let paused = match options.schedule {
+                            Schedule::Frame => debugger.run_frame(&mut system, instructions),
+                            Schedule::Dt => debugger.update(&mut system, FRAME_DT),
+                        };
+                        frame += 1;
+                        if advancing && !debugger.is_paused() { debugger.pause(); }
+                        paused.map(|paused| if paused || advancing { cursor = system.get_pc(); })
+                    };
+
+                    if let Err(err) = result {
+                        eprintln!("Emulator fault: {}", err);
+                    }
+                    audio.update(&mut system);
+                    if !movie_active { rewind.push(&system); }
+
+                    if player.as_ref().is_some_and(Player::is_finished) {
+                        println!("Movie finished");
+                        player = None;
+                    }
+                }
+            }
             Event::Input(Input::Button(args), _) => {
+                if let (Button::Keyboard(key), ButtonState::Press, false) = (args.button, args.state, movie_active) {
+                    if state_hotkey(key, &mut system, &options.rom) {
+                        rewind.clear();
+                    }
+                }
+
+                if let (Button::Keyboard(key), ButtonState::Press, false) = (args.button, args.state, movie_active) {
+                    if debugger_hotkey(key, &mut debugger, &mut system, &mut cursor) {
+                        continue;
+                    }
+                }
+
+                if let Button::Keyboard(key) = args.button {
+                    if speed_hotkey(key, args.state == ButtonState::Press, &mut speed, &mut advance_frame) {
+                        continue;
+                    }
+                }
+
+                if args.button == Button::Keyboard(piston_window::Key::Backspace) && !movie_active {
+                    rewinding = args.state == ButtonState::Press;
+                }
+
                 let key = match args.button {
                     Button::Keyboard(key) => {
                         match key {
@@ -56,44 +251,290 @@ fn main() {
                 };
 
                 let state = match args.state {
-                    piston_window::ButtonState::Press => true,
-                    piston_window::ButtonState::Release => false,
+                    ButtonState::Press => true,
+                    ButtonState::Release => false,
                 };
 
                 if let Some(key) = key {
-                    system.update_keypad(key, state);
+                    if let Some(recorder) = &mut recorder {
+                        recorder.update_keypad(&mut system, key, state);
+                    } else if player.is_none() {
+                        system.update_keypad(key, state);
+                    }
                 }
             }
             _ => {}
         }
 
+        let status = match system.fault() {
+            Some(err) => format!("Chip8 - fault: {}", err),
+            None if debugger.is_paused() => paused_title(&system, &debugger),
+            None if rewinding => "Chip8 - rewinding".to_string(),
+            None => format!("Chip8 - {}", speed.status(&system)),
+        };
+        if status != title {
+            window.set_title(status.clone());
+            title = status;
+        }
+
         let window_size = window.size();
         window.draw_2d(&event, |context, graphics, _device| {
             let sound = system.get_sound();
 
-            let bg  = [0.5, 0.5, 0.5, 1.0];
+            let bg  = if system.fault().is_some() {[0.6, 0.1, 0.1, 1.0]} else {[0.5, 0.5, 0.5, 1.0]};
             let fg0 = if sound {[0.1, 0.0, 0.0, 1.0]} else {[0.0, 0.0, 0.0, 1.0]};
             let fg1 = if sound {[1.0, 0.9, 0.9, 1.0]} else {[1.0, 1.0, 1.0, 1.0]};
+            let fg2 = if sound {[0.6, 0.4, 0.4, 1.0]} else {[0.5, 0.5, 0.5, 1.0]};
+            let fg3 = if sound {[1.0, 0.5, 0.5, 1.0]} else {[1.0, 0.7, 0.2, 1.0]};
 
             piston_window::clear(bg, graphics);
 
-            let scale = (window_size.width / 64.0).min(window_size.height / 32.0);
+            let (width, height) = system.get_resolution();
+            let (width, height) = (width as f64, height as f64);
+            let scale = (window_size.width / width).min(window_size.height / height);
 
-            let offset_x = (window_size.width - 64.0*scale) / 2.0;
-            let offset_y = (window_size.height - 32.0*scale) / 2.0;
+            let offset_x = (window_size.width - width*scale) / 2.0;
+            let offset_y = (window_size.height - height*scale) / 2.0;
 
-            piston_window::rectangle(fg0,[offset_x, offset_y, 64.0*scale, 32.0*scale], context.transform, graphics);
+            piston_window::rectangle(fg0,[offset_x, offset_y, width*scale, height*scale], context.transform, graphics);
 
             let pixels = system.get_pixels();
             for (x, row) in pixels.iter().enumerate() {
                 for (y, pix) in row.iter().enumerate() {
-                    if *pix {
-                        let x = offset_x + (x as f64) * scale;
-                        let y = offset_y + (y as f64) * scale;
-                        piston_window::rectangle(fg1, [x, y, scale, scale], context.transform, graphics);
-                    }
+                    let fg = match pix { 1 => fg1, 2 => fg2, 3 => fg3, _ => continue };
+                    let x = offset_x + (x as f64) * scale;
+                    let y = offset_y + (y as f64) * scale;
+                    piston_window::rectangle(fg, [x, y, scale, scale], context.transform, graphics);
                 }
             }
+
+            if debugger.is_paused() {
+                overlay::draw(&system, &debugger, cursor, context, graphics);
+            }
+        });
+    }
+
+    finish_trace(&mut system);
+    finish_wav(audio.into_sink().1.as_ref(), options.wav.as_deref());
+
+    if let (Some(recorder), Some(path)) = (recorder, &options.record) {
+        match fs::write(path, recorder.finish().to_string()) {
+            Ok(()) => println!("Saved movie to {}", path),
+            Err(err) => eprintln!("Could not save movie to {}: {}", path, err),
+        }
+    }
+}
+
+/// Reads a rom, compiling it first if it's Octo source.
+fn read_rom(path: &str) -> Result<Vec<u8>, String> {
+    if Path::new(path).extension().is_some_and(|extension| extension == "8o") {
+        let source = fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path, err))?;
+        return chip8::octo::compile(&source).map_err(|err| format!("{}:{}", path, err));
+    }
+    fs::read(path).map_err(|err| format!("Could not read {}: {}", path, err))
+}
+
+/// Compares two traces, or runs two roms in lockstep, printing where they
+/// diverge. Exits with 1 if they do, like diff.
+fn compare(options: &Options, diff: &DiffOptions) {
+    let other = diff.other.as_deref().unwrap_or(&options.rom);
+    let is_trace = |path: &str| {
+        let mut magic = [0; 4];
+        fs::File::open(path).and_then(|mut file| file.read_exact(&mut magic)).is_ok() && &magic == chip8::trace::MAGIC
+    };
+
+    let result = if is_trace(&options.rom) || is_trace(other) {
+        fs::File::open(&options.rom)
+            .and_then(|a| Ok((a, fs::File::open(other)?)))
+            .and_then(|(a, b)| chip8::diff::traces(a, b, diff.context))
+            .map_err(|err| format!("Could not compare traces: {}", err))
+    } else {
+        let boot = |path: &str, quirks| read_rom(path).and_then(|rom| {
+            System::with_quirks(&rom, quirks).map_err(|err| format!("Could not load {}: {}", path, err))
         });
+        boot(&options.rom, options.quirks).and_then(|a| Ok((a, boot(other, diff.quirks.unwrap_or(options.quirks))?)))
+            .and_then(|(mut a, mut b)| {
+                // Both runs need the same random numbers.
+                let seed = options.seed.unwrap_or(a.seed());
+                a.set_seed(seed);
+                b.set_seed(seed);
+                a.set_timing(options.timing);
+                b.set_timing(options.timing);
+                a.set_ips(options.ips);
+                b.set_ips(options.ips);
+                chip8::diff::lockstep(&mut a, &mut b, diff.cycles, diff.context)
+                    .map_err(|err| format!("Both runs fault: {}", err))
+            })
+    };
+
+    match result {
+        Ok(None) => println!("No differences"),
+        Ok(Some(divergence)) => {
+            print!("{}", divergence);
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(2);
+        }
+    }
+}
+
+fn assemble(source: &str, output: Option<&str>) {
+    let output = output.map(PathBuf::from).unwrap_or_else(|| Path::new(source).with_extension("ch8"));
+    if output == Path::new(source) {
+        eprintln!("Refusing to overwrite {} with its own assembly, pass --output", source);
+        process::exit(2);
+    }
+
+    let rom = chip8::asm::assemble_file(source).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    fs::write(&output, rom).unwrap_or_else(|err| {
+        eprintln!("Could not write {}: {}", output.display(), err);
+        process::exit(1);
+    });
+}
+
+/// The speakers, or a sink that drops the sound if there are none or the
+/// binary was built without the `audio` feature.
+fn open_speaker() -> Box<dyn AudioSink> {
+    #[cfg(feature = "audio")]
+    match speaker::Speaker::open() {
+        Ok(speaker) => return Box::new(speaker),
+        Err(err) => eprintln!("No sound: {}", err),
+    }
+    Box::new(NullSink)
+}
+
+/// Writes the sound recorded for `--wav`, if it was given.
+fn finish_wav(sink: Option<&WavSink>, path: Option<&str>) {
+    if let (Some(sink), Some(path)) = (sink, path) {
+        match fs::write(path, sink.to_wav()) {
+            Ok(()) => println!("Saved sound to {}", path),
+            Err(err) => eprintln!("Could not save sound to {}: {}", path, err),
+        }
+    }
+}
+
+/// Flushes the trace, if there is one.
+fn finish_trace(system: &mut System) {
+    if let Some(Err(err)) = system.take_tracer().map(Tracer::finish) {
+        eprintln!("Could not write trace: {}", err);
+    }
+}
+
+/// A debugger holding the breakpoints and watchpoints from the command line.
+fn new_debugger(options: &Options) -> Debugger {
+    let mut debugger = Debugger::new();
+    for &addr in &options.breakpoints {
+        debugger.add_breakpoint(addr);
+    }
+    for &watchpoint in &options.watchpoints {
+        debugger.add_watchpoint(watchpoint);
+    }
+    debugger
+}
+
+fn serve_gdb(port: u16, system: &mut System, debugger: &mut Debugger) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).unwrap_or_else(|err| {
+        eprintln!("Could not listen on port {}: {}", port, err);
+        process::exit(1);
+    });
+    println!("Waiting for gdb on {}", listener.local_addr().map_or(port.to_string(), |addr| addr.to_string()));
+
+    let result = listener.accept().and_then(|(stream, client)| {
+        println!("Debugging for {}", client);
+        chip8::gdb::serve(stream, system, debugger)
+    });
+    if let Err(err) = result {
+        eprintln!("gdb connection failed: {}", err);
+        finish_trace(system);
+        process::exit(1);
+    }
+}
+
+fn paused_title(system: &System, debugger: &Debugger) -> String {
+    match debugger.pause_reason() {
+        Some(reason @ PauseReason::Watchpoint(..)) => format!("Chip8 - {}, paused at ${:03X}", reason, system.get_pc()),
+        _ => format!("Chip8 - paused at ${:03X}", system.get_pc()),
+    }
+}
+
+/// P pauses and resumes, F9 toggles a breakpoint on the selected line, F10
+/// steps over, F11 steps into and F12 steps out. While paused, Up and Down
+/// move the selection. Returns true if the key was used.
+fn debugger_hotkey(key: piston_window::Key, debugger: &mut Debugger, system: &mut System, cursor: &mut u16) -> bool {
+    use piston_window::Key::*;
+
+    let result = match (key, debugger.is_paused()) {
+        (P, true) => { debugger.resume(system); Ok(()) }
+        (P, false) => { debugger.pause(); Ok(()) }
+        (F9, _) => {
+            let addr = if debugger.is_paused() { *cursor } else { system.get_pc() };
+            debugger.toggle_breakpoint(addr);
+            return true;
+        }
+        (F10, true) => debugger.step_over(system),
+        (F11, true) => debugger.step(system),
+        (F12, true) => debugger.step_out(system),
+        (Up, true) => {
+            *cursor = cursor.wrapping_sub(2);
+            return true;
+        }
+        (Down, true) => {
+            *cursor = overlay::next_instruction(system, *cursor);
+            return true;
+        }
+        _ => return false,
+    };
+
+    if let Err(err) = result {
+        eprintln!("Emulator fault: {}", err);
+    }
+    *cursor = system.get_pc();
+    true
+}
+
+/// Tab fast-forwards while held, M toggles slow motion and N runs a single
+/// frame while paused. Returns true if the key was used.
+fn speed_hotkey(key: piston_window::Key, pressed: bool, speed: &mut Speed, advance_frame: &mut bool) -> bool {
+    use piston_window::Key::*;
+
+    match (key, pressed) {
+        (Tab, _) => speed.fast_forward = pressed,
+        (M, true) => speed.slow_motion = !speed.slow_motion,
+        (N, true) => *advance_frame = true,
+        (M | N, false) => {}
+        _ => return false,
+    }
+    true
+}
+
+/// F1-F4 save the system to slots 1-4 next to the rom, F5-F8 load them back.
+/// Returns true if a state was loaded.
+fn state_hotkey(key: piston_window::Key, system: &mut System, rom: &str) -> bool {
+    use piston_window::Key::*;
+
+    let (save, slot) = match key {
+        F1 => (true, 1), F2 => (true, 2), F3 => (true, 3), F4 => (true, 4),
+        F5 => (false, 1), F6 => (false, 2), F7 => (false, 3), F8 => (false, 4),
+        _ => return false,
+    };
+    let path = format!("{}.state{}", rom, slot);
+
+    if save {
+        match fs::write(&path, system.save_state()) {
+            Ok(()) => println!("Saved state to {}", path),
+            Err(err) => eprintln!("Could not save state to {}: {}", path, err),
+        }
+        return false;
+    }
+
+    match fs::read(&path).map_err(|err| err.to_string())
+        .and_then(|data| system.load_state(&data).map_err(|err| err.to_string())) {
+        Ok(()) => { println!("Loaded state from {}", path); true }
+        Err(err) => { eprintln!("Could not load state from {}: {}", path, err); false }
     }
 }
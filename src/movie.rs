@@ -0,0 +1,349 @@
+//! Recording and playback of keypad input, for reproducible runs.
+//!
+//! A movie is a text file:
+//!
+//! ```text
+//! chip8-movie 4
+//! rom 5d8d6f5e0f5ec5b1
+//! seed 1234
+//! quirks shift_uses_vy load_store_increment_i
+//! timing fixed 500
+//! schedule frame
+//! frames 600
+//! 12 5 1
+//! 20 5 0
+//! ```
+//!
+//! The header holds the FNV-1a hash of the rom, the seed, the enabled quirks,
+//! the [`Timing`] with the instructions per second and the [`Schedule`] of
+//! each frame, followed by the total number of frames and one line per keypad
+//! transition: the frame it happened on, the key in hex and 1 for pressed or
+//! 0 for released. Version 1 has no timing and version 2 no instructions per
+//! second, which default to fixed timing at [`DEFAULT_IPS`]. Versions before
+//! 4 have no schedule and ran every frame as [`Schedule::Dt`]. Frames are
+//! counted in emulated time, so playback does not depend on the speed of the
+//! machine.
+
+use std::fmt;
+
+use crate::error::EmulatorError;
+use crate::input::Key;
+use crate::quirks::Quirks;
+use crate::system::{instructions_in_frame, System, DEFAULT_IPS};
+use crate::timing::Timing;
+
+/// Emulated time covered by one frame of a movie.
+pub const FRAME_DT: f64 = 1.0 / 60.0;
+
+const HEADER: &str = "chip8-movie 4";
+
+/// How each frame is run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Schedule {
+    /// A fixed number of instructions from [`instructions_in_frame`], then a
+    /// timer tick.
+    #[default]
+    Frame,
+    /// [`FRAME_DT`] seconds of instructions and timer ticks.
+    Dt,
+}
+
+impl Schedule {
+    /// Names accepted by [`Schedule::from_name`].
+    pub const NAMES: [&'static str; 2] = ["frame", "dt"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "frame" => Some(Self::Frame),
+            "dt"    => Some(Self::Dt),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Frame => "frame",
+            Self::Dt => "dt",
+        }
+    }
+
+    /// Runs frame `frame`, counting from 0, of `system`.
+    pub fn run_frame(self, system: &mut System, frame: u64) -> Result<(), EmulatorError> {
+        match self {
+            Self::Frame => system.run_frame(instructions_in_frame(system.ips(), frame)),
+            Self::Dt => system.update(FRAME_DT),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    /// The movie file is malformed at `line` (1 based).
+    Parse { line: usize, message: String },
+    /// The movie was recorded on a different rom, so playback will desync.
+    RomMismatch { expected: u64, actual: u64 },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            Self::RomMismatch { expected, actual } =>
+                write!(f, "desync: movie was recorded on rom {:016x}, but this rom is {:016x}", expected, actual),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovieEvent {
+    pub frame: u64,
+    pub key: Key,
+    pub pressed: bool,
+}
+
+/// Parses a script of bare event lines, as found after a movie's header.
+/// Blank lines and lines starting with `#` are ignored.
+pub fn parse_script(text: &str) -> Result<Vec<MovieEvent>, MovieError> {
+    parse_events(text.lines().enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#')))
+}
+
+fn parse_events<'a>(lines: impl Iterator<Item = (usize, &'a str)>) -> Result<Vec<MovieEvent>, MovieError> {
+    let mut events: Vec<MovieEvent> = Vec::new();
+    for (line, text) in lines {
+        let event = MovieEvent::parse(text).ok_or_else(|| MovieError::Parse {
+            line,
+            message: "expected '<frame> <key> <0|1>'".to_string(),
+        })?;
+        if events.last().is_some_and(|last| last.frame > event.frame) {
+            return Err(MovieError::Parse { line, message: "events are not in frame order".to_string() });
+        }
+        events.push(event);
+    }
+    Ok(events)
+}
+
+impl MovieEvent {
+    fn parse(text: &str) -> Option<Self> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let [frame, key, pressed] = words[..] else { return None };
+
+        Some(MovieEvent {
+            frame: frame.parse().ok()?,
+            key: u8::from_str_radix(key, 16).ok().filter(|&key| key < 16).map(Key::new)?,
+            pressed: match pressed { "1" => true, "0" => false, _ => return None },
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub timing: Timing,
+    pub ips: u32,
+    pub schedule: Schedule,
+    pub frames: u64,
+    pub events: Vec<MovieEvent>,
+}
+
+impl Movie {
+    pub fn parse(text: &str) -> Result<Self, MovieError> {
+        let mut lines = text.lines().enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        let error = |line: usize, message: &str| MovieError::Parse { line, message: message.to_string() };
+        let mut field = |name: &str| -> Result<(usize, Vec<String>), MovieError> {
+            let (line, text) = lines.next().ok_or_else(|| error(0, &format!("missing '{}'", name)))?;
+            let mut words = text.split_whitespace();
+            if words.next() != Some(name) {
+                return Err(error(line, &format!("expected '{}'", name)));
+            }
+            Ok((line, words.map(str::to_string).collect()))
+        };
+
+        let (line, version) = field("chip8-movie")?;
+        let version = match version.as_slice() {
+            [version] if version == "1" => 1,
+            [version] if version == "2" => 2,
+            [version] if version == "3" => 3,
+            [version] if version == "4" => 4,
+            _ => return Err(error(line, "unsupported movie version")),
+        };
+
+        let (line, rom) = field("rom")?;
+        let rom_hash = rom.first().and_then(|hash| u64::from_str_radix(hash, 16).ok())
+            .ok_or_else(|| error(line, "invalid rom hash"))?;
+
+        let (line, seed) = field("seed")?;
+        let seed = seed.first().and_then(|seed| seed.parse().ok())
+            .ok_or_else(|| error(line, "invalid seed"))?;
+
+        let (line, names) = field("quirks")?;
+        let mut quirks = Quirks::NONE;
+        for name in names {
+            if !quirks.set_flag(&name, true) {
+                return Err(error(line, &format!("unknown quirk '{}'", name)));
+            }
+        }
+
+        let (timing, ips) = if version == 1 {
+            (Timing::Fixed, DEFAULT_IPS)
+        } else {
+            let (line, words) = field("timing")?;
+            let timing = words.first().and_then(|name| Timing::from_name(name))
+                .ok_or_else(|| error(line, "invalid timing"))?;
+            let ips = match words.get(1).filter(|_| version >= 3) {
+                Some(ips) => ips.parse().ok().filter(|&ips| ips > 0)
+                    .ok_or_else(|| error(line, "invalid instructions per second"))?,
+                None => DEFAULT_IPS,
+            };
+            (timing, ips)
+        };
+
+        let schedule = if version < 4 {
+            Schedule::Dt
+        } else {
+            let (line, name) = field("schedule")?;
+            name.first().and_then(|name| Schedule::from_name(name))
+                .ok_or_else(|| error(line, "invalid schedule"))?
+        };
+
+        let (line, frames) = field("frames")?;
+        let frames = frames.first().and_then(|frames| frames.parse().ok())
+            .ok_or_else(|| error(line, "invalid frame count"))?;
+
+        let events = parse_events(lines)?;
+
+        Ok(Movie { rom_hash, seed, quirks, timing, ips, schedule, frames, events })
+    }
+
+    /// Fails if `rom` is not the rom the movie was recorded on.
+    pub fn check_rom(&self, rom: &[u8]) -> Result<(), MovieError> {
+        let actual = rom_hash(rom);
+        if actual != self.rom_hash {
+            return Err(MovieError::RomMismatch { expected: self.rom_hash, actual });
+        }
+        Ok(())
+    }
+
+    /// Boots `rom` with the movie's quirks, timing, speed and seed.
+    pub fn boot(&self, rom: &[u8]) -> Result<System, EmulatorError> {
+        let mut system = System::with_quirks(rom, self.quirks)?;
+        system.set_timing(self.timing);
+        system.set_ips(self.ips);
+        system.set_seed(self.seed);
+        Ok(system)
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rom {:016x}", self.rom_hash)?;
+        writeln!(f, "seed {}", self.seed)?;
+        write!(f, "quirks")?;
+        for (name, enabled) in self.quirks.flags() {
+            if enabled { write!(f, " {}", name)?; }
+        }
+        writeln!(f)?;
+        match self.timing {
+            Timing::Fixed => writeln!(f, "timing {} {}", self.timing.name(), self.ips)?,
+            Timing::Vip => writeln!(f, "timing {}", self.timing.name())?,
+        }
+        writeln!(f, "schedule {}", self.schedule.name())?;
+        writeln!(f, "frames {}", self.frames)?;
+        for event in &self.events {
+            writeln!(f, "{} {:X} {}", event.frame, event.key.get(), event.pressed as u8)?;
+        }
+        Ok(())
+    }
+}
+
+/// Logs keypad transitions as they are passed to the system.
+#[derive(Debug)]
+pub struct Recorder {
+    movie: Movie,
+}
+
+impl Recorder {
+    /// Starts a movie for `system`, which should have just been booted from
+    /// `rom` and given a seed, running its frames by `schedule`.
+    pub fn new(rom: &[u8], system: &System, schedule: Schedule) -> Self {
+        Recorder {
+            movie: Movie {
+                rom_hash: rom_hash(rom),
+                seed: system.seed(),
+                quirks: system.quirks(),
+                timing: system.timing(),
+                ips: system.ips(),
+                schedule,
+                frames: 0,
+                events: Vec::new(),
+            },
+        }
+    }
+
+    pub fn update_keypad(&mut self, system: &mut System, key: Key, pressed: bool) {
+        self.movie.events.push(MovieEvent { frame: self.movie.frames, key, pressed });
+        system.update_keypad(key, pressed);
+    }
+
+    pub fn run_frame(&mut self, system: &mut System) -> Result<(), EmulatorError> {
+        let frame = self.movie.frames;
+        self.movie.frames += 1;
+        self.movie.schedule.run_frame(system, frame)
+    }
+
+    pub fn finish(self) -> Movie { self.movie }
+}
+
+/// Feeds a movie's input back into a system.
+#[derive(Debug)]
+pub struct Player {
+    movie: Movie,
+    frame: u64,
+    next_event: usize,
+}
+
+impl Player {
+    pub fn new(movie: Movie) -> Self {
+        Player { movie, frame: 0, next_event: 0 }
+    }
+
+    pub fn is_finished(&self) -> bool { self.frame >= self.movie.frames }
+    pub fn frame(&self) -> u64 { self.frame }
+
+    /// Applies this frame's keypad transitions and runs the frame. Does
+    /// nothing once the movie has finished.
+    pub fn run_frame(&mut self, system: &mut System) -> Result<(), EmulatorError> {
+        if self.is_finished() { return Ok(()); }
+
+        while let Some(event) = self.movie.events.get(self.next_event).filter(|event| event.frame <= self.frame) {
+            system.update_keypad(event.key, event.pressed);
+            self.next_event += 1;
+        }
+
+        let frame = self.frame;
+        self.frame += 1;
+        self.movie.schedule.run_frame(system, frame)
+    }
+
+    /// Plays the rest of the movie without a window.
+    pub fn run_to_end(&mut self, system: &mut System) -> Result<(), EmulatorError> {
+        while !self.is_finished() {
+            self.run_frame(system)?;
+        }
+        Ok(())
+    }
+}
+
+// http://www.isthe.com/chongo/tech/comp/fnv/
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF29CE484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001B3))
+}
@@ -0,0 +1,851 @@
+//! Compiler for [Octo](https://github.com/JohnEarnest/Octo) source, the
+//! assembly language most modern CHIP-8 programs are written in.
+//!
+//! ```text
+//! :alias x v0
+//! :const SPEED 2
+//! : ball  0x60 0xF0 0xF0 0x60
+//! : main
+//!     loop
+//!         i := ball
+//!         sprite x v1 4
+//!         x += SPEED
+//!         if x == 60 then x := 0
+//!     again
+//! ```
+//!
+//! Supported are labels, `:alias`, `:const`, `:macro`, `:calc`, `:byte`,
+//! `:pointer`, `:org`, `:unpack`, `:next` and `:call`, every instruction
+//! including the SUPER-CHIP and XO-CHIP ones, `if ... then`,
+//! `if ... begin ... else ... end`, and `loop ... while ... again`.
+//! `:calc` expressions are evaluated right to left with no operator
+//! precedence, as in Octo. `:breakpoint` and `:monitor` are accepted and
+//! ignored.
+//!
+//! The program starts with a jump to `main`, which has to be defined.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::source_map::SourceMap;
+
+const ROM_START: usize = 0x200;
+// Stops macros that expand into themselves.
+const MAX_EXPANSIONS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OctoError {
+    /// 1 based.
+    pub line: usize,
+    /// 1 based, in characters.
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for OctoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for OctoError {}
+
+/// Compiles Octo source into a rom to be loaded at 0x200.
+pub fn compile(source: &str) -> Result<Vec<u8>, OctoError> {
+    compile_with_map(source, "<source>").map(|(rom, _)| rom)
+}
+
+/// Like [`compile`], also returning which line of `file` every byte came
+/// from. Code from a macro maps to the macro's own lines, and the jump to
+/// `main` to none.
+pub fn compile_with_map(source: &str, file: &str) -> Result<(Vec<u8>, SourceMap), OctoError> {
+    let mut compiler = Compiler::new(tokenize(source));
+    compiler.run()?;
+
+    let mut map = SourceMap::new();
+    for &(addr, line) in &compiler.lines {
+        map.add(addr, 1, file, line);
+    }
+    Ok((compiler.rom, map))
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: impl Into<String>) -> OctoError {
+        OctoError { line: self.line, column: self.column, message: message.into() }
+    }
+}
+
+/// Splits on whitespace, dropping `#` comments.
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let mut start = None;
+        for (column, c) in line.chars().chain(Some(' ')).enumerate() {
+            match (c.is_whitespace(), start) {
+                (false, None) if c == '#' => break,
+                (false, None) => start = Some(column),
+                (true, Some(from)) => {
+                    let text = line.chars().skip(from).take(column - from).collect();
+                    tokens.push(Token { text, line: i + 1, column: from + 1 });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(from) = start {
+            tokens.push(Token { text: line.chars().skip(from).collect(), line: i + 1, column: from + 1 });
+        }
+    }
+    tokens
+}
+
+#[derive(Debug)]
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+    calls: usize,
+}
+
+/// Where an address has to be written once its label is defined.
+#[derive(Debug, Clone, Copy)]
+enum Patch {
+    /// Low 12 bits of the instruction at the address.
+    Addr12,
+    /// A whole word at the address.
+    Addr16,
+    /// The immediates of the `v0 :=` `v1 :=` pair at the address, with a
+    /// nibble above the address, or `None` for `:unpack long`.
+    Unpack(Option<u8>),
+}
+
+#[derive(Debug)]
+struct Fixup {
+    addr: usize,
+    patch: Patch,
+    token: Token,
+}
+
+/// An `if ... begin` waiting for its `else` or `end`, or a `loop` for its `again`.
+#[derive(Debug)]
+enum Block {
+    If { token: Token, jump: usize, has_else: bool },
+    Loop { token: Token, start: usize, breaks: Vec<usize> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compare {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Number(u8),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Condition {
+    Compare(u8, Compare, Operand),
+    /// Register holding the key, and whether the key has to be pressed.
+    Key(u8, bool),
+}
+
+struct Compiler {
+    /// Remaining tokens, last one first.
+    tokens: Vec<Token>,
+    /// The last token taken, for errors at the end of the source.
+    last: Token,
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u16>,
+    macros: HashMap<String, Macro>,
+    fixups: HashMap<String, Vec<Fixup>>,
+    blocks: Vec<Block>,
+    expansions: usize,
+    /// The line of the statement being compiled, 0 before the first.
+    line: usize,
+    /// The line every byte was emitted by, in order.
+    lines: Vec<(u16, usize)>,
+}
+
+impl Compiler {
+    fn new(mut tokens: Vec<Token>) -> Self {
+        tokens.reverse();
+        Compiler {
+            tokens,
+            last: Token { text: String::new(), line: 1, column: 1 },
+            rom: Vec::new(),
+            here: ROM_START,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: HashMap::new(),
+            blocks: Vec::new(),
+            expansions: 0,
+            line: 0,
+            lines: Vec::new(),
+        }
+    }
+
+    fn run(&mut self) -> Result<(), OctoError> {
+        // The jump to main, patched at the end.
+        self.word(0x1000)?;
+
+        while let Some(token) = self.tokens.pop() {
+            self.last = token.clone();
+            self.line = token.line;
+            self.statement(token)?;
+        }
+
+        if let Some(block) = self.blocks.pop() {
+            return Err(match block {
+                Block::If { token, .. } => token.error("'if' without 'end'"),
+                Block::Loop { token, .. } => token.error("'loop' without 'again'"),
+            });
+        }
+        if let Some(fixup) = self.fixups.values().flatten().min_by_key(|fixup| (fixup.token.line, fixup.token.column)) {
+            return Err(fixup.token.error(format!("undefined name '{}'", fixup.token.text)));
+        }
+
+        let main = *self.labels.get("main")
+            .ok_or_else(|| OctoError { line: 1, column: 1, message: "this program is missing a 'main' label".to_string() })?;
+        self.patch(ROM_START, Patch::Addr12, main, &self.last.clone())
+    }
+
+    fn next(&mut self) -> Result<Token, OctoError> {
+        let token = self.tokens.pop().ok_or_else(|| self.last.error("unexpected end of file"))?;
+        self.last = token.clone();
+        Ok(token)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, OctoError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(token.error(format!("expected '{}', found '{}'", text, token.text)));
+        }
+        Ok(token)
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.last().is_some_and(|token| token.text == text)
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), OctoError> {
+        if let Some(x) = self.register(&token.text) {
+            return self.register_statement(x);
+        }
+        if let Some(value) = number(&token.text) {
+            return self.byte(byte(value).ok_or_else(|| token.error("byte out of range"))?);
+        }
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                self.define_label(name, self.here)?;
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.define_label(name, self.here + 1)?;
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.next()?;
+                let x = self.register(&register.text).ok_or_else(|| register.error("expected a register"))?;
+                self.aliases.insert(name.text, x);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.next()?;
+                let value = self.constant(&value)?;
+                self.define_constant(name, value)?;
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                self.define_constant(name, value)?;
+            }
+            ":macro" => self.define_macro()?,
+            ":byte" => {
+                let (token, value) = self.value_or_calc()?;
+                self.byte(byte(value as i64).ok_or_else(|| token.error("byte out of range"))?)?;
+            }
+            ":pointer" => {
+                let target = self.next()?;
+                self.address(&target, Patch::Addr16, 2)?;
+            }
+            ":call" => {
+                let target = self.next()?;
+                self.address(&target, Patch::Addr12, 0x2000)?;
+            }
+            ":org" => {
+                let (token, value) = self.value_or_calc()?;
+                if !(ROM_START as f64..=0xFFFF as f64).contains(&value) {
+                    return Err(token.error("address out of range"));
+                }
+                self.here = value as usize;
+            }
+            ":unpack" => {
+                let nibble = self.next()?;
+                let nibble = match nibble.text.as_str() {
+                    "long" => None,
+                    _ => Some(self.constant(&nibble)
+                        .ok().filter(|value| (0.0..16.0).contains(value))
+                        .ok_or_else(|| nibble.error("expected a nibble or 'long'"))? as u8),
+                };
+                let target = self.next()?;
+                let addr = self.here;
+                self.word(0x6000)?;
+                self.word(0x6100)?;
+                self.resolve(&target, addr, Patch::Unpack(nibble))?;
+            }
+            ":breakpoint" => { self.name()?; }
+            ":monitor" => { self.next()?; self.next()?; }
+
+            "return" | ";" => self.word(0x00EE)?,
+            "clear" => self.word(0x00E0)?,
+            "scroll-down" => { let n = self.nibble()?; self.word(0x00C0 | n)?; }
+            "scroll-up" => { let n = self.nibble()?; self.word(0x00D0 | n)?; }
+            "scroll-right" => self.word(0x00FB)?,
+            "scroll-left" => self.word(0x00FC)?,
+            "exit" => self.word(0x00FD)?,
+            "lores" => self.word(0x00FE)?,
+            "hires" => self.word(0x00FF)?,
+            "audio" => self.word(0xF002)?,
+            "plane" => { let n = self.nibble()?; self.word(0xF001 | n << 8)?; }
+            "bcd" => { let x = self.expect_register()?; self.word(0xF033 | x << 8)?; }
+            "saveflags" => { let x = self.expect_register()?; self.word(0xF075 | x << 8)?; }
+            "loadflags" => { let x = self.expect_register()?; self.word(0xF085 | x << 8)?; }
+            "save" | "load" => {
+                let x = self.expect_register()?;
+                if self.peek_is("-") {
+                    self.next()?;
+                    let y = self.expect_register()?;
+                    let op = if token.text == "save" { 0x5002 } else { 0x5003 };
+                    self.word(op | x << 8 | y << 4)?;
+                } else {
+                    let op = if token.text == "save" { 0xF055 } else { 0xF065 };
+                    self.word(op | x << 8)?;
+                }
+            }
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let n = self.nibble()?;
+                self.word(0xD000 | x << 8 | y << 4 | n)?;
+            }
+            "jump" => { let target = self.next()?; self.address(&target, Patch::Addr12, 0x1000)?; }
+            "jump0" => { let target = self.next()?; self.address(&target, Patch::Addr12, 0xB000)?; }
+            "native" => { let target = self.next()?; self.address(&target, Patch::Addr12, 0x0000)?; }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.expect_register()?;
+                let op = match token.text.as_str() { "delay" => 0xF015, "buzzer" => 0xF018, _ => 0xF03A };
+                self.word(op | x << 8)?;
+            }
+            "i" => self.index_statement()?,
+
+            "if" => {
+                let condition = self.condition()?;
+                let keyword = self.next()?;
+                match keyword.text.as_str() {
+                    "then" => self.skip_unless(condition)?,
+                    "begin" => {
+                        self.skip_unless(negate(condition))?;
+                        let jump = self.here;
+                        self.word(0x1000)?;
+                        self.blocks.push(Block::If { token, jump, has_else: false });
+                    }
+                    _ => return Err(keyword.error("expected 'then' or 'begin'")),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If { token: if_token, jump, has_else: false }) => {
+                    let end = self.here;
+                    self.word(0x1000)?;
+                    self.patch(jump, Patch::Addr12, self.here, &token)?;
+                    self.blocks.push(Block::If { token: if_token, jump: end, has_else: true });
+                }
+                _ => return Err(token.error("'else' without 'if ... begin'")),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. }) => self.patch(jump, Patch::Addr12, self.here, &token)?,
+                _ => return Err(token.error("'end' without 'if ... begin'")),
+            },
+            "loop" => self.blocks.push(Block::Loop { token, start: self.here, breaks: Vec::new() }),
+            "while" => {
+                let condition = self.condition()?;
+                self.skip_unless(negate(condition))?;
+                let jump = self.here;
+                self.word(0x1000)?;
+                match self.blocks.iter_mut().rev().find(|block| matches!(block, Block::Loop { .. })) {
+                    Some(Block::Loop { breaks, .. }) => breaks.push(jump),
+                    _ => return Err(token.error("'while' outside of a loop")),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, breaks, .. }) => {
+                    self.word(0x1000 | address12(start, &token)?)?;
+                    for jump in breaks {
+                        self.patch(jump, Patch::Addr12, self.here, &token)?;
+                    }
+                }
+                _ => return Err(token.error("'again' without 'loop'")),
+            },
+
+            name if self.macros.contains_key(name) => self.expand(token)?,
+            name if is_name(name) => self.address(&token, Patch::Addr12, 0x2000)?,
+            _ => return Err(token.error(format!("unexpected '{}'", token.text))),
+        }
+        Ok(())
+    }
+
+    /// `vx := ...`, `vx += ...` and the other register operators.
+    fn register_statement(&mut self, x: u16) -> Result<(), OctoError> {
+        let op = self.next()?;
+        let rhs = self.next()?;
+
+        if let Some(y) = self.register(&rhs.text) {
+            let code = match op.text.as_str() {
+                ":=" => 0x0, "|=" => 0x1, "&=" => 0x2, "^=" => 0x3, "+=" => 0x4,
+                "-=" => 0x5, ">>=" => 0x6, "=-" => 0x7, "<<=" => 0xE,
+                _ => return Err(op.error(format!("unknown operator '{}'", op.text))),
+            };
+            return self.word(0x8000 | x << 8 | y << 4 | code);
+        }
+
+        match (op.text.as_str(), rhs.text.as_str()) {
+            (":=", "key") => self.word(0xF00A | x << 8),
+            (":=", "delay") => self.word(0xF007 | x << 8),
+            (":=", "random") => {
+                let mask = self.byte_value()?;
+                self.word(0xC000 | x << 8 | mask as u16)
+            }
+            (":=" | "+=" | "-=", _) => {
+                let value = self.constant(&rhs)?;
+                let value = byte(value as i64).ok_or_else(|| rhs.error("byte out of range"))?;
+                match op.text.as_str() {
+                    ":=" => self.word(0x6000 | x << 8 | value as u16),
+                    "+=" => self.word(0x7000 | x << 8 | value as u16),
+                    _ => self.word(0x7000 | x << 8 | value.wrapping_neg() as u16),
+                }
+            }
+            _ => Err(op.error(format!("can't use '{}' with '{}'", op.text, rhs.text))),
+        }
+    }
+
+    /// `i := ...` and `i += vx`.
+    fn index_statement(&mut self) -> Result<(), OctoError> {
+        let op = self.next()?;
+        let rhs = self.next()?;
+
+        match (op.text.as_str(), rhs.text.as_str()) {
+            ("+=", _) => {
+                let x = self.register(&rhs.text).ok_or_else(|| rhs.error("expected a register"))?;
+                self.word(0xF01E | x << 8)
+            }
+            (":=", "hex") => { let x = self.expect_register()?; self.word(0xF029 | x << 8) }
+            (":=", "bighex") => { let x = self.expect_register()?; self.word(0xF030 | x << 8) }
+            (":=", "long") => {
+                let target = self.next()?;
+                self.word(0xF000)?;
+                self.address(&target, Patch::Addr16, 0)
+            }
+            (":=", _) => self.address(&rhs, Patch::Addr12, 0xA000),
+            _ => Err(op.error(format!("can't use '{}' with i", op.text))),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, OctoError> {
+        let x = self.expect_register()? as u8;
+        let op = self.next()?;
+        let compare = match op.text.as_str() {
+            "key" => return Ok(Condition::Key(x, true)),
+            "-key" => return Ok(Condition::Key(x, false)),
+            "==" => Compare::Eq,
+            "!=" => Compare::Ne,
+            "<" => Compare::Lt,
+            ">" => Compare::Gt,
+            "<=" => Compare::Le,
+            ">=" => Compare::Ge,
+            _ => return Err(op.error(format!("unknown comparison '{}'", op.text))),
+        };
+
+        let rhs = self.next()?;
+        let rhs = match self.register(&rhs.text) {
+            Some(y) => Operand::Register(y as u8),
+            None => {
+                let value = self.constant(&rhs)?;
+                Operand::Number(byte(value as i64).ok_or_else(|| rhs.error("byte out of range"))?)
+            }
+        };
+        if x == 0xF && !matches!(compare, Compare::Eq | Compare::Ne) {
+            return Err(op.error("comparisons use vf, so it can't be compared"));
+        }
+        Ok(Condition::Compare(x, compare, rhs))
+    }
+
+    /// Emits code that skips the next instruction unless the condition holds.
+    fn skip_unless(&mut self, condition: Condition) -> Result<(), OctoError> {
+        let (x, compare, rhs) = match condition {
+            Condition::Key(x, true) => return self.word(0xE0A1 | (x as u16) << 8),
+            Condition::Key(x, false) => return self.word(0xE09E | (x as u16) << 8),
+            Condition::Compare(x, compare, rhs) => (x as u16, compare, rhs),
+        };
+
+        match (compare, rhs) {
+            (Compare::Eq, Operand::Number(n)) => self.word(0x4000 | x << 8 | n as u16),
+            (Compare::Ne, Operand::Number(n)) => self.word(0x3000 | x << 8 | n as u16),
+            (Compare::Eq, Operand::Register(y)) => self.word(0x9000 | x << 8 | (y as u16) << 4),
+            (Compare::Ne, Operand::Register(y)) => self.word(0x5000 | x << 8 | (y as u16) << 4),
+            _ => {
+                // vf := rhs, then subtract so the carry flag answers the
+                // question: vf = vx >= rhs, or vf = rhs >= vx.
+                match rhs {
+                    Operand::Register(y) => self.word(0x8F00 | (y as u16) << 4)?,
+                    Operand::Number(n) => self.word(0x6F00 | n as u16)?,
+                }
+                let (subtract, holds_when_set) = match compare {
+                    Compare::Lt => (0x8F07, false),
+                    Compare::Ge => (0x8F07, true),
+                    Compare::Gt => (0x8F05, false),
+                    _ => (0x8F05, true),
+                };
+                self.word(subtract | x << 4)?;
+                // Skip when vf shows the condition doesn't hold.
+                self.word(if holds_when_set { 0x3F00 } else { 0x3F01 })
+            }
+        }
+    }
+
+    fn define_macro(&mut self) -> Result<(), OctoError> {
+        let name = self.name()?;
+        let mut args = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "{" { break; }
+            args.push(token.text);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name.text, Macro { args, body, calls: 0 });
+        Ok(())
+    }
+
+    fn expand(&mut self, token: Token) -> Result<(), OctoError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(token.error("too many macro expansions, is a macro using itself?"));
+        }
+
+        let count = self.macros[&token.text].args.len();
+        let mut values = HashMap::new();
+        for i in 0..count {
+            let value = self.next()?;
+            values.insert(self.macros[&token.text].args[i].clone(), value.text);
+        }
+
+        let mac = self.macros.get_mut(&token.text).unwrap();
+        values.insert("CALLS".to_string(), mac.calls.to_string());
+        mac.calls += 1;
+
+        for body in mac.body.iter().rev() {
+            let text = values.get(&body.text).cloned().unwrap_or_else(|| body.text.clone());
+            self.tokens.push(Token { text, ..body.clone() });
+        }
+        Ok(())
+    }
+
+    /// `{ expression }`, evaluated right to left.
+    fn calc(&mut self) -> Result<f64, OctoError> {
+        self.expect("{")?;
+        let value = self.expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn expression(&mut self) -> Result<f64, OctoError> {
+        let lhs = self.term()?;
+        let Some(op) = self.tokens.last().map(|token| token.text.clone()) else { return Ok(lhs) };
+
+        let apply: fn(f64, f64) -> f64 = match op.as_str() {
+            "+" => |a, b| a + b,
+            "-" => |a, b| a - b,
+            "*" => |a, b| a * b,
+            "/" => |a, b| a / b,
+            "%" => |a, b| a % b,
+            "&" => |a, b| (a as i64 & b as i64) as f64,
+            "|" => |a, b| (a as i64 | b as i64) as f64,
+            "^" => |a, b| (a as i64 ^ b as i64) as f64,
+            "<<" => |a, b| ((a as i64) << b as i64) as f64,
+            ">>" => |a, b| ((a as i64) >> b as i64) as f64,
+            "pow" => f64::powf,
+            "min" => f64::min,
+            "max" => f64::max,
+            "<" => |a, b| (a < b) as u8 as f64,
+            ">" => |a, b| (a > b) as u8 as f64,
+            "<=" => |a, b| (a <= b) as u8 as f64,
+            ">=" => |a, b| (a >= b) as u8 as f64,
+            "==" => |a, b| (a == b) as u8 as f64,
+            "!=" => |a, b| (a != b) as u8 as f64,
+            _ => return Ok(lhs),
+        };
+        self.next()?;
+        let rhs = self.expression()?;
+        Ok(apply(lhs, rhs))
+    }
+
+    fn term(&mut self) -> Result<f64, OctoError> {
+        let token = self.next()?;
+        let unary: fn(f64) -> f64 = match token.text.as_str() {
+            "(" => {
+                let value = self.expression()?;
+                self.expect(")")?;
+                return Ok(value);
+            }
+            "-" => |a| -a,
+            "~" => |a| !(a as i64) as f64,
+            "!" => |a| (a == 0.0) as u8 as f64,
+            "abs" => f64::abs,
+            "sqrt" => f64::sqrt,
+            "floor" => f64::floor,
+            "ceil" => f64::ceil,
+            "sin" => f64::sin,
+            "cos" => f64::cos,
+            _ => return self.constant(&token),
+        };
+        Ok(unary(self.term()?))
+    }
+
+    /// A number, constant or already defined label.
+    fn constant(&self, token: &Token) -> Result<f64, OctoError> {
+        match token.text.as_str() {
+            "HERE" => return Ok(self.here as f64),
+            "PI" => return Ok(std::f64::consts::PI),
+            "E" => return Ok(std::f64::consts::E),
+            _ => {}
+        }
+        if let Some(value) = number(&token.text) {
+            return Ok(value as f64);
+        }
+        if let Some(&value) = self.constants.get(&token.text) {
+            return Ok(value);
+        }
+        if let Some(&addr) = self.labels.get(&token.text) {
+            return Ok(addr as f64);
+        }
+        Err(token.error(format!("undefined name '{}'", token.text)))
+    }
+
+    fn value_or_calc(&mut self) -> Result<(Token, f64), OctoError> {
+        let token = self.tokens.last().cloned().ok_or_else(|| self.last.error("unexpected end of file"))?;
+        let value = if token.text == "{" {
+            self.calc()?
+        } else {
+            let token = self.next()?;
+            self.constant(&token)?
+        };
+        Ok((token, value))
+    }
+
+    fn byte_value(&mut self) -> Result<u8, OctoError> {
+        let token = self.next()?;
+        let value = self.constant(&token)?;
+        byte(value as i64).ok_or_else(|| token.error("byte out of range"))
+    }
+
+    fn nibble(&mut self) -> Result<u16, OctoError> {
+        let token = self.next()?;
+        let value = self.constant(&token)?;
+        if !(0.0..16.0).contains(&value) {
+            return Err(token.error("expected a number from 0 to 15"));
+        }
+        Ok(value as u16)
+    }
+
+    fn register(&self, text: &str) -> Option<u16> {
+        if let Some(&x) = self.aliases.get(text) {
+            return Some(x);
+        }
+        let digit = text.strip_prefix(['v', 'V'])?;
+        if digit.len() != 1 { return None; }
+        u16::from_str_radix(digit, 16).ok()
+    }
+
+    fn expect_register(&mut self) -> Result<u16, OctoError> {
+        let token = self.next()?;
+        self.register(&token.text).ok_or_else(|| token.error(format!("expected a register, found '{}'", token.text)))
+    }
+
+    fn name(&mut self) -> Result<Token, OctoError> {
+        let token = self.next()?;
+        if !is_name(&token.text) || self.register(&token.text).is_some() {
+            return Err(token.error(format!("invalid name '{}'", token.text)));
+        }
+        Ok(token)
+    }
+
+    fn define_label(&mut self, name: Token, addr: usize) -> Result<(), OctoError> {
+        if self.labels.contains_key(&name.text) || self.constants.contains_key(&name.text) {
+            return Err(name.error(format!("'{}' is already defined", name.text)));
+        }
+        for fixup in self.fixups.remove(&name.text).unwrap_or_default() {
+            self.patch(fixup.addr, fixup.patch, addr, &fixup.token)?;
+        }
+        self.labels.insert(name.text, addr);
+        Ok(())
+    }
+
+    fn define_constant(&mut self, name: Token, value: f64) -> Result<(), OctoError> {
+        if self.labels.contains_key(&name.text) {
+            return Err(name.error(format!("'{}' is already a label", name.text)));
+        }
+        self.constants.insert(name.text, value);
+        Ok(())
+    }
+
+    /// Emits `opcode` with the address `target` refers to, patching it in
+    /// later if it's a label that hasn't been defined yet. `Addr16` emits
+    /// only the address.
+    fn address(&mut self, target: &Token, patch: Patch, opcode: u16) -> Result<(), OctoError> {
+        let addr = self.here;
+        match patch {
+            Patch::Addr16 => self.word(0)?,
+            _ => self.word(opcode)?,
+        }
+        self.resolve(target, addr, patch)
+    }
+
+    fn resolve(&mut self, target: &Token, addr: usize, patch: Patch) -> Result<(), OctoError> {
+        if self.labels.contains_key(&target.text) || self.constants.contains_key(&target.text) || number(&target.text).is_some() {
+            let value = self.constant(target)?;
+            return self.patch(addr, patch, value as usize, target);
+        }
+        if !is_name(&target.text) {
+            return Err(target.error(format!("expected an address, found '{}'", target.text)));
+        }
+        self.fixups.entry(target.text.clone()).or_default().push(Fixup { addr, patch, token: target.clone() });
+        Ok(())
+    }
+
+    fn patch(&mut self, addr: usize, patch: Patch, value: usize, token: &Token) -> Result<(), OctoError> {
+        let offset = addr - ROM_START;
+        match patch {
+            Patch::Addr12 => {
+                let value = address12(value, token)?;
+                self.rom[offset] = (self.rom[offset] & 0xF0) | (value >> 8) as u8;
+                self.rom[offset + 1] = value as u8;
+            }
+            Patch::Addr16 => {
+                if value > 0xFFFF { return Err(token.error("address out of range")); }
+                self.rom[offset..offset + 2].copy_from_slice(&(value as u16).to_be_bytes());
+            }
+            Patch::Unpack(nibble) => {
+                let value = match nibble {
+                    Some(nibble) => (nibble as usize) << 12 | address12(value, token)? as usize,
+                    None if value <= 0xFFFF => value,
+                    None => return Err(token.error("address out of range")),
+                };
+                self.rom[offset + 1] = (value >> 8) as u8;
+                self.rom[offset + 3] = value as u8;
+            }
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self, value: u8) -> Result<(), OctoError> {
+        if self.here > 0xFFFF {
+            return Err(self.last.error("program runs past the end of memory"));
+        }
+        let offset = self.here - ROM_START;
+        if self.rom.len() <= offset {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = value;
+        if self.line > 0 {
+            self.lines.push((self.here as u16, self.line));
+        }
+        self.here += 1;
+        Ok(())
+    }
+
+    fn word(&mut self, value: u16) -> Result<(), OctoError> {
+        let [high, low] = value.to_be_bytes();
+        self.byte(high)?;
+        self.byte(low)
+    }
+}
+
+fn negate(condition: Condition) -> Condition {
+    match condition {
+        Condition::Key(x, pressed) => Condition::Key(x, !pressed),
+        Condition::Compare(x, compare, rhs) => Condition::Compare(x, match compare {
+            Compare::Eq => Compare::Ne,
+            Compare::Ne => Compare::Eq,
+            Compare::Lt => Compare::Ge,
+            Compare::Ge => Compare::Lt,
+            Compare::Gt => Compare::Le,
+            Compare::Le => Compare::Gt,
+        }, rhs),
+    }
+}
+
+fn address12(value: usize, token: &Token) -> Result<u16, OctoError> {
+    if value > 0xFFF {
+        return Err(token.error(format!("address {:#X} is out of range, try 'i := long'", value)));
+    }
+    Ok(value as u16)
+}
+
+/// Decimal, `0x` hex or `0b` binary, optionally negative.
+fn number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn byte(value: i64) -> Option<u8> {
+    (-0x80..=0xFF).contains(&value).then_some(value as u8)
+}
+
+fn is_name(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
@@ -1,46 +1,201 @@
-#[derive(Debug)]
-pub struct Screen {
-    pixels: [[bool; 32]; 64],
-}
-
-impl Screen {
-    pub fn new() -> Self {
-        Screen {
-            pixels: [[false; 32]; 64],
-        }
-    }
-
-    pub fn draw(&mut self, x: u8, y: u8, sprite: &[u8]) -> bool {
-        let mut collision = false;
-
-        for (i, byte) in sprite.iter().enumerate() {
-            for j in 0..8 {
-                let bit = (byte >> (7 - j)) & 1;
-                let x = (x as usize + j) % 64;
-                let y = (y as usize + i) % 32;
-
-                if bit == 1 && self.pixels[x][y] {
-                    collision = true;
-                }
-
-                self.pixels[x][y] ^= bit == 1;
-            }
-        }
-
-        collision
-    }
-
-    pub fn clear(&mut self) {
-        self.pixels = [[false; 32]; 64];
-    }
-
-    pub fn get_pixels(&self) -> &[[bool; 32]; 64] {
-        &self.pixels
-    }
-}
-
-impl Default for Screen {
-    fn default() -> Self {
-        Self::new()
-    }
-}
+use crate::state::{Reader, StateError, Writer};
+
+const MAX_WIDTH: usize = 128;
+const MAX_HEIGHT: usize = 64;
+
+/// Each pixel holds one bit per plane, so with both XO-CHIP planes in use a
+/// pixel is one of four colours: 0 (off), 1 (plane 1), 2 (plane 2) or 3 (both).
+#[derive(Debug)]
+pub struct Screen {
+    pixels: [[u8; MAX_HEIGHT]; MAX_WIDTH],
+    hires: bool,
+    planes: u8,
+}
+
+impl Screen {
+    pub fn new() -> Self {
+        Screen {
+            pixels: [[0; MAX_HEIGHT]; MAX_WIDTH],
+            hires: false,
+            planes: 1,
+        }
+    }
+
+    /// 64 pixels in low resolution, 128 in high resolution.
+    pub fn width(&self) -> usize { if self.hires { MAX_WIDTH } else { MAX_WIDTH / 2 } }
+    /// 32 pixels in low resolution, 64 in high resolution.
+    pub fn height(&self) -> usize { if self.hires { MAX_HEIGHT } else { MAX_HEIGHT / 2 } }
+
+    pub fn is_hires(&self) -> bool { self.hires }
+
+    /// Switches resolution, which also clears the screen.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels = [[0; MAX_HEIGHT]; MAX_WIDTH];
+    }
+
+    /// Bitmask of the planes that drawing, clearing and scrolling apply to.
+    pub fn planes(&self) -> u8 { self.planes }
+
+    pub fn set_planes(&mut self, planes: u8) {
+        self.planes = planes & 0b11;
+    }
+
+    /// Number of selected planes, each of which takes its own copy of a sprite.
+    pub fn plane_count(&self) -> usize { self.planes.count_ones() as usize }
+
+    /// XORs an 8 pixel wide sprite onto each selected plane, returning true if
+    /// any pixel was erased. With two planes selected, `sprite` holds the data
+    /// for plane 1 followed by the data for plane 2. The starting position
+    /// always wraps; with `clip` set, the rest of the sprite is cut off at the
+    /// edge instead of wrapping around.
+    pub fn draw(&mut self, x: u8, y: u8, sprite: &[u8], clip: bool) -> bool {
+        let mut collision = false;
+
+        for (plane, sprite) in self.plane_sprites(sprite) {
+            for (i, &byte) in sprite.iter().enumerate() {
+                collision |= self.draw_row(plane, x, y, i, (byte as u16) << 8, 8, clip);
+            }
+        }
+
+        collision
+    }
+
+    /// Like [`Screen::draw`], but for a 16x16 sprite stored as 2 bytes per row.
+    pub fn draw_wide(&mut self, x: u8, y: u8, sprite: &[u8], clip: bool) -> bool {
+        let mut collision = false;
+
+        for (plane, sprite) in self.plane_sprites(sprite) {
+            for (i, row) in sprite.chunks(2).enumerate() {
+                let bits = ((row[0] as u16) << 8) | row.get(1).copied().unwrap_or(0) as u16;
+                collision |= self.draw_row(plane, x, y, i, bits, 16, clip);
+            }
+        }
+
+        collision
+    }
+
+    fn plane_sprites<'a>(&self, sprite: &'a [u8]) -> Vec<(u8, &'a [u8])> {
+        let count = self.plane_count().max(1);
+        let len = sprite.len() / count;
+        [1, 2].into_iter()
+            .filter(|plane| self.planes & plane != 0)
+            .zip(sprite.chunks(len.max(1)))
+            .collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_row(&mut self, plane: u8, x: u8, y: u8, i: usize, bits: u16, len: usize, clip: bool) -> bool {
+        let (width, height) = (self.width(), self.height());
+        let x0 = x as usize % width;
+        let y0 = y as usize % height;
+        let mut collision = false;
+
+        for j in 0..len {
+            let bit = (bits >> (15 - j)) & 1;
+            if clip && (x0 + j >= width || y0 + i >= height) { continue; }
+            let x = (x0 + j) % width;
+            let y = (y0 + i) % height;
+
+            if bit == 1 {
+                if self.pixels[x][y] & plane != 0 {
+                    collision = true;
+                }
+
+                self.pixels[x][y] ^= plane;
+            }
+        }
+
+        collision
+    }
+
+    pub fn scroll_down(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        let n = n.min(height);
+        for x in 0..width {
+            for y in (0..height).rev() {
+                let from = if y >= n { self.pixels[x][y - n] } else { 0 };
+                self.set_selected(x, y, from);
+            }
+        }
+    }
+
+    /// XO-CHIP's `00DN`.
+    pub fn scroll_up(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        let n = n.min(height);
+        for x in 0..width {
+            for y in 0..height {
+                let from = if y + n < height { self.pixels[x][y + n] } else { 0 };
+                self.set_selected(x, y, from);
+            }
+        }
+    }
+
+    pub fn scroll_right(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        let n = n.min(width);
+        for x in (0..width).rev() {
+            for y in 0..height {
+                let from = if x >= n { self.pixels[x - n][y] } else { 0 };
+                self.set_selected(x, y, from);
+            }
+        }
+    }
+
+    pub fn scroll_left(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        let n = n.min(width);
+        for x in 0..width {
+            for y in 0..height {
+                let from = if x + n < width { self.pixels[x + n][y] } else { 0 };
+                self.set_selected(x, y, from);
+            }
+        }
+    }
+
+    /// Copies the selected planes' bits of `from` into a pixel, leaving the
+    /// other plane alone.
+    fn set_selected(&mut self, x: usize, y: usize, from: u8) {
+        self.pixels[x][y] = (self.pixels[x][y] & !self.planes) | (from & self.planes);
+    }
+
+    /// Clears the selected planes.
+    pub fn clear(&mut self) {
+        for column in self.pixels.iter_mut() {
+            for pixel in column.iter_mut() {
+                *pixel &= !self.planes;
+            }
+        }
+    }
+
+    pub(crate) fn save_state(&self, w: &mut Writer) {
+        for column in self.pixels.iter() { w.bytes(column); }
+        w.bool(self.hires);
+        w.u8(self.planes);
+    }
+
+    pub(crate) fn load_state(r: &mut Reader) -> Result<Self, StateError> {
+        let mut screen = Screen::new();
+        for column in screen.pixels.iter_mut() {
+            column.copy_from_slice(r.bytes(MAX_HEIGHT)?);
+            if column.iter().any(|&pixel| pixel > 0b11) { return Err(StateError::Invalid("pixel")); }
+        }
+        screen.hires = r.bool()?;
+        screen.planes = r.u8()?;
+        if screen.planes > 0b11 { return Err(StateError::Invalid("plane mask")); }
+        Ok(screen)
+    }
+
+    /// The visible pixels as columns, indexed `[x][y]`.
+    pub fn get_pixels(&self) -> Vec<&[u8]> {
+        let height = self.height();
+        self.pixels[..self.width()].iter().map(|column| &column[..height]).collect()
+    }
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,122 @@
+//! The debugger panel drawn over the screen while paused. It uses a tiny
+//! built-in font so the frontend needs no font files.
+
+use piston_window::{rectangle, Context, G2d};
+
+use chip8::{Debugger, Key, System};
+
+// Size of a font pixel in window pixels.
+const PIXEL: f64 = 2.0;
+const CHAR_WIDTH: f64 = 4.0 * PIXEL;
+const LINE_HEIGHT: f64 = 7.0 * PIXEL;
+const MARGIN: f64 = 8.0;
+// Instructions shown before the cursor, and in total.
+const LINES_BEFORE: u16 = 5;
+const LINES: usize = 20;
+
+const BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.8];
+const TEXT: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+const DIM: [f32; 4] = [0.4, 0.4, 0.4, 1.0];
+const HIGHLIGHT: [f32; 4] = [0.2, 0.3, 0.6, 1.0];
+const PRESSED: [f32; 4] = [1.0, 0.7, 0.2, 1.0];
+
+// Keypad keys in the same layout as the keyboard.
+const KEYPAD: [[u8; 4]; 4] = [[0x1, 0x2, 0x3, 0xC], [0x4, 0x5, 0x6, 0xD], [0x7, 0x8, 0x9, 0xE], [0xA, 0x0, 0xB, 0xF]];
+
+// 3x5 glyphs, one row per byte with the leftmost pixel in bit 2.
+const GLYPHS: &[(char, [u8; 5])] = &[
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]), ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]), ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]), ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]), ('7', [0b111, 0b001, 0b001, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]), ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]), ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]), ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]), ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]), ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]), ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]), ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]), ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]), ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]), ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]), ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]), ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]), ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]), ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('$', [0b011, 0b110, 0b010, 0b011, 0b110]), (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]), (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('[', [0b110, 0b100, 0b100, 0b100, 0b110]), (']', [0b011, 0b001, 0b001, 0b001, 0b011]),
+    ('(', [0b010, 0b100, 0b100, 0b100, 0b010]), (')', [0b010, 0b001, 0b001, 0b001, 0b010]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]), ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]), ('>', [0b100, 0b010, 0b001, 0b010, 0b100]),
+    ('*', [0b000, 0b101, 0b010, 0b101, 0b000]), ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]), ('?', [0b111, 0b001, 0b010, 0b000, 0b010]),
+];
+
+/// The address after the instruction at `addr`.
+pub fn next_instruction(system: &System, addr: u16) -> u16 {
+    addr.wrapping_add(Debugger::instruction_at(system, addr).map_or(2, |instruction| instruction.size()))
+}
+
+pub fn draw(system: &System, debugger: &Debugger, cursor: u16, context: Context, graphics: &mut G2d) {
+    let size = context.get_view_size();
+    rectangle(BACKGROUND, [0.0, 0.0, size[0], size[1]], context.transform, graphics);
+
+    let mut lines = vec![format!("PC {:03X}   I {:03X}", system.get_pc(), system.get_i())];
+    let v = system.get_registers();
+    for pair in (0..16).step_by(2) {
+        lines.push(format!("V{:X} {:02X}    V{:X} {:02X}", pair, v[pair], pair + 1, v[pair + 1]));
+    }
+    lines.push(format!("DT {:02X}    ST {:02X}", system.get_delay_timer(), system.get_sound_timer()));
+    lines.push(String::new());
+    lines.push("STACK".to_string());
+    let stack = system.get_stack();
+    lines.extend(stack.iter().rev().take(4).map(|addr| format!(" {:03X}", addr)));
+    if stack.len() > 4 { lines.push(format!(" +{}", stack.len() - 4)); }
+    for (row, line) in lines.iter().enumerate() {
+        text(line, MARGIN, MARGIN + row as f64 * LINE_HEIGHT, TEXT, context, graphics);
+    }
+
+    let keypad_y = size[1] - MARGIN - 4.0 * LINE_HEIGHT;
+    for (row, keys) in KEYPAD.iter().enumerate() {
+        for (column, &key) in keys.iter().enumerate() {
+            let color = if system.is_key_pressed(Key::new(key)) { PRESSED } else { DIM };
+            let x = MARGIN + column as f64 * 2.0 * CHAR_WIDTH;
+            text(&format!("{:X}", key), x, keypad_y + row as f64 * LINE_HEIGHT, color, context, graphics);
+        }
+    }
+
+    // Disassembly from a few instructions before the cursor.
+    let x = MARGIN + 20.0 * CHAR_WIDTH;
+    let mut addr = cursor.saturating_sub(LINES_BEFORE * 2);
+    for row in 0..LINES {
+        let y = MARGIN + row as f64 * LINE_HEIGHT;
+        if addr == cursor {
+            rectangle(HIGHLIGHT, [x - PIXEL, y - PIXEL, size[0] - x - MARGIN, LINE_HEIGHT], context.transform, graphics);
+        }
+
+        let pc = if addr == system.get_pc() { '>' } else { ' ' };
+        let breakpoint = if debugger.breakpoints().contains(&addr) { '*' } else { ' ' };
+        let word = system.read_memory(addr, 2).map_or(0, |word| u16::from_be_bytes([word[0], word[1]]));
+        let mnemonic = Debugger::instruction_at(system, addr).map_or("?".to_string(), |instruction| instruction.to_string());
+        text(&format!("{}{}{:03X} {:04X} {}", pc, breakpoint, addr, word, mnemonic), x, y, TEXT, context, graphics);
+
+        addr = next_instruction(system, addr);
+    }
+}
+
+fn text(text: &str, x: f64, y: f64, color: [f32; 4], context: Context, graphics: &mut G2d) {
+    for (i, c) in text.chars().enumerate() {
+        let c = c.to_ascii_uppercase();
+        let Some((_, rows)) = GLYPHS.iter().find(|(glyph, _)| *glyph == c) else { continue };
+        let left = x + i as f64 * CHAR_WIDTH;
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) != 0 {
+                    let pixel = [left + column as f64 * PIXEL, y + row as f64 * PIXEL, PIXEL, PIXEL];
+                    rectangle(color, pixel, context.transform, graphics);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use crate::state::{Reader, StateError, Writer};
+
+// https://chip8.gulrak.net/#quirks
+// https://github.com/Timendus/chip8-test-suite#quirks-test
+
+/// Behaviours that differ between chip8 interpreters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8XY6`/`8XYE` shift VY into VX, instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// `FX55`/`FX65` leave I pointing after the last register.
+    pub load_store_increment_i: bool,
+    /// `BNNN` jumps to `XNN + VX` instead of `NNN + V0`.
+    pub jump_uses_vx: bool,
+    /// `8XY1`/`8XY2`/`8XY3` reset VF to 0.
+    pub vf_reset: bool,
+    /// Sprites are clipped at the screen edge instead of wrapping around.
+    pub clip_sprites: bool,
+    /// `DXYN` waits for the next 60hz tick before the cpu continues.
+    pub display_wait: bool,
+    /// Memory covers the whole 64 KiB address space instead of the original
+    /// 4 KiB, so accesses past `$FFF` don't fault.
+    pub extended_memory: bool,
+}
+
+impl Quirks {
+    /// Every quirk turned off.
+    pub const NONE: Self = Quirks {
+        shift_uses_vy: false,
+        load_store_increment_i: false,
+        jump_uses_vx: false,
+        vf_reset: false,
+        clip_sprites: false,
+        display_wait: false,
+        extended_memory: false,
+    };
+
+    /// The original COSMAC VIP interpreter.
+    pub const VIP: Self = Quirks {
+        shift_uses_vy: true,
+        load_store_increment_i: true,
+        jump_uses_vx: false,
+        vf_reset: true,
+        clip_sprites: true,
+        display_wait: true,
+        extended_memory: false,
+    };
+
+    /// CHIP-48 on the HP-48 calculators.
+    pub const CHIP48: Self = Quirks {
+        shift_uses_vy: false,
+        load_store_increment_i: true,
+        jump_uses_vx: true,
+        vf_reset: false,
+        clip_sprites: true,
+        display_wait: false,
+        extended_memory: false,
+    };
+
+    /// SUPER-CHIP 1.1.
+    pub const SCHIP: Self = Quirks {
+        shift_uses_vy: false,
+        load_store_increment_i: false,
+        jump_uses_vx: true,
+        vf_reset: false,
+        clip_sprites: true,
+        display_wait: false,
+        extended_memory: false,
+    };
+
+    /// XO-CHIP, as implemented by Octo.
+    pub const XO_CHIP: Self = Quirks {
+        shift_uses_vy: true,
+        load_store_increment_i: true,
+        jump_uses_vx: false,
+        vf_reset: false,
+        clip_sprites: false,
+        display_wait: false,
+        extended_memory: true,
+    };
+
+    /// Names accepted by [`Quirks::from_name`].
+    pub const NAMES: [&'static str; 4] = ["vip", "chip48", "schip", "xochip"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace(['-', '_', '.'], "").as_str() {
+            "vip" | "cosmacvip" | "chip8" => Some(Self::VIP),
+            "chip48"                      => Some(Self::CHIP48),
+            "schip" | "superchip"         => Some(Self::SCHIP),
+            "schip11" | "superchip11"     => Some(Self::SCHIP),
+            "xochip"                      => Some(Self::XO_CHIP),
+            _ => None,
+        }
+    }
+
+    /// Each quirk by its field name.
+    pub fn flags(&self) -> [(&'static str, bool); 7] {
+        [
+            ("shift_uses_vy", self.shift_uses_vy),
+            ("load_store_increment_i", self.load_store_increment_i),
+            ("jump_uses_vx", self.jump_uses_vx),
+            ("vf_reset", self.vf_reset),
+            ("clip_sprites", self.clip_sprites),
+            ("display_wait", self.display_wait),
+            ("extended_memory", self.extended_memory),
+        ]
+    }
+
+    /// Sets a quirk by its field name, returning false if there is no such quirk.
+    pub fn set_flag(&mut self, name: &str, value: bool) -> bool {
+        let flag = match name {
+            "shift_uses_vy"          => &mut self.shift_uses_vy,
+            "load_store_increment_i" => &mut self.load_store_increment_i,
+            "jump_uses_vx"           => &mut self.jump_uses_vx,
+            "vf_reset"               => &mut self.vf_reset,
+            "clip_sprites"           => &mut self.clip_sprites,
+            "display_wait"           => &mut self.display_wait,
+            "extended_memory"        => &mut self.extended_memory,
+            _ => return false,
+        };
+        *flag = value;
+        true
+    }
+
+    pub(crate) fn save_state(&self, w: &mut Writer) {
+        w.bool(self.shift_uses_vy);
+        w.bool(self.load_store_increment_i);
+        w.bool(self.jump_uses_vx);
+        w.bool(self.vf_reset);
+        w.bool(self.clip_sprites);
+        w.bool(self.display_wait);
+        w.bool(self.extended_memory);
+    }
+
+    pub(crate) fn load_state(r: &mut Reader) -> Result<Self, StateError> {
+        Ok(Quirks {
+            shift_uses_vy: r.bool()?,
+            load_store_increment_i: r.bool()?,
+            jump_uses_vx: r.bool()?,
+            vf_reset: r.bool()?,
+            clip_sprites: r.bool()?,
+            display_wait: r.bool()?,
+            extended_memory: r.bool()?,
+        })
+    }
+}
+
+/// XO-CHIP, whose instruction quirks match the emulator before they were
+/// configurable.
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::XO_CHIP
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::VecDeque;
+
+use crate::system::System;
+
+/// A bounded history of save states for running the emulation backwards.
+///
+/// Only the newest state is kept in full. Every older frame is stored as the
+/// XOR of it and the frame after it, with runs of zeros (bytes that did not
+/// change) run length encoded, so a frame usually costs a few dozen bytes.
+#[derive(Debug)]
+pub struct Rewind {
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl Rewind {
+    /// Keeps at most `capacity` frames of history.
+    pub fn new(capacity: usize) -> Self {
+        Rewind {
+            latest: None,
+            deltas: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Records the current state of `system` as the newest frame.
+    pub fn push(&mut self, system: &System) {
+        let state = system.save_state();
+
+        if let Some(latest) = self.latest.take() {
+            self.deltas.push_back(encode_delta(&latest, &state));
+            while self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+
+        self.latest = Some(state);
+    }
+
+    /// Restores `system` to the frame before the newest one and drops the
+    /// newest. Returns false, leaving `system` at the oldest frame, once the
+    /// history runs out.
+    pub fn rewind(&mut self, system: &mut System) -> bool {
+        let Some(latest) = &mut self.latest else { return false };
+
+        let stepped = match self.deltas.pop_back() {
+            Some(delta) => {
+                *latest = apply_delta(latest, &delta);
+                true
+            }
+            None => false,
+        };
+
+        system.load_state(latest).expect("rewind only holds states saved by System");
+        stepped
+    }
+
+    /// Number of frames that can be stepped back.
+    pub fn len(&self) -> usize { self.deltas.len() }
+    pub fn is_empty(&self) -> bool { self.deltas.is_empty() }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+
+    /// Approximate number of bytes held by the history.
+    pub fn memory_usage(&self) -> usize {
+        self.latest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+}
+
+// A delta is the length of the older state followed by (zero run, literal
+// run, literal bytes) triples covering the XOR of both states, with the
+// shorter one padded with zeros.
+
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let len = old.len().max(new.len());
+    let xor = (0..len).map(|i| old.get(i).unwrap_or(&0) ^ new.get(i).unwrap_or(&0));
+
+    let mut out = Vec::new();
+    write_varint(&mut out, old.len());
+
+    let mut zeros = 0;
+    let mut literal = Vec::new();
+    for byte in xor {
+        if byte == 0 {
+            if !literal.is_empty() {
+                flush_run(&mut out, zeros, &literal);
+                zeros = 0;
+                literal.clear();
+            }
+            zeros += 1;
+        } else {
+            literal.push(byte);
+        }
+    }
+    if zeros > 0 || !literal.is_empty() {
+        flush_run(&mut out, zeros, &literal);
+    }
+
+    out
+}
+
+fn flush_run(out: &mut Vec<u8>, zeros: usize, literal: &[u8]) {
+    write_varint(out, zeros);
+    write_varint(out, literal.len());
+    out.extend_from_slice(literal);
+}
+
+fn apply_delta(new: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let old_len = read_varint(delta, &mut pos);
+
+    let mut old = new.to_vec();
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let literal = read_varint(delta, &mut pos);
+        for &byte in &delta[pos..pos + literal] {
+            if i >= old.len() { old.resize(i + 1, 0); }
+            old[i] ^= byte;
+            i += 1;
+        }
+        pos += literal;
+    }
+
+    old.resize(old_len, 0);
+    old
+}
+
+fn write_varint(out: &mut Vec<u8>, mut x: usize) {
+    while x >= 0x80 {
+        out.push((x as u8) | 0x80);
+        x >>= 7;
+    }
+    out.push(x as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut x = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        x |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 { return x; }
+        shift += 7;
+    }
+}
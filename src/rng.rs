@@ -0,0 +1,37 @@
+// https://prng.di.unimi.it/splitmix64.c
+// https://en.wikipedia.org/wiki/Xorshift#xorshift*
+
+/// Small deterministic random number generator for `CXNN`.
+///
+/// Its whole state is a single `u64`, so it can be stored in save states
+/// and replaying from a snapshot gives the same numbers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Run the seed through splitmix64 so similar seeds give unrelated
+        // sequences and the state is never 0, which xorshift can't leave.
+        let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^= z >> 31;
+
+        Rng { state: if z == 0 { 1 } else { z } }
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545F4914F6CDD1D) >> 56) as u8
+    }
+
+    pub(crate) fn state(&self) -> u64 { self.state }
+
+    pub(crate) fn from_state(state: u64) -> Option<Self> {
+        if state == 0 { None } else { Some(Rng { state }) }
+    }
+}
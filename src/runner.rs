@@ -0,0 +1,49 @@
+use std::fs;
+use std::path::Path;
+
+use chip8::{image, Movie, System};
+use chip8::headless::{self, RunLength};
+use chip8::movie::{self, Schedule};
+
+use crate::cli::HeadlessOptions;
+
+const DEFAULT_FRAMES: u64 = 600;
+
+/// Runs the rom without a window and writes out the requested dumps. A
+/// movie's frames run by its own schedule instead of `schedule`.
+pub fn run(options: &HeadlessOptions, system: &mut System, movie: Option<&Movie>, schedule: Schedule) -> Result<(), String> {
+    let script = match (&options.input, movie) {
+        (Some(path), _) => {
+            let text = fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path, err))?;
+            movie::parse_script(&text).map_err(|err| format!("{}: {}", path, err))?
+        }
+        (None, Some(movie)) => movie.events.clone(),
+        (None, None) => Vec::new(),
+    };
+
+    let length = options.length
+        .or(movie.map(|movie| RunLength::Frames(movie.frames)))
+        .unwrap_or(RunLength::Frames(DEFAULT_FRAMES));
+
+    // A fault still leaves a screen and registers worth dumping.
+    let schedule = movie.map_or(schedule, |movie| movie.schedule);
+    let result = headless::run(system, length, schedule, &script);
+
+    if let Some(path) = &options.screen {
+        let pixels = system.get_pixels();
+        let data = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("png") => image::png(&pixels),
+            Some("pbm") => image::pbm(&pixels),
+            Some("txt") => image::ascii(&pixels).into_bytes(),
+            _ => return Err(format!("Unknown screen format for {}, expected .png, .pbm or .txt", path)),
+        };
+        fs::write(path, data).map_err(|err| format!("Could not write {}: {}", path, err))?;
+    }
+
+    if let Some(path) = &options.registers {
+        fs::write(path, headless::registers_json(system))
+            .map_err(|err| format!("Could not write {}: {}", path, err))?;
+    }
+
+    result.map_err(|err| format!("Emulator fault: {}", err))
+}
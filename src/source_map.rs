@@ -0,0 +1,54 @@
+//! Which source line every byte of an assembled rom came from, so debuggers
+//! can show source instead of addresses.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub addr: u16,
+    pub len: u16,
+    pub file: String,
+    /// 1 based.
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    spans: Vec<Span>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `len` bytes at `addr` as coming from `line` of `file`. Bytes
+    /// right after the last span from the same line extend it.
+    pub fn add(&mut self, addr: u16, len: u16, file: &str, line: usize) {
+        if let Some(last) = self.spans.last_mut() {
+            if last.file == file && last.line == line && last.addr as usize + last.len as usize == addr as usize {
+                last.len += len;
+                return;
+            }
+        }
+        self.spans.push(Span { addr, len, file: file.to_string(), line });
+    }
+
+    /// Spans in the order they were emitted.
+    pub fn spans(&self) -> &[Span] { &self.spans }
+
+    /// The file and line the byte at `addr` came from.
+    pub fn location(&self, addr: u16) -> Option<(&str, usize)> {
+        self.spans.iter()
+            .find(|span| (span.addr as usize..span.addr as usize + span.len as usize).contains(&(addr as usize)))
+            .map(|span| (span.file.as_str(), span.line))
+    }
+
+    /// The first address emitted by `line` of `file`, or if it emitted
+    /// nothing, by the next line that did. Returns the address and the line
+    /// it belongs to.
+    pub fn address(&self, file: &str, line: usize) -> Option<(u16, usize)> {
+        self.spans.iter()
+            .filter(|span| span.file == file && span.line >= line)
+            .min_by_key(|span| (span.line, span.addr))
+            .map(|span| (span.addr, span.line))
+    }
+}
@@ -0,0 +1,68 @@
+//! The default audio output device, as an [`AudioSink`].
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use chip8::audio::AudioSink;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+
+// Samples queued beyond this many seconds are dropped, so a stall in the
+// emulator doesn't leave the sound lagging behind for good.
+const MAX_LATENCY: f64 = 0.1;
+
+pub struct Speaker {
+    sample_rate: u32,
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    // Playback stops when the stream is dropped.
+    _stream: Stream,
+}
+
+impl Speaker {
+    pub fn open() -> Result<Self, String> {
+        let device = cpal::default_host().default_output_device().ok_or("no audio output device")?;
+        let supported = device.default_output_config().map_err(|err| err.to_string())?;
+        let config = supported.config();
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => build::<f32>(&device, &config, queue.clone()),
+            SampleFormat::I16 => build::<i16>(&device, &config, queue.clone()),
+            SampleFormat::U16 => build::<u16>(&device, &config, queue.clone()),
+            format => return Err(format!("unsupported sample format {}", format)),
+        }.map_err(|err| err.to_string())?;
+        stream.play().map_err(|err| err.to_string())?;
+
+        Ok(Speaker { sample_rate: config.sample_rate.0, queue, _stream: stream })
+    }
+}
+
+/// A stream playing the queue on every channel, and silence when it runs dry.
+fn build<T: SizedSample + FromSample<f32>>(device: &Device, config: &StreamConfig, queue: Arc<Mutex<VecDeque<f32>>>) -> Result<Stream, cpal::BuildStreamError> {
+    let channels = config.channels as usize;
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let mut queue = queue.lock().unwrap();
+            for frame in data.chunks_mut(channels) {
+                frame.fill(T::from_sample(queue.pop_front().unwrap_or(0.0)));
+            }
+        },
+        |err| eprintln!("Audio error: {}", err),
+        None,
+    )
+}
+
+impl AudioSink for Speaker {
+    fn sample_rate(&self) -> u32 { self.sample_rate }
+
+    fn play(&mut self, samples: &[f32]) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(samples);
+        let max = (self.sample_rate as f64 * MAX_LATENCY) as usize;
+        if queue.len() > max {
+            let excess = queue.len() - max;
+            queue.drain(..excess);
+        }
+    }
+}
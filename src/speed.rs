@@ -0,0 +1,125 @@
+//! Speed controls for the window: fast-forward and slow motion, and the
+//! speed and frame rate emulation actually runs at.
+
+use std::time::{Duration, Instant};
+
+use chip8::movie::FRAME_DT;
+use chip8::{System, Timing};
+
+/// Speed in slow motion, as a multiple of normal.
+const SLOW_MOTION: f64 = 0.25;
+/// How often the speed and frame rate are measured.
+const MEASURE_EVERY: Duration = Duration::from_secs(1);
+// Share of each update that fast-forwarding as fast as possible spends
+// emulating, leaving the rest for drawing and input.
+const UNCAPPED_SHARE: f64 = 0.8;
+
+/// Frames to run in an update.
+#[derive(Debug, Clone, Copy)]
+pub enum Due {
+    Frames(u32),
+    Until(Instant),
+}
+
+impl Due {
+    /// Whether another frame is due after `run` of them.
+    pub fn more(self, run: u32) -> bool {
+        match self {
+            Due::Frames(frames) => run < frames,
+            Due::Until(deadline) => Instant::now() < deadline,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Speed {
+    /// Speed multiple while fast-forwarding, or `None` for as fast as possible.
+    pub fast_forward_rate: Option<f64>,
+    pub fast_forward: bool,
+    pub slow_motion: bool,
+    // Wall time owed to frames that haven't run yet.
+    frame_dt: f64,
+    // Since the last measurement: when it was, the system's time then, and
+    // frames drawn.
+    since: Instant,
+    time: f64,
+    draws: u32,
+    // Emulated seconds per second and frames drawn per second, as measured.
+    rate: f64,
+    fps: f64,
+}
+
+impl Speed {
+    pub fn new(fast_forward_rate: Option<f64>, system: &System) -> Self {
+        Speed {
+            fast_forward_rate,
+            fast_forward: false,
+            slow_motion: false,
+            frame_dt: 0.0,
+            since: Instant::now(),
+            time: system.get_time(),
+            draws: 0,
+            rate: 1.0,
+            fps: 0.0,
+        }
+    }
+
+    /// The speed multiple to run at, or `None` for as fast as possible.
+    fn target(&self) -> Option<f64> {
+        match (self.fast_forward, self.slow_motion) {
+            (true, _) => self.fast_forward_rate,
+            (false, true) => Some(SLOW_MOTION),
+            (false, false) => Some(1.0),
+        }
+    }
+
+    /// Frames to run for an update `dt` seconds after the last one. Nothing
+    /// is owed for the time spent not `running`.
+    pub fn due(&mut self, dt: f64, running: bool) -> Due {
+        if !running {
+            self.frame_dt = 0.0;
+            return Due::Frames(0);
+        }
+
+        match self.target() {
+            Some(rate) => {
+                self.frame_dt += dt * rate;
+                let frames = (self.frame_dt / FRAME_DT) as u32;
+                self.frame_dt -= frames as f64 * FRAME_DT;
+                Due::Frames(frames)
+            }
+            None => {
+                self.frame_dt = 0.0;
+                Due::Until(Instant::now() + Duration::from_secs_f64(dt * UNCAPPED_SHARE))
+            }
+        }
+    }
+
+    /// Counts a drawn frame, measuring once enough time has passed.
+    pub fn drawn(&mut self, system: &System) {
+        self.draws += 1;
+        let elapsed = self.since.elapsed();
+        if elapsed < MEASURE_EVERY { return; }
+
+        let seconds = elapsed.as_secs_f64();
+        self.rate = (system.get_time() - self.time) / seconds;
+        self.fps = self.draws as f64 / seconds;
+        self.since = Instant::now();
+        self.time = system.get_time();
+        self.draws = 0;
+    }
+
+    /// The measured speed, e.g. `100% speed (500 ips), 60 fps`.
+    pub fn status(&self, system: &System) -> String {
+        let mode = match (self.fast_forward, self.slow_motion) {
+            (true, _) => "fast-forward, ",
+            (false, true) => "slow motion, ",
+            (false, false) => "",
+        };
+        let ips = match system.timing() {
+            Timing::Fixed => format!(" ({:.0} ips)", self.rate * system.ips() as f64),
+            Timing::Vip => String::new(),
+        };
+        format!("{}{:.0}% speed{}, {:.0} fps", mode, self.rate * 100.0, ips, self.fps)
+    }
+}
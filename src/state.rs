@@ -0,0 +1,121 @@
+//! Versioned binary save states.
+//!
+//! A state starts with the magic `CH8S` and a little endian `u32` version,
+//! followed by the fields of [`crate::System`] in the order written by
+//! [`crate::System::save_state`]. All integers are little endian.
+
+use std::fmt;
+
+pub const MAGIC: &[u8; 4] = b"CH8S";
+pub const VERSION: u32 = 4;
+
+// Version history:
+//   1  initial format
+//   2  adds the random number generator after the halted flag
+//   3  adds the timing mode and the cycles into the VIP's frame at the end
+//   4  adds the instructions per second at the end
+
+/// Why a save state could not be loaded. The system is left untouched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start with the save state magic.
+    NotAState,
+    /// The state was written by a version of the format this build cannot read.
+    UnsupportedVersion(u32),
+    /// The data ends in the middle of the state.
+    Truncated,
+    /// A field holds a value that no system could be in.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAState => write!(f, "not a chip8 save state"),
+            Self::UnsupportedVersion(v) =>
+                write!(f, "save state version {} is not supported (expected 1 to {})", v, VERSION),
+            Self::Truncated => write!(f, "save state is truncated"),
+            Self::Invalid(field) => write!(f, "save state has an invalid {}", field),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+#[derive(Debug, Default)]
+pub(crate) struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        let mut writer = Writer { data: Vec::new() };
+        writer.bytes(MAGIC);
+        writer.u32(VERSION);
+        writer
+    }
+
+    pub fn u8(&mut self, x: u8) { self.data.push(x); }
+    pub fn bool(&mut self, x: bool) { self.u8(x as u8); }
+    pub fn u16(&mut self, x: u16) { self.bytes(&x.to_le_bytes()); }
+    pub fn u32(&mut self, x: u32) { self.bytes(&x.to_le_bytes()); }
+    pub fn u64(&mut self, x: u64) { self.bytes(&x.to_le_bytes()); }
+    pub fn f64(&mut self, x: f64) { self.bytes(&x.to_le_bytes()); }
+    pub fn bytes(&mut self, x: &[u8]) { self.data.extend_from_slice(x); }
+
+    pub fn finish(self) -> Vec<u8> { self.data }
+}
+
+#[derive(Debug)]
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    version: u32,
+}
+
+impl<'a> Reader<'a> {
+    /// Checks the header, leaving the reader at the first field.
+    pub fn new(data: &'a [u8]) -> Result<Self, StateError> {
+        let mut reader = Reader { data, version: 0 };
+        if reader.bytes(4).ok() != Some(&MAGIC[..]) {
+            return Err(StateError::NotAState);
+        }
+        reader.version = reader.u32()?;
+        if !(1..=VERSION).contains(&reader.version) {
+            return Err(StateError::UnsupportedVersion(reader.version));
+        }
+        Ok(reader)
+    }
+
+    /// Version of the format being read, for migrating older states.
+    pub fn version(&self) -> u32 { self.version }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> { Ok(self.bytes(1)?[0]) }
+    pub fn u16(&mut self) -> Result<u16, StateError> { Ok(u16::from_le_bytes(self.array()?)) }
+    pub fn u32(&mut self) -> Result<u32, StateError> { Ok(u32::from_le_bytes(self.array()?)) }
+    pub fn u64(&mut self) -> Result<u64, StateError> { Ok(u64::from_le_bytes(self.array()?)) }
+    pub fn f64(&mut self) -> Result<f64, StateError> { Ok(f64::from_le_bytes(self.array()?)) }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("flag")),
+        }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len { return Err(StateError::Truncated); }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    /// Fails unless every byte has been read.
+    pub fn finish(self) -> Result<(), StateError> {
+        if self.data.is_empty() { Ok(()) } else { Err(StateError::Invalid("length")) }
+    }
+}
@@ -1,226 +1,845 @@
-use rand::random;
-
-use crate::input::*;
-use crate::output::*;
-use crate::cpu::*;
-use crate::DEBUG;
-
-// https://tobiasvl.github.io/blog/write-a-chip-8-emulator
-// http://www.emulator101.com/chip-8-instruction-set.html
-// https://en.wikipedia.org/wiki/CHIP-8
-// https://chip-8.github.io/links
-
-const CLOCK_HZ: f64 = 500.0;
-
-#[derive(Debug)]
-pub struct System {
-    display: Screen,
-    keypad: Keypad,
-
-    memory: Memory,
-    stack: Vec<Address>,
-    pc: Address,
-    i: Address,
-    v: [u8; 16],
-
-    delay_timer: u8,
-    sound_timer: u8,
-
-    clock_dt: f64,
-    timer_dt: f64,
-}
-
-impl System {
-    pub fn new(rom: &[u8]) -> Self {
-        let mut ram = Memory::new();
-
-        ram.write(0x050.into(), &[
-            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-        ]);
-
-        ram.write(0x200.into(), rom);
-
-        System {
-            display: Screen::new(),
-            keypad: Keypad::new(),
-
-            memory: ram,
-            stack: Vec::new(),
-            pc: Address::new(0x200),
-            i: Address::new(0),
-            v: [0; 16],
-
-            delay_timer: 0,
-            sound_timer: 0,
-
-            clock_dt: 0.0,
-            timer_dt: 0.0,
-        }
-    }
-
-    pub fn update(&mut self, dt: f64) {
-        self.clock_dt += dt;
-        self.timer_dt += dt;
-
-        while self.clock_dt >= 1.0 / CLOCK_HZ {
-            self.clock_dt -= 1.0 / CLOCK_HZ;
-
-            let pc_old = self.pc;
-
-            let opcode = self.memory.read16(self.pc);
-            let instruction = Instruction::new(opcode).unwrap();
-            self.execute(instruction);
-
-            if DEBUG && pc_old != self.pc {
-                println!("[{:03X}]: {:04X} => {}", pc_old.get(), opcode, instruction);
-            }
-        }
-
-        while self.timer_dt >= 1.0 / 60.0 {
-            self.timer_dt -= 1.0 / 60.0;
-
-            if self.delay_timer > 0 { self.delay_timer -= 1; }
-            if self.sound_timer > 0 { self.sound_timer -= 1; }
-        }
-    }
-
-    pub fn update_keypad(&mut self, key: Key, pressed: bool) {
-        self.keypad.set_pressed(key, pressed);
-    }
-
-    pub fn get_pixels(&self) -> &[[bool; 32]; 64] { self.display.get_pixels() }
-    pub fn get_sound(&self) -> bool { self.sound_timer > 0 }
-
-    fn execute(&mut self, instruction: Instruction) {
-        match instruction {
-            Instruction::Clear => self.display.clear(),
-            Instruction::Return => self.pc = self.stack.pop().unwrap(),
-            Instruction::Jump(addr) => return self.pc = addr,
-            Instruction::Call(addr) => {
-                self.stack.push(self.pc);
-                return self.pc = addr;
-            }
-            Instruction::EqNum(reg, num) => {
-                if self.v[reg.idx()] == num { self.pc = self.pc.add(2); }
-            }
-            Instruction::NeqNum(reg, num) => {
-                if self.v[reg.idx()] != num { self.pc = self.pc.add(2); }
-            }
-            Instruction::Eq(reg_x, reg_y) => {
-                if self.v[reg_x.idx()] == self.v[reg_y.idx()] { self.pc = self.pc.add(2); }
-            }
-            Instruction::Neq(reg_x, reg_y) => {
-                if self.v[reg_x.idx()] != self.v[reg_y.idx()] { self.pc = self.pc.add(2); }
-            }
-            Instruction::SetNum(reg, num) => self.v[reg.idx()] = num,
-            Instruction::AddNum(reg, num) => {
-                self.v[reg.idx()] = self.v[reg.idx()].overflowing_add(num).0
-            }
-            Instruction::Move(reg_x, reg_y) => self.v[reg_x.idx()] = self.v[reg_y.idx()],
-            Instruction::Or(reg_x, reg_y) => self.v[reg_x.idx()] |= self.v[reg_y.idx()],
-            Instruction::And(reg_x, reg_y) => self.v[reg_x.idx()] &= self.v[reg_y.idx()],
-            Instruction::Xor(reg_x, reg_y) => self.v[reg_x.idx()] ^= self.v[reg_y.idx()],
-            Instruction::Add(reg_x, reg_y) => {
-                let value1 = self.v[reg_x.idx()];
-                let value2 = self.v[reg_y.idx()];
-
-                let (result, overflow) = value1.overflowing_add(value2);
-
-                self.v[0xF] = overflow as u8;
-                self.v[reg_x.idx()] = result;
-            }
-            Instruction::Sub(reg_x, reg_y) => {
-                let value1 = self.v[reg_x.idx()];
-                let value2 = self.v[reg_y.idx()];
-
-                let (result, overflow) = value1.overflowing_sub(value2);
-
-                self.v[0xF] = !overflow as u8;
-                self.v[reg_x.idx()] = result;
-            }
-            Instruction::Shr(reg_x, reg_y) => {
-                self.v[0xF] = self.v[reg_y.idx()] & 1;
-                self.v[reg_x.idx()] = self.v[reg_y.idx()] >> 1;
-            }
-            Instruction::Subb(reg_x, reg_y) => {
-                let value1 = self.v[reg_y.idx()];
-                let value2 = self.v[reg_x.idx()];
-
-                let (result, overflow) = value1.overflowing_sub(value2);
-
-                self.v[0xF] = !overflow as u8;
-                self.v[reg_x.idx()] = result;
-            }
-            Instruction::Shl(reg_x, reg_y) => {
-                self.v[0xF] = (self.v[reg_y.idx()] & 0x80) >> 7;
-                self.v[reg_x.idx()] = self.v[reg_y.idx()] << 1;
-            }
-            Instruction::SetIdx(addr) => self.i = addr,
-            Instruction::JumpV0(addr) => return self.pc = addr.add(self.v[0] as u16),
-            Instruction::Rand(reg, num) => {
-                self.v[reg.idx()] = random::<u8>() & num;
-            }
-            Instruction::Draw(reg_x, reg_y, size) => {
-                let x = self.v[reg_x.idx()];
-                let y = self.v[reg_y.idx()];
-                let sprite = self.memory.read(self.i, size.into());
-                let collision = self.display.draw(x, y, sprite);
-                self.v[0xF] = collision as u8;
-            }
-            Instruction::KeyUp(reg) => {
-                let key = Key::new(self.v[reg.idx()]);
-                if self.keypad.is_pressed(key) {
-                    self.pc = self.pc.add(2);
-                }
-            }
-            Instruction::KeyDown(reg) => {
-                let key = Key::new(self.v[reg.idx()]);
-                if !self.keypad.is_pressed(key) {
-                    self.pc = self.pc.add(2);
-                }
-            }
-            Instruction::GetDelay(reg) => self.v[reg.idx()] = self.delay_timer,
-            Instruction::WaitKey(reg) => {
-                if let Some(key) = self.keypad.get_key() {
-                    self.v[reg.idx()] = key.get();
-                } else {
-                    self.pc = self.pc.sub(2);
-                }
-            }
-            Instruction::SetDelay(reg) => self.delay_timer = self.v[reg.idx()],
-            Instruction::SetSound(reg) => self.sound_timer = self.v[reg.idx()],
-            Instruction::AddIdx(reg) => self.i = self.i.add(self.v[reg.idx()].into()),
-            Instruction::SetSprite(reg) => self.i = (0x050 + self.v[reg.idx()] as u16 * 5).into(),
-            Instruction::StoreBcd(reg) => {
-                let value = self.v[reg.idx()];
-                self.memory.write(self.i, &[value / 100, (value / 10) % 10, value % 10]);
-            }
-            Instruction::Store(reg) => {
-                self.memory.write(self.i, &self.v[..=reg.idx()]);
-                self.i = self.i.add(1 + reg.get() as u16);
-            }
-            Instruction::Load(reg) => {
-                let data = self.memory.read(self.i, 1 + reg.get() as u16);
-                self.v[..=reg.idx()].copy_from_slice(data);
-                self.i = self.i.add(1 + reg.get() as u16);
-            }
-        }
-
-        self.pc = self.pc.add(2);
-    }
-}
+use crate::input::*;
+use crate::output::*;
+use crate::cpu::*;
+use crate::quirks::Quirks;
+use crate::error::EmulatorError;
+use crate::state::{Reader, StateError, Writer};
+use crate::rng::Rng;
+use crate::trace::{Registers, Tracer};
+use crate::audio::{Sound, SoundChange};
+use crate::timing::{self, Timing, VIP_CYCLE_HZ, VIP_FRAME_CYCLES, VIP_INTERRUPT_CYCLES};
+
+// https://tobiasvl.github.io/blog/write-a-chip-8-emulator
+// http://www.emulator101.com/chip-8-instruction-set.html
+// https://en.wikipedia.org/wiki/CHIP-8
+// https://chip-8.github.io/links
+
+/// Instructions executed per second of emulated time under
+/// [`Timing::Fixed`], unless set with [`System::set_ips`].
+pub const DEFAULT_IPS: u32 = 500;
+/// Rate of the delay and sound timers.
+pub const TIMER_HZ: f64 = 60.0;
+const STACK_SIZE: usize = 16;
+const ROM_START: u16 = 0x200;
+const FONT_START: u16 = 0x050;
+const BIG_FONT_START: u16 = 0x0A0;
+
+/// Bytes of memory the cpu can address, see [`Quirks::extended_memory`].
+fn memory_size(quirks: Quirks) -> usize {
+    if quirks.extended_memory { Memory::SIZE } else { Memory::CLASSIC_SIZE }
+}
+
+/// Hooks into the cpu for debuggers. [`System::update`] and [`System::step`]
+/// use [`NoHooks`], which compiles away, so normal runs don't pay for them.
+pub trait Hooks {
+    /// Called before each instruction. Returning true stops the update
+    /// before the instruction runs.
+    fn before_step(&mut self, _system: &System) -> bool { false }
+    /// `len` bytes at `addr` are about to be read as data, by `Load`, `Draw`
+    /// and the like. Fetching instructions doesn't count.
+    fn read(&mut self, _addr: u16, _len: u16) {}
+    /// `len` bytes at `addr` are about to be written, by `Store`, `StoreBcd`
+    /// and the like.
+    fn write(&mut self, _addr: u16, _len: u16) {}
+}
+
+/// Hooks that do nothing.
+#[derive(Debug, Clone, Copy)]
+pub struct NoHooks;
+
+impl Hooks for NoHooks {}
+
+/// A closure deciding when to stop.
+impl<F: FnMut(&System) -> bool> Hooks for F {
+    fn before_step(&mut self, system: &System) -> bool { self(system) }
+}
+
+/// Instructions to run in frame `frame`, counting from 0, of
+/// [`System::run_frame`] so that frames average `ips` instructions a second.
+pub fn instructions_in_frame(ips: u32, frame: u64) -> u32 {
+    let total = |frame: u64| frame * ips as u64 / TIMER_HZ as u64;
+    (total(frame + 1) - total(frame)) as u32
+}
+
+#[derive(Debug)]
+pub struct System {
+    display: Screen,
+    keypad: Keypad,
+
+    memory: Memory,
+    stack: Vec<Address>,
+    pc: Address,
+    i: Address,
+    v: [u8; 16],
+
+    delay_timer: u8,
+    sound_timer: u8,
+
+    clock_dt: f64,
+    timer_dt: f64,
+    ips: u32,
+    timing: Timing,
+    // Machine cycles since the last display interrupt, under Timing::Vip.
+    frame_cycles: u64,
+
+    rpl: [u8; 16],
+    rng: Rng,
+    seed: u64,
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+
+    quirks: Quirks,
+    vblank_wait: bool,
+    halted: bool,
+    fault: Option<EmulatorError>,
+    tracer: Option<Tracer>,
+
+    // Emulated seconds since boot, and the sound changes since they were
+    // last taken, if anyone is listening.
+    time: f64,
+    sound_log: Option<Vec<SoundChange>>,
+}
+
+impl System {
+    pub fn new(rom: &[u8]) -> Result<Self, EmulatorError> {
+        Self::with_quirks(rom, Quirks::default())
+    }
+
+    pub fn with_quirks(rom: &[u8], quirks: Quirks) -> Result<Self, EmulatorError> {
+        let size = memory_size(quirks);
+        let max = size - ROM_START as usize;
+        if rom.len() > max {
+            return Err(EmulatorError::RomTooLarge { size: rom.len(), max });
+        }
+
+        let seed = rand::random();
+        let mut ram = Memory::new(size);
+
+        ram.write(FONT_START.into(), &[
+            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+            0x20, 0x60, 0x20, 0x20, 0x70, // 1
+            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+        ]).unwrap();
+
+        ram.write(BIG_FONT_START.into(), &[
+            0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+            0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+            0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+            0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+            0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+            0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+            0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+            0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+            0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+            0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+            0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+            0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+        ]).unwrap();
+
+        ram.write(ROM_START.into(), rom).unwrap();
+
+        Ok(System {
+            display: Screen::new(),
+            keypad: Keypad::new(),
+
+            memory: ram,
+            stack: Vec::new(),
+            pc: Address::new(ROM_START),
+            i: Address::new(0),
+            v: [0; 16],
+
+            delay_timer: 0,
+            sound_timer: 0,
+
+            clock_dt: 0.0,
+            timer_dt: 0.0,
+            ips: DEFAULT_IPS,
+            timing: Timing::Fixed,
+            frame_cycles: 0,
+
+            rpl: [0; 16],
+            rng: Rng::new(seed),
+            seed,
+            audio_pattern: None,
+            pitch: 64,
+
+            quirks,
+            vblank_wait: false,
+            halted: false,
+            fault: None,
+            tracer: None,
+
+            time: 0.0,
+            sound_log: None,
+        })
+    }
+
+    /// Runs the cpu and timers for `dt` seconds. Once the cpu has faulted,
+    /// every call returns the same error without doing anything.
+    pub fn update(&mut self, dt: f64) -> Result<(), EmulatorError> {
+        self.update_with(dt, &mut NoHooks).map(|_| ())
+    }
+
+    /// Like [`System::update`], but asks `stop` before every instruction.
+    /// When it says to stop, the rest of `dt` is dropped, timers included,
+    /// and this returns true.
+    pub fn update_until(&mut self, dt: f64, mut stop: impl FnMut(&System) -> bool) -> Result<bool, EmulatorError> {
+        self.update_with(dt, &mut stop)
+    }
+
+    /// Like [`System::update_until`], with `hooks` also seeing every memory access.
+    pub fn update_with(&mut self, dt: f64, hooks: &mut impl Hooks) -> Result<bool, EmulatorError> {
+        if let Some(fault) = &self.fault { return Err(fault.clone()); }
+
+        self.clock_dt += dt;
+
+        if self.timing == Timing::Vip {
+            // Instructions take as long as they take, so the last one can
+            // overrun into the next update. The timers follow the cycles.
+            while self.clock_dt > 0.0 {
+                if hooks.before_step(self) {
+                    self.clock_dt = 0.0;
+                    return Ok(true);
+                }
+                let time = self.time;
+                self.step_with(hooks)?;
+                self.clock_dt -= self.time - time;
+            }
+            return Ok(false);
+        }
+
+        let period = 1.0 / self.ips as f64;
+        while self.clock_dt >= period {
+            if hooks.before_step(self) {
+                self.clock_dt = 0.0;
+                return Ok(true);
+            }
+            self.clock_dt -= period;
+
+            self.step_with(hooks)?;
+        }
+
+        self.timer_dt += dt;
+
+        while self.timer_dt >= 1.0 / TIMER_HZ {
+            self.timer_dt -= 1.0 / TIMER_HZ;
+
+            self.tick_timers();
+        }
+
+        Ok(false)
+    }
+
+    /// Runs one 60hz frame: `instructions` instructions, then a timer tick.
+    /// Unlike [`System::update`] no time is carried between calls, so every
+    /// frame runs exactly as many instructions. [`instructions_in_frame`]
+    /// spreads [`System::ips`] over frames. Under [`Timing::Vip`] the count
+    /// is ignored and the frame runs `1 / TIMER_HZ` seconds of cycles.
+    pub fn run_frame(&mut self, instructions: u32) -> Result<(), EmulatorError> {
+        self.run_frame_with(instructions, &mut NoHooks).map(|_| ())
+    }
+
+    /// Like [`System::run_frame`], with `hooks` as in [`System::update_with`].
+    /// When they stop it, the rest of the frame is dropped, timer tick
+    /// included, and this returns true.
+    pub fn run_frame_with(&mut self, instructions: u32, hooks: &mut impl Hooks) -> Result<bool, EmulatorError> {
+        if self.timing == Timing::Vip { return self.update_with(1.0 / TIMER_HZ, hooks); }
+        if let Some(fault) = &self.fault { return Err(fault.clone()); }
+
+        for _ in 0..instructions {
+            if hooks.before_step(self) { return Ok(true); }
+            self.step_with(hooks)?;
+        }
+        self.tick_timers();
+
+        Ok(false)
+    }
+
+    /// One 60hz tick: counts down both timers and ends any wait for the display.
+    pub fn tick_timers(&mut self) {
+        let sound = self.sound();
+        self.vblank_wait = false;
+
+        if self.delay_timer > 0 { self.delay_timer -= 1; }
+        if self.sound_timer > 0 { self.sound_timer -= 1; }
+        self.log_sound(sound);
+    }
+
+    /// Executes a single instruction, without touching the timers. Does
+    /// nothing while the cpu is halted or waiting for the display.
+    ///
+    /// Under [`Timing::Vip`] the timers run from the instruction's cycles
+    /// instead, and doing nothing waits for the next display interrupt.
+    pub fn step(&mut self) -> Result<(), EmulatorError> {
+        self.step_with(&mut NoHooks)
+    }
+
+    /// Like [`System::step`], with `hooks` seeing the instruction's memory
+    /// accesses. `Hooks::before_step` is not called.
+    pub fn step_with(&mut self, hooks: &mut impl Hooks) -> Result<(), EmulatorError> {
+        if let Some(fault) = &self.fault { return Err(fault.clone()); }
+        let vip = self.timing == Timing::Vip;
+        if !vip { self.time += 1.0 / self.ips as f64; }
+        if self.halted || self.vblank_wait {
+            if vip { self.wait_for_interrupt(); }
+            return Ok(());
+        }
+
+        let pc_old = self.pc;
+        let opcode = self.memory.read16(self.pc);
+        let next = self.memory.read16(self.pc.add(2));
+        let instruction = Instruction::decode(opcode, next);
+        // The VIP waits for the interrupt before drawing rather than after,
+        // so the cpu carries on from the start of the frame.
+        if vip && self.quirks.display_wait && matches!(instruction, Some(Instruction::Draw(..))) {
+            self.wait_for_interrupt();
+        }
+
+        let sound = self.sound();
+        let v = self.v;
+        let before = self.tracer.as_ref().map(|_| Registers::of(self));
+
+        let result = instruction
+            .ok_or(Fault::InvalidOpcode)
+            .and_then(|instruction| self.execute(instruction, hooks).map(|_| instruction));
+
+        self.log_sound(sound);
+        match result {
+            Ok(instruction) => {
+                if let Some(before) = before {
+                    let after = Registers::of(self);
+                    let operand = (instruction.size() == 4).then_some(next);
+                    if let Some(tracer) = &mut self.tracer {
+                        tracer.record(pc_old.get() as u16, opcode, operand, &before, &after);
+                    }
+                }
+                if vip {
+                    // Already waited for the display above.
+                    self.vblank_wait = false;
+                    let skipped = self.pc != pc_old.add(instruction.size());
+                    self.run_cycles(timing::vip_cycles(instruction, &v, skipped));
+                }
+                Ok(())
+            }
+            Err(fault) => {
+                let error = fault.into_error(pc_old, opcode);
+                self.fault = Some(error.clone());
+                Err(error)
+            }
+        }
+    }
+
+    /// Serializes the whole machine, see [`crate::state`] for the format.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+
+        self.memory.save_state(&mut w);
+        for &v in self.v.iter() { w.u8(v); }
+        w.u16(self.i.get() as u16);
+        w.u16(self.pc.get() as u16);
+        w.u8(self.stack.len() as u8);
+        for addr in self.stack.iter() { w.u16(addr.get() as u16); }
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+        w.f64(self.clock_dt);
+        w.f64(self.timer_dt);
+
+        self.display.save_state(&mut w);
+        self.keypad.save_state(&mut w);
+
+        w.bytes(&self.rpl);
+        w.bool(self.audio_pattern.is_some());
+        w.bytes(&self.audio_pattern.unwrap_or_default());
+        w.u8(self.pitch);
+
+        self.quirks.save_state(&mut w);
+        w.bool(self.vblank_wait);
+        w.bool(self.halted);
+
+        w.u64(self.seed);
+        w.u64(self.rng.state());
+
+        w.u8(self.timing as u8);
+        w.u64(self.frame_cycles);
+        w.u32(self.ips);
+
+        w.finish()
+    }
+
+    /// Restores a state written by [`System::save_state`]. On error the
+    /// system is left as it was. A faulted cpu is no longer faulted.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = Reader::new(data)?;
+
+        let mut memory = Memory::load_state(&mut r)?;
+        let v = r.array::<16>()?;
+        let i = Address::new(r.u16()?);
+        let pc = Address::new(r.u16()?);
+        let stack_len = r.u8()? as usize;
+        if stack_len > STACK_SIZE { return Err(StateError::Invalid("stack size")); }
+        let stack = (0..stack_len).map(|_| r.u16().map(Address::new)).collect::<Result<_, _>>()?;
+        let delay_timer = r.u8()?;
+        let sound_timer = r.u8()?;
+        let clock_dt = r.f64()?;
+        let timer_dt = r.f64()?;
+
+        let display = Screen::load_state(&mut r)?;
+        let keypad = Keypad::load_state(&mut r)?;
+
+        let rpl = r.array::<16>()?;
+        let has_audio_pattern = r.bool()?;
+        let audio_pattern = r.array::<16>()?;
+        let pitch = r.u8()?;
+
+        let quirks = Quirks::load_state(&mut r)?;
+        memory.set_size(memory_size(quirks));
+        let vblank_wait = r.bool()?;
+        let halted = r.bool()?;
+
+        // Version 1 had no generator of its own, so any seed is as faithful.
+        let (seed, rng) = if r.version() >= 2 {
+            let seed = r.u64()?;
+            (seed, Rng::from_state(r.u64()?).ok_or(StateError::Invalid("random state"))?)
+        } else {
+            let seed = rand::random();
+            (seed, Rng::new(seed))
+        };
+
+        let (timing, frame_cycles) = if r.version() >= 3 {
+            let timing = match r.u8()? {
+                0 => Timing::Fixed,
+                1 => Timing::Vip,
+                _ => return Err(StateError::Invalid("timing")),
+            };
+            let frame_cycles = r.u64()?;
+            if frame_cycles >= VIP_FRAME_CYCLES { return Err(StateError::Invalid("frame cycles")); }
+            (timing, frame_cycles)
+        } else {
+            (Timing::Fixed, 0)
+        };
+        let ips = if r.version() >= 4 { r.u32()? } else { DEFAULT_IPS };
+        if ips == 0 { return Err(StateError::Invalid("instructions per second")); }
+
+        r.finish()?;
+
+        let sound = self.sound();
+        *self = System {
+            display,
+            keypad,
+
+            memory,
+            stack,
+            pc,
+            i,
+            v,
+
+            delay_timer,
+            sound_timer,
+
+            clock_dt,
+            timer_dt,
+            ips,
+            timing,
+            frame_cycles,
+
+            rpl,
+            rng,
+            seed,
+            audio_pattern: if has_audio_pattern { Some(audio_pattern) } else { None },
+            pitch,
+
+            quirks,
+            vblank_wait,
+            halted,
+            fault: None,
+            tracer: self.tracer.take(),
+
+            time: self.time,
+            sound_log: self.sound_log.take(),
+        };
+        self.log_sound(sound);
+
+        Ok(())
+    }
+
+    pub fn get_registers(&self) -> &[u8; 16] { &self.v }
+    pub fn get_i(&self) -> u16 { self.i.get() as u16 }
+    pub fn get_pc(&self) -> u16 { self.pc.get() as u16 }
+    /// Addresses of the `CALL`s being returned to, oldest first.
+    pub fn get_stack(&self) -> Vec<u16> { self.stack.iter().map(|addr| addr.get() as u16).collect() }
+    pub fn stack_depth(&self) -> usize { self.stack.len() }
+    pub fn get_delay_timer(&self) -> u8 { self.delay_timer }
+    pub fn get_sound_timer(&self) -> u8 { self.sound_timer }
+
+    /// `len` bytes of memory from `addr`, or `None` past the end.
+    pub fn read_memory(&self, addr: u16, len: u16) -> Option<&[u8]> {
+        self.memory.read(Address::new(addr), len)
+    }
+
+    /// Writes `data` at `addr` as a debugger would, without any checks. Returns
+    /// `None`, leaving memory untouched, if it runs past the end.
+    pub fn write_memory(&mut self, addr: u16, data: &[u8]) -> Option<()> {
+        self.memory.write(Address::new(addr), data)
+    }
+
+    /// Sets `Vx`. Returns `None` if there is no such register.
+    pub fn set_register(&mut self, x: u8, value: u8) -> Option<()> {
+        *self.v.get_mut(x as usize)? = value;
+        Some(())
+    }
+    pub fn set_i(&mut self, value: u16) { self.i = Address::new(value); }
+    pub fn set_pc(&mut self, value: u16) { self.pc = Address::new(value); }
+    pub fn set_delay_timer(&mut self, value: u8) { self.delay_timer = value; }
+    pub fn set_sound_timer(&mut self, value: u8) {
+        let sound = self.sound();
+        self.sound_timer = value;
+        self.log_sound(sound);
+    }
+
+    pub fn is_key_pressed(&self, key: Key) -> bool { self.keypad.is_pressed(key) }
+
+    /// True while a draw waits for the next timer tick, see [`Quirks::display_wait`].
+    pub fn is_waiting_for_display(&self) -> bool { self.vblank_wait }
+
+    /// True once the rom has run `EXIT`.
+    pub fn is_halted(&self) -> bool { self.halted }
+
+    /// The error the cpu stopped on, if any.
+    pub fn fault(&self) -> Option<&EmulatorError> { self.fault.as_ref() }
+
+    /// Records every instruction executed from now on, see [`crate::trace`].
+    /// Loading a state keeps the tracer.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) { self.tracer = tracer; }
+
+    /// Stops tracing, handing back the tracer to be finished.
+    pub fn take_tracer(&mut self) -> Option<Tracer> { self.tracer.take() }
+
+    pub fn update_keypad(&mut self, key: Key, pressed: bool) {
+        self.keypad.set_pressed(key, pressed);
+    }
+
+    pub fn quirks(&self) -> Quirks { self.quirks }
+
+    /// Instructions per second under [`Timing::Fixed`].
+    pub fn ips(&self) -> u32 { self.ips }
+    /// Sets the instructions per second, at least 1.
+    pub fn set_ips(&mut self, ips: u32) { self.ips = ips.max(1); }
+
+    pub fn timing(&self) -> Timing { self.timing }
+    /// Best set straight after booting, see [`crate::timing`].
+    pub fn set_timing(&mut self, timing: Timing) { self.timing = timing; }
+
+    /// Bytes of memory, 4 KiB or 64 KiB with [`Quirks::extended_memory`].
+    pub fn memory_size(&self) -> usize { self.memory.size() }
+
+    /// Restarts the random number generator, so runs with the same seed and
+    /// input are identical. Systems start with a random seed.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Rng::new(seed);
+    }
+
+    /// The seed last passed to [`System::set_seed`], or the random one.
+    pub fn seed(&self) -> u64 { self.seed }
+
+    /// Colour index of every visible pixel, see [`Screen`].
+    pub fn get_pixels(&self) -> Vec<&[u8]> { self.display.get_pixels() }
+    pub fn get_resolution(&self) -> (usize, usize) { (self.display.width(), self.display.height()) }
+    pub fn get_sound(&self) -> bool { self.sound_timer > 0 }
+    /// The XO-CHIP 1-bit audio pattern, if the rom has loaded one.
+    pub fn get_audio_pattern(&self) -> Option<&[u8; 16]> { self.audio_pattern.as_ref() }
+    /// Playback rate of the audio pattern in samples (bits) per second.
+    pub fn get_audio_rate(&self) -> f64 { 4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0) }
+
+    /// Emulated seconds since boot: `1 / ips` for every instruction,
+    /// including ones spent halted or waiting for the display, or the
+    /// cycles spent under [`Timing::Vip`]. Loading a state doesn't change it.
+    pub fn get_time(&self) -> f64 { self.time }
+
+    /// Starts or stops keeping a log of every change to the sound, stamped
+    /// with [`System::get_time`], for [`crate::audio::Audio`]. Loading a
+    /// state keeps the log.
+    pub fn record_sound(&mut self, record: bool) {
+        self.sound_log = record.then(|| self.sound_log.take().unwrap_or_default());
+    }
+
+    /// The sound changes since the last call, oldest first.
+    pub fn take_sound_changes(&mut self) -> Vec<SoundChange> {
+        self.sound_log.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn execute(&mut self, instruction: Instruction, hooks: &mut impl Hooks) -> Result<(), Fault> {
+        match instruction {
+            Instruction::ScrollDown(n) => self.display.scroll_down(n.into()),
+            Instruction::ScrollUp(n) => self.display.scroll_up(n.into()),
+            Instruction::Clear => self.display.clear(),
+            Instruction::Return => self.pc = self.stack.pop().ok_or(Fault::StackUnderflow)?,
+            Instruction::ScrollRight => self.display.scroll_right(4),
+            Instruction::ScrollLeft => self.display.scroll_left(4),
+            Instruction::Exit => self.halted = true,
+            Instruction::Lores => self.display.set_hires(false),
+            Instruction::Hires => self.display.set_hires(true),
+            Instruction::Jump(addr) => { self.pc = addr; return Ok(()); }
+            Instruction::Call(addr) => {
+                if self.stack.len() >= STACK_SIZE { return Err(Fault::StackOverflow); }
+                self.stack.push(self.pc);
+                self.pc = addr;
+                return Ok(());
+            }
+            Instruction::EqNum(reg, num) => {
+                if self.v[reg.idx()] == num { self.skip(); }
+            }
+            Instruction::NeqNum(reg, num) => {
+                if self.v[reg.idx()] != num { self.skip(); }
+            }
+            Instruction::Eq(reg_x, reg_y) => {
+                if self.v[reg_x.idx()] == self.v[reg_y.idx()] { self.skip(); }
+            }
+            Instruction::Neq(reg_x, reg_y) => {
+                if self.v[reg_x.idx()] != self.v[reg_y.idx()] { self.skip(); }
+            }
+            Instruction::SaveRange(reg_x, reg_y) => {
+                let range = Self::register_range(reg_x, reg_y);
+                let mut data = self.v[range].to_vec();
+                if reg_x.get() > reg_y.get() { data.reverse(); }
+                Self::write_data(&mut self.memory, hooks, self.i, &data)?;
+            }
+            Instruction::LoadRange(reg_x, reg_y) => {
+                let range = Self::register_range(reg_x, reg_y);
+                let len = range.len() as u16;
+                let mut data = Self::read_data(&self.memory, hooks, self.i, len)?.to_vec();
+                if reg_x.get() > reg_y.get() { data.reverse(); }
+                self.v[range].copy_from_slice(&data);
+            }
+            Instruction::SetNum(reg, num) => self.v[reg.idx()] = num,
+            Instruction::AddNum(reg, num) => {
+                self.v[reg.idx()] = self.v[reg.idx()].overflowing_add(num).0
+            }
+            Instruction::Move(reg_x, reg_y) => self.v[reg_x.idx()] = self.v[reg_y.idx()],
+            Instruction::Or(reg_x, reg_y) => {
+                self.v[reg_x.idx()] |= self.v[reg_y.idx()];
+                if self.quirks.vf_reset { self.v[0xF] = 0; }
+            }
+            Instruction::And(reg_x, reg_y) => {
+                self.v[reg_x.idx()] &= self.v[reg_y.idx()];
+                if self.quirks.vf_reset { self.v[0xF] = 0; }
+            }
+            Instruction::Xor(reg_x, reg_y) => {
+                self.v[reg_x.idx()] ^= self.v[reg_y.idx()];
+                if self.quirks.vf_reset { self.v[0xF] = 0; }
+            }
+            Instruction::Add(reg_x, reg_y) => {
+                let value1 = self.v[reg_x.idx()];
+                let value2 = self.v[reg_y.idx()];
+
+                let (result, overflow) = value1.overflowing_add(value2);
+
+                self.v[reg_x.idx()] = result;
+                self.v[0xF] = overflow as u8;
+            }
+            Instruction::Sub(reg_x, reg_y) => {
+                let value1 = self.v[reg_x.idx()];
+                let value2 = self.v[reg_y.idx()];
+
+                let (result, overflow) = value1.overflowing_sub(value2);
+
+                self.v[reg_x.idx()] = result;
+                self.v[0xF] = !overflow as u8;
+            }
+            Instruction::Shr(reg_x, reg_y) => {
+                let value = if self.quirks.shift_uses_vy { self.v[reg_y.idx()] } else { self.v[reg_x.idx()] };
+                self.v[reg_x.idx()] = value >> 1;
+                self.v[0xF] = value & 1;
+            }
+            Instruction::Subb(reg_x, reg_y) => {
+                let value1 = self.v[reg_y.idx()];
+                let value2 = self.v[reg_x.idx()];
+
+                let (result, overflow) = value1.overflowing_sub(value2);
+
+                self.v[reg_x.idx()] = result;
+                self.v[0xF] = !overflow as u8;
+            }
+            Instruction::Shl(reg_x, reg_y) => {
+                let value = if self.quirks.shift_uses_vy { self.v[reg_y.idx()] } else { self.v[reg_x.idx()] };
+                self.v[reg_x.idx()] = value << 1;
+                self.v[0xF] = (value & 0x80) >> 7;
+            }
+            Instruction::SetIdx(addr) => self.i = addr,
+            Instruction::JumpV0(addr) => {
+                let reg = if self.quirks.jump_uses_vx { (addr.get() >> 8) & 0xF } else { 0 };
+                self.pc = addr.add(self.v[reg] as u16);
+                return Ok(());
+            }
+            Instruction::Rand(reg, num) => {
+                self.v[reg.idx()] = self.rng.next_u8() & num;
+            }
+            Instruction::Draw(reg_x, reg_y, size) => {
+                let x = self.v[reg_x.idx()];
+                let y = self.v[reg_y.idx()];
+                let len = if size == 0 { 32 } else { size as u16 };
+                let len = len * self.display.plane_count() as u16;
+                let sprite = Self::read_data(&self.memory, hooks, self.i, len)?;
+                let collision = if size == 0 {
+                    self.display.draw_wide(x, y, sprite, self.quirks.clip_sprites)
+                } else {
+                    self.display.draw(x, y, sprite, self.quirks.clip_sprites)
+                };
+                self.v[0xF] = collision as u8;
+                if self.quirks.display_wait { self.vblank_wait = true; }
+            }
+            // Like the VIP, only the low nibble of VX picks the key.
+            Instruction::KeyUp(reg) => {
+                let key = Key::new(self.v[reg.idx()] & 0xF);
+                if self.keypad.is_pressed(key) {
+                    self.skip();
+                }
+            }
+            Instruction::KeyDown(reg) => {
+                let key = Key::new(self.v[reg.idx()] & 0xF);
+                if !self.keypad.is_pressed(key) {
+                    self.skip();
+                }
+            }
+            Instruction::LongIdx(addr) => self.i = addr,
+            Instruction::Plane(n) => self.display.set_planes(n),
+            Instruction::Audio => {
+                let data = Self::read_data(&self.memory, hooks, self.i, 16)?;
+                self.audio_pattern = Some(data.try_into().unwrap());
+            }
+            Instruction::GetDelay(reg) => self.v[reg.idx()] = self.delay_timer,
+            Instruction::WaitKey(reg) => {
+                if let Some(key) = self.keypad.get_key() {
+                    self.v[reg.idx()] = key.get();
+                } else {
+                    self.pc = self.pc.sub(2);
+                }
+            }
+            Instruction::SetDelay(reg) => self.delay_timer = self.v[reg.idx()],
+            Instruction::SetSound(reg) => self.sound_timer = self.v[reg.idx()],
+            Instruction::AddIdx(reg) => self.i = self.i.add(self.v[reg.idx()].into()),
+            Instruction::SetSprite(reg) => {
+                self.i = (FONT_START + (self.v[reg.idx()] & 0xF) as u16 * 5).into();
+            }
+            Instruction::SetBigSprite(reg) => {
+                self.i = (BIG_FONT_START + (self.v[reg.idx()] & 0xF) as u16 * 10).into();
+            }
+            Instruction::Pitch(reg) => self.pitch = self.v[reg.idx()],
+            Instruction::StoreBcd(reg) => {
+                let value = self.v[reg.idx()];
+                Self::write_data(&mut self.memory, hooks, self.i, &[value / 100, (value / 10) % 10, value % 10])?;
+            }
+            Instruction::Store(reg) => {
+                Self::write_data(&mut self.memory, hooks, self.i, &self.v[..=reg.idx()])?;
+                if self.quirks.load_store_increment_i {
+                    self.i = self.i.add(1 + reg.get() as u16);
+                }
+            }
+            Instruction::Load(reg) => {
+                let data = Self::read_data(&self.memory, hooks, self.i, 1 + reg.get() as u16)?;
+                self.v[..=reg.idx()].copy_from_slice(data);
+                if self.quirks.load_store_increment_i {
+                    self.i = self.i.add(1 + reg.get() as u16);
+                }
+            }
+            Instruction::SaveFlags(reg) => self.rpl[..=reg.idx()].copy_from_slice(&self.v[..=reg.idx()]),
+            Instruction::LoadFlags(reg) => self.v[..=reg.idx()].copy_from_slice(&self.rpl[..=reg.idx()]),
+        }
+
+        self.pc = self.pc.add(instruction.size());
+        Ok(())
+    }
+
+    /// Reads data for an instruction, telling `hooks` first.
+    fn read_data<'a>(memory: &'a Memory, hooks: &mut impl Hooks, addr: Address, len: u16) -> Result<&'a [u8], Fault> {
+        hooks.read(addr.get() as u16, len);
+        memory.read(addr, len).ok_or(Fault::MemoryOutOfRange(addr, len))
+    }
+
+    /// Writes data for an instruction, telling `hooks` first.
+    fn write_data(memory: &mut Memory, hooks: &mut impl Hooks, addr: Address, data: &[u8]) -> Result<(), Fault> {
+        hooks.write(addr.get() as u16, data.len() as u16);
+        memory.write(addr, data).ok_or(Fault::MemoryOutOfRange(addr, data.len() as u16))
+    }
+
+    /// What the sound is now, if anyone is listening.
+    fn sound(&self) -> Option<Sound> {
+        self.sound_log.as_ref().map(|_| Sound::of(self))
+    }
+
+    /// Logs the sound if it is different from `before`.
+    fn log_sound(&mut self, before: Option<Sound>) {
+        let Some(before) = before else { return };
+        let sound = Sound::of(self);
+        if let (true, Some(log)) = (sound != before, &mut self.sound_log) {
+            log.push(SoundChange { time: self.time, sound });
+        }
+    }
+
+    /// Spends VIP machine cycles, running the display interrupt whenever a
+    /// frame's worth have passed.
+    fn run_cycles(&mut self, cycles: u64) {
+        self.time += cycles as f64 / VIP_CYCLE_HZ;
+        self.frame_cycles += cycles;
+        while self.frame_cycles >= VIP_FRAME_CYCLES {
+            self.frame_cycles -= VIP_FRAME_CYCLES;
+            self.tick_timers();
+            self.time += VIP_INTERRUPT_CYCLES as f64 / VIP_CYCLE_HZ;
+            self.frame_cycles += VIP_INTERRUPT_CYCLES;
+        }
+    }
+
+    /// Spends the rest of the frame waiting for the display interrupt.
+    fn wait_for_interrupt(&mut self) {
+        self.run_cycles(VIP_FRAME_CYCLES - self.frame_cycles);
+    }
+
+    /// Skips the next instruction, which may be the 4 byte `F000 NNNN`.
+    fn skip(&mut self) {
+        let next = Instruction::decode(self.memory.read16(self.pc.add(2)), self.memory.read16(self.pc.add(4)));
+        self.pc = self.pc.add(next.map_or(2, |instruction| instruction.size()));
+    }
+
+    /// The registers from X to Y, in either direction.
+    fn register_range(reg_x: Register, reg_y: Register) -> std::ops::Range<usize> {
+        reg_x.idx().min(reg_y.idx())..reg_x.idx().max(reg_y.idx()) + 1
+    }
+}
+
+/// An [`EmulatorError`] before the faulting pc and opcode are known.
+enum Fault {
+    InvalidOpcode,
+    StackUnderflow,
+    StackOverflow,
+    MemoryOutOfRange(Address, u16),
+}
+
+impl Fault {
+    fn into_error(self, pc: Address, opcode: u16) -> EmulatorError {
+        let pc = pc.get() as u16;
+        match self {
+            Fault::InvalidOpcode => EmulatorError::InvalidOpcode { pc, opcode },
+            Fault::StackUnderflow => EmulatorError::StackUnderflow { pc, opcode },
+            Fault::StackOverflow => EmulatorError::StackOverflow { pc, opcode },
+            Fault::MemoryOutOfRange(addr, len) =>
+                EmulatorError::MemoryOutOfRange { pc, opcode, addr: addr.get() as u16, len },
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! How long instructions take.
+//!
+//! By default every instruction takes `1 / ips` seconds and the timers
+//! tick at 60hz on their own. [`Timing::Vip`] instead charges each
+//! instruction what it cost the interpreter on the COSMAC VIP, in machine
+//! cycles of its 1802 cpu, and runs the timers from the 60hz display
+//! interrupt, which also steals the cycles the video chip spends reading the
+//! screen. Draws wait for that interrupt when [`Quirks::display_wait`] is on.
+//!
+//! The costs are approximations of the interpreter's routines, close enough
+//! that games run at the speed they were written for.
+//!
+//! [`Quirks::display_wait`]: crate::quirks::Quirks::display_wait
+
+use crate::cpu::Instruction;
+
+/// Machine cycles per second: the VIP's 1.76064 MHz clock over the 8 clocks
+/// of a machine cycle.
+pub const VIP_CYCLE_HZ: f64 = 220_080.0;
+/// Machine cycles between display interrupts.
+pub const VIP_FRAME_CYCLES: u64 = 3668;
+/// Machine cycles each interrupt takes from the interpreter: the interrupt
+/// routine, and DMA of the 256 byte screen, each row shown 4 times.
+pub const VIP_INTERRUPT_CYCLES: u64 = 1024 + 22;
+
+// Fetching and dispatching an instruction, which every instruction pays.
+const FETCH: u64 = 20;
+// Extra cost of a skip that skips.
+const SKIP: u64 = 4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Timing {
+    /// [`System::ips`] instructions a second, whatever they are.
+    ///
+    /// [`System::ips`]: crate::System::ips
+    #[default]
+    Fixed,
+    /// Instructions take as long as on the COSMAC VIP.
+    Vip,
+}
+
+impl Timing {
+    /// Names accepted by [`Timing::from_name`].
+    pub const NAMES: [&'static str; 2] = ["fixed", "vip"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace(['-', '_', '.'], "").as_str() {
+            "fixed"             => Some(Self::Fixed),
+            "vip" | "cosmacvip" => Some(Self::Vip),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Fixed => "fixed",
+            Self::Vip => "vip",
+        }
+    }
+}
+
+/// Machine cycles `instruction` takes on the VIP, given the registers `v`
+/// it started with and whether it skipped the next instruction. Waiting for
+/// the display isn't included.
+pub fn vip_cycles(instruction: Instruction, v: &[u8; 16], skipped: bool) -> u64 {
+    let skip = if skipped { SKIP } else { 0 };
+    let cost = match instruction {
+        // Zeroes the screen a byte at a time.
+        Instruction::Clear => 12 + 256 * 6,
+        Instruction::Return => 10,
+        Instruction::Jump(_) => 10,
+        Instruction::Call(_) => 16,
+        Instruction::EqNum(..) | Instruction::NeqNum(..) => 8 + skip,
+        Instruction::Eq(..) | Instruction::Neq(..) => 12 + skip,
+        Instruction::SetNum(..) => 4,
+        Instruction::AddNum(..) => 8,
+        // The arithmetic runs as a small routine built in ram.
+        Instruction::Move(..) | Instruction::Or(..) | Instruction::And(..) | Instruction::Xor(..)
+        | Instruction::Add(..) | Instruction::Sub(..) | Instruction::Shr(..) | Instruction::Subb(..)
+        | Instruction::Shl(..) => 28,
+        Instruction::SetIdx(_) => 8,
+        Instruction::JumpV0(_) => 18,
+        Instruction::Rand(..) => 32,
+        Instruction::Draw(x, _, height) => {
+            // A sprite at a multiple of 8 covers one byte per row. Anywhere
+            // else each row is shifted into two, a bit at a time.
+            let shift = v[x.idx()] as u64 % 8;
+            let row = if shift == 0 { 22 } else { 40 + 8 * shift };
+            let (rows, bytes) = if height == 0 { (16, 2) } else { (height as u64, 1) };
+            26 + rows * bytes * row
+        }
+        Instruction::KeyUp(_) | Instruction::KeyDown(_) => 14 + skip,
+        Instruction::GetDelay(_) | Instruction::SetDelay(_) | Instruction::SetSound(_) => 8,
+        // Paid again every time it checks the keypad.
+        Instruction::WaitKey(_) => 16,
+        Instruction::AddIdx(_) => 16,
+        Instruction::SetSprite(_) => 16,
+        // Counts down the hundreds, tens and ones one at a time.
+        Instruction::StoreBcd(x) => {
+            let value = v[x.idx()] as u64;
+            24 + 8 * (value / 100 + value / 10 % 10 + value % 10)
+        }
+        Instruction::Store(x) | Instruction::Load(x) => 14 + 12 * (x.idx() as u64 + 1),
+        // Instructions the VIP never had, at the cost of a simple one.
+        _ => 8,
+    };
+    FETCH + cost
+}
@@ -0,0 +1,318 @@
+//! Execution traces: every instruction the cpu runs, with the registers
+//! before and after it, written to a compact binary file.
+//!
+//! A trace starts with the magic `CH8T` and a little endian `u32` version,
+//! followed by one record per instruction:
+//!
+//! - the cycle, as a LEB128 varint counting up from the previous record
+//! - the PC and opcode as little endian `u16`s
+//! - a flags byte: 1 if a second opcode word follows (XO-CHIP `LONG`), 2, 4
+//!   and 8 if I, DT and ST changed
+//! - the second opcode word, if any
+//! - V0-VF, I, DT and ST before the instruction
+//! - a `u16` mask of the V registers that changed, then their new values
+//! - the new I, DT and ST, each only if it changed
+//!
+//! That is about 30 bytes per instruction, against about 100 for the line
+//! of text [`render`] makes of it.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::cpu::Instruction;
+use crate::system::System;
+
+pub const MAGIC: &[u8; 4] = b"CH8T";
+pub const VERSION: u32 = 1;
+
+const LONG: u8 = 1;
+const I_CHANGED: u8 = 2;
+const DT_CHANGED: u8 = 4;
+const ST_CHANGED: u8 = 8;
+
+/// The registers a trace records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl Registers {
+    pub fn of(system: &System) -> Self {
+        Registers {
+            v: *system.get_registers(),
+            i: system.get_i(),
+            delay_timer: system.get_delay_timer(),
+            sound_timer: system.get_sound_timer(),
+        }
+    }
+}
+
+/// One executed instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    /// Instructions executed before this one since tracing started.
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    /// The word after `opcode`, for instructions that are two words long.
+    pub operand: Option<u16>,
+    pub before: Registers,
+    pub after: Registers,
+}
+
+impl Record {
+    pub fn instruction(&self) -> Option<Instruction> {
+        Instruction::decode(self.opcode, self.operand.unwrap_or(0))
+    }
+}
+
+/// Writes `cycle pc: opcode mnemonic`, the registers before, and what changed.
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = self.instruction().map_or("?".to_string(), |instruction| instruction.to_string());
+        let opcode = match self.operand {
+            Some(operand) => format!("{:04X}{:04X}", self.opcode, operand),
+            None => format!("{:04X}    ", self.opcode),
+        };
+        let (before, after) = (&self.before, &self.after);
+
+        write!(f, "{:>10} {:03X}: {} {:<20} V=", self.cycle, self.pc, opcode, mnemonic)?;
+        for value in before.v {
+            write!(f, "{:02X}", value)?;
+        }
+        write!(f, " I={:03X} DT={:02X} ST={:02X}", before.i, before.delay_timer, before.sound_timer)?;
+
+        let mut arrow = " ->";
+        let mut change = |f: &mut fmt::Formatter<'_>, text: String| {
+            let result = write!(f, "{} {}", arrow, text);
+            arrow = "";
+            result
+        };
+        for x in 0..16 {
+            if before.v[x] != after.v[x] { change(f, format!("V{:X}={:02X}", x, after.v[x]))?; }
+        }
+        if before.i != after.i { change(f, format!("I={:03X}", after.i))?; }
+        if before.delay_timer != after.delay_timer { change(f, format!("DT={:02X}", after.delay_timer))?; }
+        if before.sound_timer != after.sound_timer { change(f, format!("ST={:02X}", after.sound_timer))?; }
+        Ok(())
+    }
+}
+
+/// Which instructions to keep. Every part that is set has to match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    pub addresses: Option<RangeInclusive<u16>>,
+    /// Bit n keeps opcodes whose top nibble is n, so `1 << 0xD` keeps draws.
+    pub opcode_classes: Option<u16>,
+    pub cycles: Option<RangeInclusive<u64>>,
+}
+
+impl Filter {
+    pub fn matches(&self, cycle: u64, pc: u16, opcode: u16) -> bool {
+        self.addresses.as_ref().is_none_or(|addresses| addresses.contains(&pc))
+            && self.opcode_classes.is_none_or(|classes| classes & 1 << (opcode >> 12) != 0)
+            && self.cycles.as_ref().is_none_or(|cycles| cycles.contains(&cycle))
+    }
+}
+
+/// Records the instructions a [`System`] runs, see [`System::set_tracer`].
+pub struct Tracer {
+    output: Box<dyn Write + Send>,
+    filter: Filter,
+    cycle: u64,
+    // Cycle of the last record written, which the next one counts up from.
+    written: u64,
+    // The first write error. Tracing stops there, and `finish` returns it.
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new(output: impl Write + Send + 'static, filter: Filter) -> io::Result<Self> {
+        let mut output: Box<dyn Write + Send> = Box::new(output);
+        output.write_all(MAGIC)?;
+        output.write_all(&VERSION.to_le_bytes())?;
+        Ok(Tracer { output, filter, cycle: 0, written: 0, error: None })
+    }
+
+    /// Traces to a new file at `path`.
+    pub fn create(path: impl AsRef<Path>, filter: Filter) -> io::Result<Self> {
+        Tracer::new(BufWriter::new(File::create(path)?), filter)
+    }
+
+    /// Counts an executed instruction, writing it if the filter keeps it.
+    pub(crate) fn record(&mut self, pc: u16, opcode: u16, operand: Option<u16>, before: &Registers, after: &Registers) {
+        let cycle = self.cycle;
+        self.cycle += 1;
+        if self.error.is_some() || !self.filter.matches(cycle, pc, opcode) { return; }
+
+        let record = Record { cycle, pc, opcode, operand, before: *before, after: *after };
+        let bytes = encode(&record, self.written);
+        self.written = cycle;
+        if let Err(err) = self.output.write_all(&bytes) {
+            self.error = Some(err);
+        }
+    }
+
+    /// Flushes the trace, or returns the error that stopped it.
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.output.flush(),
+        }
+    }
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer").field("filter", &self.filter).field("cycle", &self.cycle).finish_non_exhaustive()
+    }
+}
+
+fn encode(record: &Record, previous: u64) -> Vec<u8> {
+    let (before, after) = (&record.before, &record.after);
+    let mut bytes = Vec::with_capacity(40);
+
+    let mut delta = record.cycle - previous;
+    loop {
+        let low = (delta & 0x7F) as u8;
+        delta >>= 7;
+        if delta == 0 {
+            bytes.push(low);
+            break;
+        }
+        bytes.push(low | 0x80);
+    }
+
+    bytes.extend(record.pc.to_le_bytes());
+    bytes.extend(record.opcode.to_le_bytes());
+    let flags = record.operand.map_or(0, |_| LONG)
+        | if before.i != after.i { I_CHANGED } else { 0 }
+        | if before.delay_timer != after.delay_timer { DT_CHANGED } else { 0 }
+        | if before.sound_timer != after.sound_timer { ST_CHANGED } else { 0 };
+    bytes.push(flags);
+    if let Some(operand) = record.operand {
+        bytes.extend(operand.to_le_bytes());
+    }
+
+    bytes.extend(before.v);
+    bytes.extend(before.i.to_le_bytes());
+    bytes.extend([before.delay_timer, before.sound_timer]);
+
+    let changed = (0..16).filter(|&x| before.v[x] != after.v[x]).fold(0u16, |mask, x| mask | 1 << x);
+    bytes.extend(changed.to_le_bytes());
+    bytes.extend((0..16).filter(|&x| changed & 1 << x != 0).map(|x| after.v[x]));
+    if flags & I_CHANGED != 0 { bytes.extend(after.i.to_le_bytes()); }
+    if flags & DT_CHANGED != 0 { bytes.push(after.delay_timer); }
+    if flags & ST_CHANGED != 0 { bytes.push(after.sound_timer); }
+    bytes
+}
+
+/// Reads the records of a trace back. It reads a byte at a time, so files
+/// want a `BufReader`.
+pub struct TraceReader<R> {
+    input: R,
+    cycle: u64,
+}
+
+impl<R: Read> TraceReader<R> {
+    /// Checks the header, leaving the reader at the first record.
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut header = [0; 8];
+        input.read_exact(&mut header).map_err(|_| invalid("not a chip8 trace"))?;
+        if &header[..4] != MAGIC {
+            return Err(invalid("not a chip8 trace"));
+        }
+        let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid(&format!("trace version {} is not supported (expected {})", version, VERSION)));
+        }
+        Ok(TraceReader { input, cycle: 0 })
+    }
+
+    /// The next record, or `None` at the end of the trace.
+    pub fn next_record(&mut self) -> io::Result<Option<Record>> {
+        let mut delta = 0u64;
+        for shift in (0..64).step_by(7) {
+            let mut byte = [0];
+            if self.input.read(&mut byte)? == 0 {
+                return match shift {
+                    0 => Ok(None),
+                    _ => Err(invalid("trace is truncated")),
+                };
+            }
+            delta |= ((byte[0] & 0x7F) as u64) << shift;
+            if byte[0] & 0x80 == 0 { break; }
+        }
+        self.cycle += delta;
+
+        self.read_record().map(Some).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => invalid("trace is truncated"),
+            _ => err,
+        })
+    }
+
+    fn read_record(&mut self) -> io::Result<Record> {
+        let pc = self.u16()?;
+        let opcode = self.u16()?;
+        let flags = self.u8()?;
+        let operand = if flags & LONG != 0 { Some(self.u16()?) } else { None };
+
+        let mut before = Registers::default();
+        self.input.read_exact(&mut before.v)?;
+        before.i = self.u16()?;
+        before.delay_timer = self.u8()?;
+        before.sound_timer = self.u8()?;
+
+        let mut after = before;
+        let changed = self.u16()?;
+        for x in (0..16).filter(|&x| changed & 1 << x != 0) {
+            after.v[x] = self.u8()?;
+        }
+        if flags & I_CHANGED != 0 { after.i = self.u16()?; }
+        if flags & DT_CHANGED != 0 { after.delay_timer = self.u8()?; }
+        if flags & ST_CHANGED != 0 { after.sound_timer = self.u8()?; }
+
+        Ok(Record { cycle: self.cycle, pc, opcode, operand, before, after })
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        let mut bytes = [0];
+        self.input.read_exact(&mut bytes)?;
+        Ok(bytes[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let mut bytes = [0; 2];
+        self.input.read_exact(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+/// Writes a trace as text, one line per record that `filter` keeps.
+pub fn render(input: impl Read, mut output: impl Write, filter: &Filter) -> io::Result<()> {
+    for record in TraceReader::new(BufReader::new(input))? {
+        let record = record?;
+        if filter.matches(record.cycle, record.pc, record.opcode) {
+            writeln!(output, "{}", record)?;
+        }
+    }
+    output.flush()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
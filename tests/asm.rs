@@ -0,0 +1,72 @@
+//! Disassembling and reassembling must give back the exact same bytes.
+
+use std::fs;
+
+use chip8::{asm, disasm, Instruction};
+
+#[test]
+fn encode_inverts_decode() {
+    for word in 0..=0xFFFF {
+        if let Some(instruction) = Instruction::new(word) {
+            assert_eq!(instruction.encode(), word, "{}", instruction);
+        }
+    }
+}
+
+#[test]
+fn roms_round_trip() {
+    for entry in fs::read_dir("rom").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|extension| extension != "ch8") { continue; }
+
+        let rom = fs::read(&path).unwrap();
+        let listing = disasm::disassemble(&rom, 0x200).to_string();
+        let assembled = asm::assemble(&listing).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+        assert!(assembled == rom, "{} did not round trip", path.display());
+    }
+}
+
+#[test]
+fn labels_constants_and_data() {
+    let source = "
+        SPEED EQU ball - start + 1   ; forward references are fine
+            ORG $300
+        start:
+            MOV I, LONG ball
+            JUMP V0 + start
+            ADD V15, SPEED
+        ball: db %01100000, $F0, -1
+            dw start + 2
+    ";
+    assert_eq!(asm::assemble(source).unwrap(), [
+        0xF0, 0x00, 0x03, 0x08,
+        0xB3, 0x00,
+        0x7F, 0x09,
+        0x60, 0xF0, 0xFF,
+        0x03, 0x02,
+    ]);
+
+    let err = asm::assemble("    MOV V1, 300").unwrap_err();
+    assert_eq!(err.to_string(), "<source>:1: byte 300 is out of range");
+}
+
+#[test]
+fn source_map_follows_includes() {
+    let dir = std::env::temp_dir().join(format!("chip8-asm-map-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("main.asm"), "start:\n    CLS\n    INCLUDE \"sub.asm\"\n    JUMP start\n").unwrap();
+    fs::write(dir.join("sub.asm"), "; nothing on line 1\n    db 1, 2, 3\n").unwrap();
+
+    let (rom, map) = asm::assemble_file_with_map(dir.join("main.asm")).unwrap();
+    let main = dir.join("main.asm").display().to_string();
+    let sub = dir.join("sub.asm").display().to_string();
+
+    assert_eq!(rom.len(), 7);
+    assert_eq!(map.location(0x200), Some((main.as_str(), 2)));
+    assert_eq!(map.location(0x204), Some((sub.as_str(), 2)));
+    assert_eq!(map.location(0x205), Some((main.as_str(), 4)));
+    assert_eq!(map.location(0x207), None);
+    assert_eq!(map.address(&main, 3), Some((0x205, 4)));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,105 @@
+//! The beeper, played into a WAV sink.
+
+use chip8::audio::{self, Audio, AudioSink, Beeper, NullSink, Sound, WavSink, Waveform};
+use chip8::headless::{self, RunLength};
+use chip8::{octo, Schedule, System};
+
+const RATE: u32 = 50000;
+
+const BEEP: Sound = Sound { playing: true, pattern: None };
+
+#[test]
+fn beeps_on_the_samples_the_sound_timer_runs() {
+    // Instructions take 1/500s, so buzzer := v0 finishes at 0.006s. The
+    // timers tick after every 8.33 instructions, reaching 0 after the 17th.
+    let source = "
+        : main
+            v0 := 2
+            buzzer := v0
+            loop again
+    ";
+    let mut system = System::new(&octo::compile(source).unwrap()).unwrap();
+    let mut audio = Audio::new(Beeper::new(1000.0, 0.5, Waveform::Square), WavSink::new(RATE), &mut system);
+    headless::run(&mut system, RunLength::Cycles(25), Schedule::default(), &[]).unwrap();
+    audio.update(&mut system);
+
+    let samples = audio.sink().samples();
+    assert_eq!(samples.len(), 2500);
+    let beeping: Vec<_> = samples.iter().map(|&sample| sample != 0.0).collect();
+    assert_eq!(beeping.iter().position(|&on| on), Some(300));
+    assert_eq!(beeping.iter().rposition(|&on| on), Some(1699));
+    assert!(beeping[300..1700].iter().all(|&on| on));
+    assert_eq!(samples[300..310], [0.5; 10]);
+    assert_eq!(samples[325..335], [-0.5; 10]);
+}
+
+#[test]
+fn follows_loaded_states() {
+    let mut system = System::new(&octo::compile(": main loop again").unwrap()).unwrap();
+    let silent = system.save_state();
+    system.set_sound_timer(10);
+    let mut audio = Audio::new(Beeper::default(), WavSink::new(RATE), &mut system);
+
+    headless::run(&mut system, RunLength::Cycles(5), Schedule::default(), &[]).unwrap();
+    system.load_state(&silent).unwrap();
+    headless::run(&mut system, RunLength::Cycles(5), Schedule::default(), &[]).unwrap();
+    audio.update(&mut system);
+
+    let samples = audio.sink().samples();
+    assert_eq!(samples.len(), 1000);
+    assert!(samples[..500].iter().all(|&sample| sample != 0.0));
+    assert!(samples[500..].iter().all(|&sample| sample == 0.0));
+}
+
+#[test]
+fn waveforms() {
+    let levels = |waveform| {
+        let mut samples = [0.0; 4];
+        Beeper::new(RATE as f64 / 4.0, 1.0, waveform).fill(&BEEP, &mut samples, RATE);
+        samples.map(|sample| (sample * 100.0).round() / 100.0)
+    };
+
+    assert_eq!(levels(Waveform::Square), [1.0, 1.0, -1.0, -1.0]);
+    assert_eq!(levels(Waveform::Triangle), [-1.0, 0.0, 1.0, 0.0]);
+    assert_eq!(levels(Waveform::Sawtooth), [-1.0, -0.5, 0.0, 0.5]);
+    assert_eq!(levels(Waveform::Sine), [0.0, 1.0, 0.0, -1.0]);
+    assert_eq!(Waveform::from_name("Saw"), Some(Waveform::Sawtooth));
+}
+
+#[test]
+fn plays_the_xo_chip_pattern() {
+    // The default pitch plays the pattern's 128 bits at 4000 a second.
+    let source = "
+        : pattern 0xF0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+        : main
+            i := pattern
+            audio
+            v0 := 2
+            buzzer := v0
+            loop again
+    ";
+    let mut system = System::new(&octo::compile(source).unwrap()).unwrap();
+    headless::run(&mut system, RunLength::Cycles(10), Schedule::default(), &[]).unwrap();
+
+    let mut samples = [0.0; 6];
+    Beeper::new(1000.0, 1.0, Waveform::Square).fill(&Sound::of(&system), &mut samples, 4000);
+    assert_eq!(samples, [1.0, 1.0, 1.0, 1.0, -1.0, -1.0]);
+}
+
+#[test]
+fn writes_wav_files() {
+    let mut sink = WavSink::new(RATE);
+    sink.play(&[0.0, 1.0, -1.0, 2.0]);
+    let wav = sink.to_wav();
+
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 36 + 8);
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), RATE);
+    assert_eq!(&wav[36..40], b"data");
+    let samples: Vec<_> = wav[44..].chunks(2).map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]])).collect();
+    assert_eq!(samples, [0, 32767, -32767, 32767]);
+
+    assert_eq!(audio::wav(&[], RATE).len(), 44);
+    assert_eq!(NullSink.sample_rate(), audio::SAMPLE_RATE);
+}
@@ -0,0 +1,34 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use chip8::System;
+
+/// Assembles raw opcodes into a rom, big endian.
+pub fn rom(opcodes: &[u16]) -> Vec<u8> {
+    opcodes.iter().flat_map(|opcode| opcode.to_be_bytes()).collect()
+}
+
+/// Draws the byte at each address as a row, starting from the top left.
+pub fn peek(addrs: &[u16]) -> Vec<u16> {
+    let mut opcodes = vec![0x6D00];
+    for (row, &addr) in addrs.iter().enumerate() {
+        opcodes.extend([0xA000 | addr, 0x6E00 | row as u16, 0xDDE1]);
+    }
+    opcodes
+}
+
+/// Stores V0-VF at 0x400, then draws the given registers as rows.
+pub fn show(regs: &[u16]) -> Vec<u16> {
+    let mut opcodes = vec![0xA400, 0xFF55];
+    opcodes.extend(peek(&regs.iter().map(|reg| 0x400 + reg).collect::<Vec<_>>()));
+    opcodes
+}
+
+/// The top rows of the screen, eight pixels wide.
+pub fn rows(system: &System, count: usize) -> Vec<u8> {
+    let pixels = system.get_pixels();
+    (0..count)
+        .map(|y| (0..8).fold(0, |byte, x| byte << 1 | (pixels[x][y] != 0) as u8))
+        .collect()
+}
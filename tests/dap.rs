@@ -0,0 +1,223 @@
+//! Debugs an Octo program through the debug adapter over a loopback socket.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::{env, fs, thread};
+
+use chip8::dap;
+use chip8::json::Json;
+
+// Lines are numbered from 1, sub is at $202 and main at $206.
+const SOURCE: &str = ": sub
+    v1 += 1
+    return
+: main
+    v0 += 1
+    sub
+    jump main
+";
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    seq: i64,
+    events: VecDeque<Json>,
+}
+
+impl Client {
+    fn connect() -> Client {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            stream.set_nodelay(true).unwrap();
+            dap::serve(stream.try_clone().unwrap(), stream).unwrap();
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        Client { reader: BufReader::new(stream.try_clone().unwrap()), writer: stream, seq: 0, events: VecDeque::new() }
+    }
+
+    /// Sends a request and returns the body of its successful response.
+    fn request(&mut self, command: &str, arguments: Json) -> Json {
+        let message = self.respond(command, arguments);
+        assert_eq!(message.get("success"), Some(&Json::Bool(true)), "{}", message);
+        message.get("body").cloned().unwrap_or(Json::Null)
+    }
+
+    /// Sends a request that has to fail, and returns the error message.
+    fn fail(&mut self, command: &str, arguments: Json) -> String {
+        let message = self.respond(command, arguments);
+        assert_eq!(message.get("success"), Some(&Json::Bool(false)), "{}", message);
+        message.get("message").and_then(Json::as_str).unwrap().to_string()
+    }
+
+    /// Sends a request and returns its response, queueing any events before it.
+    fn respond(&mut self, command: &str, arguments: Json) -> Json {
+        self.seq += 1;
+        let text = Json::object([
+            ("seq", self.seq.into()),
+            ("type", "request".into()),
+            ("command", command.into()),
+            ("arguments", arguments),
+        ]).to_string();
+        write!(self.writer, "Content-Length: {}\r\n\r\n{}", text.len(), text).unwrap();
+
+        loop {
+            let message = self.receive();
+            if message.get("type").and_then(Json::as_str) == Some("event") {
+                self.events.push_back(message);
+                continue;
+            }
+            assert_eq!(message.get("request_seq").and_then(Json::as_i64), Some(self.seq));
+            return message;
+        }
+    }
+
+    /// Waits for the next event, which has to be `event`, and returns its body.
+    fn event(&mut self, event: &str) -> Json {
+        let message = self.events.pop_front().unwrap_or_else(|| self.receive());
+        assert_eq!(message.get("event").and_then(Json::as_str), Some(event), "{}", message);
+        message.get("body").cloned().unwrap_or(Json::Null)
+    }
+
+    fn receive(&mut self) -> Json {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            match line.trim_end().split_once(": ") {
+                Some(("Content-Length", value)) => length = value.parse().unwrap(),
+                _ => break,
+            }
+        }
+        let mut body = vec![0; length];
+        self.reader.read_exact(&mut body).unwrap();
+        Json::parse(&String::from_utf8(body).unwrap()).unwrap()
+    }
+}
+
+fn top_frame(client: &mut Client) -> (i64, Vec<Json>) {
+    let trace = client.request("stackTrace", Json::object([("threadId", Json::from(1i64))]));
+    let frames = trace.get("stackFrames").and_then(Json::as_array).unwrap().to_vec();
+    (frames[0].get("line").and_then(Json::as_i64).unwrap(), frames)
+}
+
+#[test]
+fn source_breakpoints_stepping_and_memory() {
+    let path = env::temp_dir().join(format!("chip8-dap-{}.8o", std::process::id()));
+    fs::write(&path, SOURCE).unwrap();
+    let path = path.to_str().unwrap().to_string();
+    let source = || Json::object([("path", Json::from(path.as_str()))]);
+    let mut client = Client::connect();
+
+    let capabilities = client.request("initialize", Json::object([("adapterID", Json::from("chip8"))]));
+    assert_eq!(capabilities.get("supportsReadMemoryRequest"), Some(&Json::Bool(true)));
+    client.request("launch", Json::object([("program", Json::from(path.as_str()))]));
+    client.event("initialized");
+
+    // Line 4 has no code, so the breakpoint moves down to line 5.
+    let lines = vec![Json::object([("line", Json::from(4usize))]), Json::object([("line", Json::from(6usize))])];
+    let reply = client.request("setBreakpoints", Json::object([("source", source()), ("breakpoints", lines.into())]));
+    let verified: Vec<_> = reply.get("breakpoints").and_then(Json::as_array).unwrap().iter()
+        .map(|breakpoint| (breakpoint.get("verified").cloned(), breakpoint.get("line").and_then(Json::as_i64)))
+        .collect();
+    assert_eq!(verified, [(Some(Json::Bool(true)), Some(5)), (Some(Json::Bool(true)), Some(6))]);
+
+    client.request("configurationDone", Json::Null);
+    assert_eq!(client.event("stopped").get("reason").and_then(Json::as_str), Some("breakpoint"));
+    let (line, frames) = top_frame(&mut client);
+    assert_eq!(line, 5);
+    assert_eq!(frames[0].get("source").and_then(|source| source.get("path")).and_then(Json::as_str), Some(path.as_str()));
+
+    client.request("continue", Json::object([("threadId", Json::from(1i64))]));
+    client.event("stopped");
+    assert_eq!(top_frame(&mut client).0, 6);
+
+    // Into sub, with the call to it as the second frame.
+    client.request("stepIn", Json::object([("threadId", Json::from(1i64))]));
+    assert_eq!(client.event("stopped").get("reason").and_then(Json::as_str), Some("step"));
+    let (line, frames) = top_frame(&mut client);
+    assert_eq!(line, 2);
+    assert_eq!(frames[1].get("line").and_then(Json::as_i64), Some(6));
+
+    let variables = client.request("variables", Json::object([("variablesReference", Json::from(1i64))]));
+    let v0 = &variables.get("variables").and_then(Json::as_array).unwrap()[0];
+    assert_eq!(v0.get("value").and_then(Json::as_str), Some("0x01"));
+
+    let memory = client.request("readMemory", Json::object([("memoryReference", Json::from("0x202")), ("count", Json::from(2usize))]));
+    assert_eq!(memory.get("data").and_then(Json::as_str), Some("cQE="));
+
+    client.request("disconnect", Json::Null);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn memory_reads_cover_the_whole_address_space() {
+    let path = env::temp_dir().join(format!("chip8-dap-memory-{}.8o", std::process::id()));
+    fs::write(&path, SOURCE).unwrap();
+    let mut client = Client::connect();
+
+    client.request("initialize", Json::object([("adapterID", Json::from("chip8"))]));
+    client.request("launch", Json::object([("program", Json::from(path.to_str().unwrap())), ("stopOnEntry", Json::Bool(true))]));
+    client.event("initialized");
+    client.request("configurationDone", Json::Null);
+    client.event("stopped");
+
+    let memory = client.request("readMemory", Json::object([("memoryReference", Json::from("0x0")), ("count", Json::from(0x10000usize))]));
+    // 64 KiB is 21846 groups of three bytes in base64, the last one padded.
+    let data = memory.get("data").and_then(Json::as_str).unwrap();
+    assert_eq!((data.len(), &data[data.len() - 4..]), (21846 * 4, "AA=="));
+    assert_eq!(memory.get("unreadableBytes").and_then(Json::as_i64), Some(0));
+
+    client.request("disconnect", Json::Null);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn variables_can_be_set() {
+    let path = env::temp_dir().join(format!("chip8-dap-variables-{}.8o", std::process::id()));
+    fs::write(&path, SOURCE).unwrap();
+    let mut client = Client::connect();
+
+    let capabilities = client.request("initialize", Json::object([("adapterID", Json::from("chip8"))]));
+    assert_eq!(capabilities.get("supportsSetVariable"), Some(&Json::Bool(true)));
+    client.request("launch", Json::object([("program", Json::from(path.to_str().unwrap())), ("stopOnEntry", Json::Bool(true))]));
+    client.event("initialized");
+    client.request("configurationDone", Json::Null);
+    client.event("stopped");
+
+    let set = |reference: i64, name: &str, value: &str| Json::object([
+        ("variablesReference", Json::from(reference)),
+        ("name", Json::from(name)),
+        ("value", Json::from(value)),
+    ]);
+    let value = |body: Json| body.get("value").and_then(Json::as_str).map(str::to_string);
+    assert_eq!(value(client.request("setVariable", set(1, "VF", "0x2a"))), Some("0x2A".to_string()));
+    assert_eq!(value(client.request("setVariable", set(1, "I", "$300"))), Some("0x300".to_string()));
+    assert_eq!(value(client.request("setVariable", set(2, "DT", "30"))), Some("30".to_string()));
+
+    let variables = client.request("variables", Json::object([("variablesReference", Json::from(1i64))]));
+    let variables = variables.get("variables").and_then(Json::as_array).unwrap();
+    assert_eq!(variables[15].get("value").and_then(Json::as_str), Some("0x2A"));
+    assert_eq!(variables[16].get("value").and_then(Json::as_str), Some("0x300"));
+
+    assert_eq!(client.fail("setVariable", set(1, "V10", "1")), "unknown variable 'V10'");
+    assert_eq!(client.fail("setVariable", set(1, "DT", "1")), "unknown variable 'DT'");
+    assert_eq!(client.fail("setVariable", set(1, "V0", "0x100")), "0x100 doesn't fit in V0");
+    assert_eq!(client.fail("setVariable", set(2, "ST", "lots")), "invalid value 'lots'");
+
+    client.request("disconnect", Json::Null);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn json_round_trips() {
+    let text = r#"{"a":[1,-2.5,true,null],"b":"quote \" tab \t é 😀"}"#;
+    let value = Json::parse(text).unwrap();
+    assert_eq!(value.get("b").and_then(Json::as_str), Some("quote \" tab \t é 😀"));
+    assert_eq!(Json::parse(&value.to_string()).unwrap(), value);
+    assert!(Json::parse("[1,]").is_err());
+}
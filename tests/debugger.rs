@@ -0,0 +1,120 @@
+//! Breakpoints, watchpoints and stepping over and out of subroutines.
+
+use chip8::{octo, Debugger, PauseReason, System, Watchpoint};
+
+const FRAME: f64 = 1.0 / 60.0;
+
+// main is at $206, with the call to sub at $208.
+const SOURCE: &str = "
+    : sub
+        v1 += 1
+        return
+    : main
+        v0 += 1
+        sub
+        v2 += 1
+        jump main
+";
+
+fn boot() -> (System, Debugger) {
+    let system = System::new(&octo::compile(SOURCE).unwrap()).unwrap();
+    let mut debugger = Debugger::new();
+    debugger.add_breakpoint(0x208);
+    (system, debugger)
+}
+
+#[test]
+fn breakpoint_pauses_before_the_instruction() {
+    let (mut system, mut debugger) = boot();
+
+    assert!(debugger.update(&mut system, FRAME).unwrap());
+    assert!(debugger.is_paused());
+    assert_eq!(system.get_pc(), 0x208);
+    assert_eq!(system.get_registers()[..3], [1, 0, 0]);
+
+    // Paused, so nothing runs.
+    assert!(!debugger.update(&mut system, FRAME).unwrap());
+    assert_eq!(system.get_pc(), 0x208);
+
+    // Resuming doesn't stop on the breakpoint it is sitting on.
+    debugger.resume(&system);
+    assert!(debugger.update(&mut system, FRAME).unwrap());
+    assert_eq!(system.get_registers()[..3], [2, 1, 1]);
+}
+
+#[test]
+fn step_over_and_out() {
+    let (mut system, mut debugger) = boot();
+    debugger.update(&mut system, FRAME).unwrap();
+
+    debugger.step_over(&mut system).unwrap();
+    assert!(debugger.update(&mut system, FRAME).unwrap());
+    assert_eq!(system.get_pc(), 0x20A);
+    assert_eq!(system.get_registers()[..3], [1, 1, 0]);
+
+    debugger.resume(&system);
+    debugger.update(&mut system, FRAME).unwrap();
+    debugger.step(&mut system).unwrap();
+    assert_eq!((system.get_pc(), system.stack_depth()), (0x202, 1));
+
+    debugger.step_out(&mut system).unwrap();
+    assert!(debugger.update(&mut system, FRAME).unwrap());
+    assert_eq!((system.get_pc(), system.stack_depth()), (0x20A, 0));
+}
+
+#[test]
+fn watchpoints_pause_after_the_instruction() {
+    let source = "
+        : main
+            v0 := 3
+            v1 := 7
+            i := 0x300
+            load v0
+            save v1
+            jump main
+    ";
+    let rom = octo::compile(source).unwrap();
+    let watchpoints = [
+        ("read:2ff+2", 0x208),
+        ("write:301", 0x20A),
+        ("v1=7", 0x204),
+        ("i", 0x206),
+    ];
+
+    for (spec, by) in watchpoints {
+        let watchpoint: Watchpoint = spec.parse().unwrap();
+        let mut system = System::new(&rom).unwrap();
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(watchpoint);
+
+        assert!(debugger.update(&mut system, FRAME).unwrap(), "{}", spec);
+        assert_eq!(debugger.pause_reason(), Some(PauseReason::Watchpoint(watchpoint, by)), "{}", spec);
+        assert_eq!(system.get_pc(), by + 2, "{}", spec);
+    }
+}
+
+#[test]
+fn watchpoints_added_while_running_only_see_new_changes() {
+    let mut system = System::new(&octo::compile(SOURCE).unwrap()).unwrap();
+    let mut debugger = Debugger::new();
+    debugger.resume(&system);
+    assert!(!debugger.update(&mut system, FRAME).unwrap());
+    assert!(system.get_registers()[1] > 0);
+
+    // V1 changed since resuming, but only the next v1 += 1 in sub sets it off.
+    let watchpoint = Watchpoint::Register { x: 1, value: None };
+    debugger.add_watchpoint(watchpoint);
+    assert!(debugger.update(&mut system, FRAME).unwrap());
+    assert_eq!(debugger.pause_reason(), Some(PauseReason::Watchpoint(watchpoint, 0x202)));
+    assert_eq!(system.get_pc(), 0x204);
+}
+
+#[test]
+fn watchpoint_specs() {
+    assert_eq!("write:300+3".parse(), Ok(Watchpoint::Write { addr: 0x300, len: 3 }));
+    assert_eq!("READ:$2A0".parse(), Ok(Watchpoint::Read { addr: 0x2A0, len: 1 }));
+    assert_eq!("vF".parse(), Ok(Watchpoint::Register { x: 0xF, value: None }));
+    assert_eq!("i=3f0".parse(), Ok(Watchpoint::Index { value: Some(0x3F0) }));
+    assert!("v1=100".parse::<Watchpoint>().is_err());
+    assert!("pc".parse::<Watchpoint>().is_err());
+}
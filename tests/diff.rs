@@ -0,0 +1,103 @@
+//! Finding where two runs diverge, in lockstep and from traces.
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use chip8::diff::{self, Difference, Run};
+use chip8::trace::{Filter, Tracer};
+use chip8::{octo, Quirks, System};
+
+// After the reset jump at $200, main is at $202 and the shift at $206.
+const SHIFT: &str = "
+    : main
+        v1 := 6
+        v0 := 1
+        v0 >>= v1
+        jump main
+";
+
+fn boot(source: &str, quirks: Quirks) -> System {
+    System::with_quirks(&octo::compile(source).unwrap(), quirks).unwrap()
+}
+
+#[test]
+fn lockstep_finds_the_first_differing_instruction() {
+    let (mut a, mut b) = (boot(SHIFT, Quirks::XO_CHIP), boot(SHIFT, Quirks::SCHIP));
+    let divergence = diff::lockstep(&mut a, &mut b, 100, 2).unwrap().unwrap();
+
+    assert_eq!(divergence.cycle, 3);
+    assert_eq!(divergence.differences, [
+        Difference::Register { x: 0x0, a: 3, b: 0 },
+        Difference::Register { x: 0xF, a: 0, b: 1 },
+    ]);
+    let pcs = |records: &[chip8::trace::Record]| records.iter().map(|record| record.pc).collect::<Vec<_>>();
+    assert_eq!(pcs(&divergence.a), [0x202, 0x204, 0x206, 0x208, 0x202]);
+    assert_eq!(pcs(&divergence.b), pcs(&divergence.a));
+
+    let text = divergence.to_string();
+    assert!(text.starts_with("Runs diverge at cycle 3:\n    V0: 03 vs 00\n    VF: 00 vs 01\nA:\n"), "{}", text);
+    assert!(text.contains(">         3 206: 8016"), "{}", text);
+}
+
+#[test]
+fn lockstep_compares_memory_and_the_screen() {
+    let source = |row| format!("
+        : main
+            i := data
+            sprite v0 v0 1
+            jump main
+        : data
+            {}
+    ", row);
+    let (mut a, mut b) = (boot(&source("0x80"), Quirks::XO_CHIP), boot(&source("0x40"), Quirks::XO_CHIP));
+
+    let divergence = diff::lockstep(&mut a, &mut b, 100, 0).unwrap().unwrap();
+    assert_eq!(divergence.cycle, 2);
+    assert_eq!(divergence.differences, [
+        Difference::Memory { addr: 0x208, a: 0x80, b: 0x40 },
+        Difference::Pixel { x: 0, y: 0, a: 1, b: 0 },
+    ]);
+}
+
+#[test]
+fn identical_runs_do_not_diverge() {
+    let (mut a, mut b) = (boot(SHIFT, Quirks::VIP), boot(SHIFT, Quirks::VIP));
+    assert_eq!(diff::lockstep(&mut a, &mut b, 1000, 5), Ok(None));
+}
+
+/// A writer the test can still read after the system owns the tracer.
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+fn trace(quirks: Quirks, cycles: usize) -> Vec<u8> {
+    let output = Shared::default();
+    let mut system = boot(SHIFT, quirks);
+    system.set_tracer(Some(Tracer::new(output.clone(), Filter::default()).unwrap()));
+    for _ in 0..cycles {
+        system.step().unwrap();
+    }
+    system.take_tracer().unwrap().finish().unwrap();
+    let bytes = output.0.lock().unwrap().clone();
+    bytes
+}
+
+#[test]
+fn traces_diverge_like_lockstep() {
+    let divergence = diff::traces(&trace(Quirks::XO_CHIP, 10)[..], &trace(Quirks::SCHIP, 10)[..], 1).unwrap().unwrap();
+    assert_eq!(divergence.cycle, 3);
+    assert_eq!(divergence.differences.len(), 2);
+    assert_eq!(divergence.a.len(), 3);
+
+    let divergence = diff::traces(&trace(Quirks::XO_CHIP, 10)[..], &trace(Quirks::XO_CHIP, 6)[..], 1).unwrap().unwrap();
+    assert_eq!((divergence.cycle, divergence.differences), (6, vec![Difference::Ended(Run::B)]));
+
+    assert!(diff::traces(&trace(Quirks::VIP, 10)[..], &trace(Quirks::VIP, 10)[..], 1).unwrap().is_none());
+}
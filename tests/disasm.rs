@@ -0,0 +1,51 @@
+//! Control flow analysis and labels in disassembly listings.
+
+mod common;
+
+use chip8::disasm;
+use common::rom;
+
+#[test]
+fn listing_separates_code_sprites_and_data() {
+    let mut rom = rom(&[0x2208, 0xA20E, 0xD015, 0x1206, 0x3000, 0x00E0, 0x00EE]);
+    rom.extend_from_slice(&[0xF0, 0x90, 0x90, 0x90, 0xF0, 0x12, 0x34]);
+
+    assert_eq!(disasm::disassemble(&rom, 0x200).to_string(), "\
+ORG $200
+    CALL sub_208            ; 200: 2208
+    MOV I, sprite_20E       ; 202: A20E
+    DRAW V0, V1, 5          ; 204: D015
+
+label_206:
+    JUMP label_206          ; 206: 1206
+
+sub_208:
+    SEQ V0, 0               ; 208: 3000
+    CLS                     ; 20A: 00E0
+
+label_20C:
+    RET                     ; 20C: 00EE
+
+sprite_20E:
+    db $F0                  ; 20E: F0       ####....
+    db $90                  ; 20F: 90       #..#....
+    db $90                  ; 210: 90       #..#....
+    db $90                  ; 211: 90       #..#....
+    db $F0                  ; 212: F0       ####....
+    db $12, $34             ; 213: 1234
+");
+}
+
+#[test]
+fn skips_step_over_long_index_loads() {
+    let listing = disasm::disassemble(&rom(&[0x3000, 0xF000, 0x1234, 0x00FD]), 0x200).to_string();
+    assert!(listing.contains("    MOV I, LONG $1234       ; 202: F0001234\n\nlabel_206:\n    EXIT"), "{}", listing);
+}
+
+#[test]
+fn unreached_bytes_stay_data() {
+    // Everything after the exit and the computed jump looks like code, but
+    // nothing reaches it.
+    let listing = disasm::disassemble(&rom(&[0x00FD, 0x6001, 0xB300, 0x00E0]), 0x200).to_string();
+    assert!(listing.contains("    EXIT                    ; 200: 00FD\n    db $60, $01, $B3, $00, $00, $E0"), "{}", listing);
+}
@@ -0,0 +1,75 @@
+//! Faults stop the cpu with an error instead of panicking.
+
+mod common;
+
+use chip8::{EmulatorError, Key, Quirks, System};
+use common::rom;
+
+#[test]
+fn key_skips_use_the_low_nibble_of_the_register() {
+    // v0 := $FF, then skip the loop if key F is down.
+    let rom = rom(&[0x60FF, 0xE09E, 0x1204, 0x1206]);
+    let run = |pressed| {
+        let mut system = System::new(&rom).unwrap();
+        system.update_keypad(Key::new(0xF), pressed);
+        for _ in 0..3 {
+            system.step().unwrap();
+        }
+        system.get_pc()
+    };
+    assert_eq!(run(false), 0x204);
+    assert_eq!(run(true), 0x206);
+}
+
+#[test]
+fn calls_past_the_top_of_the_stack_overflow() {
+    // A subroutine that calls itself.
+    let mut system = System::new(&rom(&[0x2200])).unwrap();
+    for _ in 0..16 {
+        system.step().unwrap();
+    }
+    let error = EmulatorError::StackOverflow { pc: 0x200, opcode: 0x2200 };
+    assert_eq!(system.step(), Err(error.clone()));
+    assert_eq!(error.to_string(), "[200]: 2200 => call with full stack");
+}
+
+#[test]
+fn returns_with_an_empty_stack_underflow() {
+    let mut system = System::new(&rom(&[0x00EE])).unwrap();
+    assert_eq!(system.step(), Err(EmulatorError::StackUnderflow { pc: 0x200, opcode: 0x00EE }));
+}
+
+#[test]
+fn accesses_past_the_end_of_memory_are_out_of_range() {
+    // i := $FFE, then save v0 - v2 across the end of the VIP's 4 KiB.
+    let mut system = System::with_quirks(&rom(&[0xAFFE, 0xF255]), Quirks::VIP).unwrap();
+    system.step().unwrap();
+    let error = EmulatorError::MemoryOutOfRange { pc: 0x202, opcode: 0xF255, addr: 0xFFE, len: 3 };
+    assert_eq!(system.step(), Err(error.clone()));
+    assert_eq!(error.to_string(), "[202]: F255 => access of 3 bytes at $FFE is out of range");
+}
+
+#[test]
+fn roms_past_the_end_of_memory_are_too_large() {
+    let error = System::with_quirks(&[0; 0xE01], Quirks::VIP).unwrap_err();
+    assert_eq!(error, EmulatorError::RomTooLarge { size: 0xE01, max: 0xE00 });
+    assert!(System::with_quirks(&[0; 0xE00], Quirks::VIP).is_ok());
+}
+
+#[test]
+fn bad_opcodes_fault_until_a_state_is_loaded() {
+    let mut system = System::new(&rom(&[0x6005, 0x0123])).unwrap();
+    let state = system.save_state();
+    system.step().unwrap();
+
+    let error = EmulatorError::InvalidOpcode { pc: 0x202, opcode: 0x0123 };
+    assert_eq!(system.step(), Err(error.clone()));
+    assert_eq!(system.fault(), Some(&error));
+    // A faulted cpu stays put.
+    assert_eq!(system.step(), Err(error.clone()));
+    assert_eq!(system.update(1.0), Err(error));
+
+    system.load_state(&state).unwrap();
+    assert_eq!(system.fault(), None);
+    system.step().unwrap();
+}
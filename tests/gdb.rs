@@ -0,0 +1,120 @@
+//! Drives the gdb stub over a loopback socket with a minimal RSP client.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use chip8::{gdb, octo, Debugger, System};
+
+// main is at $202, the loop at $206.
+const SOURCE: &str = "
+    : main
+        v0 := 0
+        i := 0x300
+    : loop
+        v0 += 1
+        save v0
+        jump loop
+";
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn connect() -> (Client, thread::JoinHandle<System>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut system = System::new(&octo::compile(SOURCE).unwrap()).unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            gdb::serve(stream, &mut system, &mut Debugger::new()).unwrap();
+            system
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        (Client { stream }, server)
+    }
+
+    /// Sends `packet` and returns the reply's data.
+    fn request(&mut self, packet: &str) -> String {
+        let checksum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", packet, checksum).unwrap();
+        assert_eq!(self.byte(), b'+', "{} was not acked", packet);
+
+        assert_eq!(self.byte(), b'$');
+        let mut reply = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                byte => reply.push(byte),
+            }
+        }
+        let digits = [self.byte(), self.byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&digits).unwrap(), 16).unwrap();
+        assert_eq!(checksum, reply.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)));
+        self.stream.write_all(b"+").unwrap();
+
+        String::from_utf8(reply).unwrap()
+    }
+
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+}
+
+#[test]
+fn registers_memory_and_stepping() {
+    let (mut client, server) = Client::connect();
+
+    assert_eq!(client.request("?"), "S05");
+    // V0-VF, then I, PC, SP, DT and ST.
+    assert_eq!(client.request("g"), format!("{}{}{}", "00".repeat(16), "00000200", "000000"));
+
+    // The jump to main, then the two setup instructions.
+    for _ in 0..3 {
+        assert_eq!(client.request("s"), "S05");
+    }
+    assert_eq!(client.request("p11"), "0206");
+    assert_eq!(client.request("p10"), "0300");
+
+    assert_eq!(client.request("P0=41"), "OK");
+    assert_eq!(client.request("s"), "S05");
+    assert_eq!(client.request("p0"), "42");
+
+    assert_eq!(client.request("M300,2:abcd"), "OK");
+    assert_eq!(client.request("m300,2"), "abcd");
+    assert_eq!(client.request("m1,0"), "");
+    assert_eq!(client.request("P12=05"), "E01");
+    assert_eq!(client.request("Pf=07"), "OK");
+    assert_eq!(client.request("pf"), "07");
+    assert_eq!(client.request("P15=07"), "E01");
+
+    assert_eq!(client.request("D"), "OK");
+    let mut system = server.join().unwrap();
+    assert_eq!(system.read_memory(0x300, 2), Some(&[0xAB, 0xCD][..]));
+    assert_eq!(system.set_register(16, 1), None);
+}
+
+#[test]
+fn breakpoints_and_watchpoints() {
+    let (mut client, server) = Client::connect();
+
+    assert_eq!(client.request("Z0,206,2"), "OK");
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(client.request("p11"), "0206");
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(client.request("p0"), "01");
+    assert_eq!(client.request("z0,206,2"), "OK");
+
+    // The default quirks move I past each save, so the third one writes $302.
+    assert_eq!(client.request("Z2,302,1"), "OK");
+    assert_eq!(client.request("c"), "T05watch:302;");
+    assert_eq!(client.request("p0"), "03");
+    assert_eq!(client.request("m300,3"), "010203");
+
+    drop(client);
+    server.join().unwrap();
+}
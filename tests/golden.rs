@@ -0,0 +1,93 @@
+//! Boots every rom in `rom/` headlessly, runs it for a fixed number of frames
+//! with scripted key presses, and compares the final screen to a snapshot in
+//! `tests/snapshots/`.
+//!
+//! When behaviour changes on purpose, regenerate the snapshots with
+//!
+//!     CHIP8_BLESS=1 cargo test --test golden
+//!
+//! and review the diff before committing it.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8::headless::{self, RunLength};
+use chip8::{image, movie, Schedule, System};
+
+const SEED: u64 = 0;
+
+struct Case {
+    rom: &'static str,
+    frames: u64,
+    /// Movie style `<frame> <key> <0|1>` lines.
+    script: &'static str,
+}
+
+impl Case {
+    const fn new(rom: &'static str, frames: u64) -> Self {
+        Case { rom, frames, script: "" }
+    }
+
+    const fn script(self, script: &'static str) -> Self {
+        Case { script, ..self }
+    }
+}
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Runs the case and checks its snapshot.
+fn check(name: &str, case: Case) {
+    let rom_path = root().join(case.rom);
+    let rom = fs::read(&rom_path)
+        .unwrap_or_else(|err| panic!("could not read {}: {}", rom_path.display(), err));
+
+    let mut system = System::new(&rom).unwrap();
+    system.set_seed(SEED);
+    let script = movie::parse_script(case.script).unwrap();
+    headless::run(&mut system, RunLength::Frames(case.frames), Schedule::Frame, &script)
+        .unwrap_or_else(|err| panic!("{} faulted: {}", name, err));
+
+    let actual = image::ascii(&system.get_pixels());
+    let snapshot = root().join("tests/snapshots").join(format!("{}.txt", name));
+    compare(name, &snapshot, &actual);
+}
+
+fn compare(name: &str, snapshot: &Path, actual: &str) {
+    if env::var_os("CHIP8_BLESS").is_some() {
+        fs::write(snapshot, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(snapshot).unwrap_or_else(|_| {
+        panic!("no snapshot for {}, run with CHIP8_BLESS=1 to create {}", name, snapshot.display())
+    });
+
+    assert!(
+        expected == actual,
+        "screen of {} does not match {}\n\nexpected:\n{}\nactual:\n{}",
+        name, snapshot.display(), expected, actual,
+    );
+}
+
+macro_rules! golden {
+    ($($name:ident: $case:expr,)*) => {$(
+        #[test]
+        fn $name() { check(stringify!($name), $case); }
+    )*};
+}
+
+golden! {
+    test_rom: Case::new("rom/test.ch8", 60),
+    cavern: Case::new("rom/cavern.ch8", 120),
+    chipquarium: Case::new("rom/chipquarium.ch8", 200)
+        .script("30 5 1\n35 5 0\n100 6 1\n140 6 0"),
+    delay_timer_test: Case::new("rom/delay_timer_test.ch8", 200)
+        .script("30 2 1\n34 2 0\n40 2 1\n44 2 0\n60 5 1\n64 5 0"),
+    heart_monitor: Case::new("rom/heart_monitor.ch8", 300),
+    morse_demo: Case::new("rom/morse_demo.ch8", 300),
+    random_number_test: Case::new("rom/random_number_test.ch8", 120)
+        .script("30 0 1\n35 0 0"),
+}
@@ -0,0 +1,113 @@
+//! Running without a window and the screen dump encoders.
+
+mod common;
+
+use chip8::headless::{self, RunLength};
+use chip8::json::Json;
+use chip8::{image, movie, Schedule, System};
+use common::rom;
+
+/// A `width` x `height` screen with the colour of each pixel from `color`.
+fn screen(width: usize, height: usize, color: impl Fn(usize, usize) -> u8) -> Vec<Vec<u8>> {
+    (0..width).map(|x| (0..height).map(|y| color(x, y)).collect()).collect()
+}
+
+fn columns(screen: &[Vec<u8>]) -> Vec<&[u8]> {
+    screen.iter().map(Vec::as_slice).collect()
+}
+
+/// The raw scanlines of a PNG written with stored zlib blocks, checking the
+/// chunk layout on the way.
+fn png_scanlines(png: &[u8]) -> (u32, u32, Vec<u8>) {
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    let mut pos = 8;
+    let mut chunks = Vec::new();
+    while pos < png.len() {
+        let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+        chunks.push((&png[pos + 4..pos + 8], &png[pos + 8..pos + 8 + len], &png[pos + 8 + len..pos + 12 + len]));
+        pos += 12 + len;
+    }
+    let kinds: Vec<_> = chunks.iter().map(|(kind, _, _)| *kind).collect();
+    assert_eq!(kinds, [&b"IHDR"[..], b"IDAT", b"IEND"]);
+    assert_eq!(chunks[2].2, [0xAE, 0x42, 0x60, 0x82]);
+
+    let ihdr = chunks[0].1;
+    assert_eq!(&ihdr[8..], [8, 0, 0, 0, 0]);
+    let width = u32::from_be_bytes(ihdr[..4].try_into().unwrap());
+    let height = u32::from_be_bytes(ihdr[4..8].try_into().unwrap());
+
+    let zlib = chunks[1].1;
+    assert_eq!(&zlib[..2], [0x78, 0x01]);
+    let mut pos = 2;
+    let mut raw = Vec::new();
+    loop {
+        let last = zlib[pos] == 1;
+        let len = u16::from_le_bytes([zlib[pos + 1], zlib[pos + 2]]) as usize;
+        assert_eq!(u16::from_le_bytes([zlib[pos + 3], zlib[pos + 4]]), !(len as u16));
+        raw.extend_from_slice(&zlib[pos + 5..pos + 5 + len]);
+        pos += 5 + len;
+        if last { break; }
+    }
+    assert_eq!(pos + 4, zlib.len());
+    (width, height, raw)
+}
+
+#[test]
+fn pbm_packs_rows_into_bits() {
+    // A 10x2 screen: a diagonal in row 0 and every colour in row 1.
+    let screen = screen(10, 2, |x, y| if y == 0 { (x % 3 == 0) as u8 } else { x as u8 % 4 });
+    let pbm = image::pbm(&columns(&screen));
+    assert_eq!(&pbm[..8], b"P4\n10 2\n");
+    assert_eq!(&pbm[8..], [0b1001_0010, 0b0100_0000, 0b0111_0111, 0b0100_0000]);
+}
+
+#[test]
+fn png_is_greyscale_with_a_filter_byte_per_row() {
+    let screen = screen(4, 2, |x, y| (x + y) as u8 % 4);
+    let (width, height, raw) = png_scanlines(&image::png(&columns(&screen)));
+    assert_eq!((width, height), (4, 2));
+    assert_eq!(raw, [0, 0, 255, 85, 170, 0, 255, 85, 170, 0]);
+}
+
+#[test]
+fn png_splits_large_images_into_stored_blocks() {
+    let screen = screen(300, 300, |x, y| ((x ^ y) & 1) as u8);
+    let (width, height, raw) = png_scanlines(&image::png(&columns(&screen)));
+    assert_eq!((width, height), (300, 300));
+    assert_eq!(raw.len(), 301 * 300);
+    assert_eq!(raw[301..304], [0, 255, 0]);
+}
+
+#[test]
+fn ascii_draws_one_line_per_row() {
+    let screen = screen(4, 2, |x, _| x as u8);
+    assert_eq!(image::ascii(&columns(&screen)), ".#+@\n.#+@\n");
+}
+
+#[test]
+fn scripts_press_keys_on_their_frame() {
+    // Count frames until a key is down, then stop:
+    // loop v0 += 1 v1 := 5 if v1 -key then again, then jump to itself.
+    let mut system = System::new(&rom(&[0x7001, 0x6105, 0xE19E, 0x1200, 0x1208])).unwrap();
+    let script = movie::parse_script("# press 5\n10 5 1").unwrap();
+    headless::run(&mut system, RunLength::Frames(20), Schedule::Frame, &script).unwrap();
+
+    // 10 frames at 500 instructions a second is 83 instructions, 4 a loop,
+    // so the key is first seen on the 22nd pass.
+    assert_eq!(system.get_registers()[0], 22);
+    assert_eq!(system.get_pc(), 0x208);
+    let registers = Json::parse(&headless::registers_json(&system)).unwrap();
+    let v = registers.get("v").and_then(Json::as_array).unwrap();
+    assert_eq!((v[0].as_i64(), v[1].as_i64()), (Some(22), Some(5)));
+    assert_eq!(registers.get("pc").and_then(Json::as_i64), Some(0x208));
+    assert_eq!(registers.get("fault"), Some(&Json::Null));
+}
+
+#[test]
+fn register_dumps_include_the_fault() {
+    let mut system = System::new(&[0x00, 0xEE]).unwrap();
+    assert!(headless::run(&mut system, RunLength::Cycles(10), Schedule::default(), &[]).is_err());
+    let registers = Json::parse(&headless::registers_json(&system)).unwrap();
+    assert_eq!(registers.get("fault").and_then(Json::as_str), Some("[200]: 00EE => return with empty stack"));
+    assert_eq!(registers.get("stack").and_then(Json::as_array), Some(&[][..]));
+}
@@ -0,0 +1,103 @@
+//! Recording, playing back and parsing input movies.
+
+mod common;
+
+use chip8::movie::{MovieError, Player, Recorder};
+use chip8::{Key, Movie, Quirks, Schedule, System};
+
+fn rom() -> Vec<u8> {
+    // loop v1 := key v0 += v1 v2 := random 0xFF again
+    common::rom(&[0xF10A, 0x8014, 0xC2FF, 0x1200])
+}
+
+fn record(rom: &[u8], schedule: Schedule) -> (Movie, System) {
+    let mut system = System::with_quirks(rom, Quirks::SCHIP).unwrap();
+    system.set_seed(42);
+    let mut recorder = Recorder::new(rom, &system, schedule);
+    for frame in 0..60 {
+        if frame % 10 == 3 { recorder.update_keypad(&mut system, Key::new(frame / 10 + 1), true); }
+        if frame % 10 == 6 { recorder.update_keypad(&mut system, Key::new(frame / 10 + 1), false); }
+        recorder.run_frame(&mut system).unwrap();
+    }
+    (recorder.finish(), system)
+}
+
+#[test]
+fn playback_reproduces_the_recording() {
+    let rom = rom();
+    for schedule in [Schedule::Frame, Schedule::Dt] {
+        let (movie, recorded) = record(&rom, schedule);
+        assert_eq!((movie.frames, movie.events.len()), (60, 12));
+
+        let movie = Movie::parse(&movie.to_string()).unwrap();
+        assert_eq!(movie.schedule, schedule);
+        movie.check_rom(&rom).unwrap();
+        let mut system = movie.boot(&rom).unwrap();
+        assert_eq!((system.quirks(), system.seed()), (Quirks::SCHIP, 42));
+
+        let mut player = Player::new(movie);
+        player.run_to_end(&mut system).unwrap();
+        assert!(player.is_finished());
+        assert_eq!(system.save_state(), recorded.save_state(), "{:?}", schedule);
+    }
+}
+
+#[test]
+fn recordings_run_frames_like_the_window() {
+    // The window's default schedule: a 60th of the instructions a second,
+    // then a timer tick, whatever the frame's length in time.
+    let rom = common::rom(&[0x7001, 0x1200]);
+    let mut system = System::new(&rom).unwrap();
+    system.set_ips(100);
+    let mut recorder = Recorder::new(&rom, &system, Schedule::Frame);
+    for _ in 0..3 {
+        recorder.run_frame(&mut system).unwrap();
+    }
+    // 1 + 2 + 2 instructions, three of them adds.
+    assert_eq!(system.get_registers()[0], 3);
+
+    let movie = recorder.finish();
+    let mut played = movie.boot(&rom).unwrap();
+    Player::new(movie).run_to_end(&mut played).unwrap();
+    assert_eq!(played.save_state(), system.save_state());
+}
+
+#[test]
+fn older_movies_run_frames_by_time() {
+    let movie = Movie::parse("chip8-movie 3\nrom 0\nseed 1\nquirks\ntiming fixed 500\nframes 0\n").unwrap();
+    assert_eq!(movie.schedule, Schedule::Dt);
+    assert!(movie.to_string().contains("\nschedule dt\n"));
+    assert_eq!(Movie::parse(&movie.to_string()), Ok(movie));
+}
+
+#[test]
+fn playing_on_another_rom_is_a_desync() {
+    let (movie, _) = record(&rom(), Schedule::default());
+    let other = common::rom(&[0x1200]);
+    let error = movie.check_rom(&other).unwrap_err();
+    assert!(matches!(error, MovieError::RomMismatch { expected, .. } if expected == movie.rom_hash));
+    assert!(error.to_string().starts_with("desync: "));
+}
+
+#[test]
+fn malformed_movies_report_the_line() {
+    let header = "chip8-movie 4\nrom 00ff\nseed 1\nquirks vf_reset\ntiming fixed 500\nschedule frame\nframes 10\n";
+    assert!(Movie::parse(header).is_ok());
+
+    let cases = [
+        (header.replace("movie 4", "movie 9"), 1, "unsupported movie version"),
+        (header.replace("rom 00ff", "rom xyz"), 2, "invalid rom hash"),
+        (header.replace("seed 1", "sead 1"), 3, "expected 'seed'"),
+        (header.replace("vf_reset", "vf_rest"), 4, "unknown quirk 'vf_rest'"),
+        (header.replace("fixed 500", "fixed 0"), 5, "invalid instructions per second"),
+        (header.replace("schedule frame", "schedule fast"), 6, "invalid schedule"),
+        (header.replace("schedule frame\n", ""), 6, "expected 'schedule'"),
+        (header.replace("frames 10", "frames -1"), 7, "invalid frame count"),
+        (header.replace("frames 10\n", ""), 0, "missing 'frames'"),
+        (format!("{}1 5 1\n1 G 0\n", header), 9, "expected '<frame> <key> <0|1>'"),
+        (format!("{}\n# comment\n5 5 1\n4 5 0\n", header), 11, "events are not in frame order"),
+    ];
+    for (text, line, message) in cases {
+        assert_eq!(Movie::parse(&text), Err(MovieError::Parse { line, message: message.to_string() }), "{}", text);
+    }
+}
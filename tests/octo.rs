@@ -0,0 +1,62 @@
+//! Compiles small Octo programs and checks what they leave in the registers.
+
+use chip8::{octo, System};
+
+fn run(source: &str, cycles: usize) -> [u8; 16] {
+    let rom = octo::compile(source).unwrap_or_else(|err| panic!("{}", err));
+    let mut system = System::new(&rom).unwrap();
+    for _ in 0..cycles {
+        system.step().unwrap();
+    }
+    *system.get_registers()
+}
+
+#[test]
+fn control_flow() {
+    let v = run("
+        :alias count v8
+        :const LIMIT 10
+        : main
+            v0 := 5  v1 := 7
+            if v0 < v1 then v2 := 1
+            if v0 > v1 then v3 := 1
+            if v0 <= 5 then v4 := 1
+            if v1 >= 8 then v5 := 1
+            if v1 key begin v6 := 1 else v6 := 2 end
+            loop
+                count += 1
+                while count != LIMIT
+            again
+            loop again
+    ", 200);
+
+    assert_eq!(v[2..=6], [1, 0, 1, 0, 2]);
+    assert_eq!(v[8], 10);
+}
+
+#[test]
+fn compile_time_values() {
+    let v = run("
+        :macro double reg { reg += reg }
+        :calc THREE { 1 + 2 }
+        : main
+            v2 := THREE
+            double v2
+            :unpack 0xA data
+            loop again
+        : data
+    ", 20);
+
+    assert_eq!(v[2], 6);
+    // The jump to main and five words of code come before data.
+    assert_eq!(v[..2], [0xA2, 0x0C]);
+}
+
+#[test]
+fn errors_have_positions() {
+    let err = octo::compile(": main\n    v0 := 300\n").unwrap_err();
+    assert_eq!((err.line, err.column), (2, 11));
+
+    let err = octo::compile(": main\n    jump nowhere\n").unwrap_err();
+    assert_eq!(err.to_string(), "2:10: undefined name 'nowhere'");
+}
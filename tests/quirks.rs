@@ -0,0 +1,111 @@
+//! Each quirk, on and off. The roms draw the registers and memory they care
+//! about as sprite rows, so the results can be read back off the screen.
+
+mod common;
+
+use chip8::{Quirks, System};
+use common::{peek, rom, rows, show};
+
+fn run(quirks: Quirks, opcodes: &[u16]) -> System {
+    let mut opcodes = opcodes.to_vec();
+    let end = 0x200 + 2 * opcodes.len() as u16;
+    opcodes.push(0x1000 | end);
+
+    let mut system = System::with_quirks(&rom(&opcodes), quirks).unwrap();
+    for _ in 0..60 {
+        system.update(1.0 / 60.0).unwrap();
+    }
+    system
+}
+
+#[test]
+fn shift_uses_vy() {
+    // v0 = 4, v1 = 3, v0 >>= 1 or v0 = v1 >> 1.
+    let opcodes = [&[0x6004, 0x6103, 0x8016][..], &show(&[0x0, 0xF])].concat();
+
+    let system = run(Quirks { shift_uses_vy: true, ..Quirks::default() }, &opcodes);
+    assert_eq!(rows(&system, 2), [1, 1]);
+
+    let system = run(Quirks { shift_uses_vy: false, ..Quirks::default() }, &opcodes);
+    assert_eq!(rows(&system, 2), [2, 0]);
+}
+
+#[test]
+fn load_store_increment_i() {
+    // Store 0x11 and 0x22 at 0x300, then 0x33 at wherever I was left.
+    let opcodes = [&[0xA300, 0x6011, 0x6122, 0xF155, 0x6033, 0xF055][..], &peek(&[0x300, 0x301, 0x302])].concat();
+
+    let system = run(Quirks { load_store_increment_i: true, ..Quirks::default() }, &opcodes);
+    assert_eq!(rows(&system, 3), [0x11, 0x22, 0x33]);
+
+    let system = run(Quirks { load_store_increment_i: false, ..Quirks::default() }, &opcodes);
+    assert_eq!(rows(&system, 3), [0x33, 0x22, 0x00]);
+
+    // Load 0x300-0x301 into v0-v1, then v2 from wherever I was left.
+    let opcodes = [&[0xA300, 0x6011, 0x6122, 0x6233, 0xF255, 0xA300, 0xF165, 0xF265][..], &show(&[0x0, 0x1, 0x2])].concat();
+
+    let system = run(Quirks { load_store_increment_i: true, ..Quirks::default() }, &opcodes);
+    assert_eq!(rows(&system, 3), [0x33, 0x00, 0x00]);
+
+    let system = run(Quirks { load_store_increment_i: false, ..Quirks::default() }, &opcodes);
+    assert_eq!(rows(&system, 3), [0x11, 0x22, 0x33]);
+}
+
+#[test]
+fn jump_uses_vx() {
+    // v0 = 4, v2 = 8, then B20A lands on 0x20E with v0 or 0x212 with v2.
+    let opcodes = [
+        &[0x6004, 0x6208, 0xB20A, 0x6509, 0x6509, 0x6509, 0x6509, 0x6501, 0x1214, 0x6502][..],
+        &show(&[0x5]),
+    ].concat();
+
+    let system = run(Quirks { jump_uses_vx: true, ..Quirks::default() }, &opcodes);
+    assert_eq!(rows(&system, 1), [2]);
+
+    let system = run(Quirks { jump_uses_vx: false, ..Quirks::default() }, &opcodes);
+    assert_eq!(rows(&system, 1), [1]);
+}
+
+#[test]
+fn vf_reset() {
+    // v0 = 3 op 5 for or, and, xor, with vf = 0x55 beforehand.
+    for (op, result) in [(0x8011, 0x07), (0x8012, 0x01), (0x8013, 0x06)] {
+        let opcodes = [&[0x6F55, 0x6003, 0x6105, op][..], &show(&[0x0, 0xF])].concat();
+
+        let system = run(Quirks { vf_reset: true, ..Quirks::default() }, &opcodes);
+        assert_eq!(rows(&system, 2), [result, 0x00], "{op:04X}");
+
+        let system = run(Quirks { vf_reset: false, ..Quirks::default() }, &opcodes);
+        assert_eq!(rows(&system, 2), [result, 0x55], "{op:04X}");
+    }
+}
+
+#[test]
+fn clip_sprites() {
+    // An 8x2 block at 60, 31: half of it is off the right edge, half off the bottom.
+    let opcodes = [0xA300, 0x60FF, 0x61FF, 0xF155, 0xA300, 0x603C, 0x611F, 0xD012];
+
+    let system = run(Quirks { clip_sprites: true, ..Quirks::default() }, &opcodes);
+    let lit = |x: usize, y: usize| system.get_pixels()[x][y] != 0;
+    assert!(lit(60, 31) && lit(63, 31));
+    assert!(!lit(0, 31) && !lit(60, 0) && !lit(0, 0));
+
+    let system = run(Quirks { clip_sprites: false, ..Quirks::default() }, &opcodes);
+    let lit = |x: usize, y: usize| system.get_pixels()[x][y] != 0;
+    assert!(lit(60, 31) && lit(63, 31));
+    assert!(lit(3, 31) && lit(60, 0) && lit(3, 0));
+    assert!(!lit(4, 31) && !lit(4, 0) && !lit(60, 1));
+}
+
+#[test]
+fn display_wait() {
+    // Draw a dot at x = 0, 1, 2, ... forever.
+    let opcodes = [0xA300, 0x6080, 0xF055, 0xA300, 0x6000, 0x6100, 0xD011, 0x7001, 0x120C];
+
+    // Fifteen instructions, but only one 60hz tick.
+    for (wait, dots) in [(true, 1), (false, 3)] {
+        let mut system = System::with_quirks(&rom(&opcodes), Quirks { display_wait: wait, ..Quirks::default() }).unwrap();
+        system.update(0.03).unwrap();
+        assert_eq!(rows(&system, 1), [!0u8 << (8 - dots)], "display_wait: {wait}");
+    }
+}
@@ -0,0 +1,62 @@
+//! Rewinding through delta encoded save states.
+
+mod common;
+
+use chip8::{Rewind, System};
+use common::rom;
+
+const FRAME: f64 = 1.0 / 60.0;
+
+fn boot() -> System {
+    // i := 0x300 loop v0 += 1 v1 := random 0xFF save v1 sprite v0 v1 3 again
+    System::new(&rom(&[0xA300, 0x7001, 0xC1FF, 0xF155, 0xD013, 0x1202])).unwrap()
+}
+
+#[test]
+fn rewinding_restores_every_frame_exactly() {
+    let mut system = boot();
+    let mut rewind = Rewind::new(100);
+    let mut states = Vec::new();
+    for _ in 0..20 {
+        rewind.push(&system);
+        states.push(system.save_state());
+        system.update(FRAME).unwrap();
+    }
+    assert_eq!(rewind.len(), 19);
+    // A frame only changes a few bytes of the 70 KiB state.
+    assert!(rewind.memory_usage() < states[0].len() + 19 * 200, "{}", rewind.memory_usage());
+
+    // Rewinding steps back from the newest frame pushed.
+    states.pop();
+    while let Some(state) = states.pop() {
+        assert!(rewind.rewind(&mut system));
+        assert_eq!(system.save_state(), state);
+    }
+    assert!(rewind.is_empty());
+}
+
+#[test]
+fn rewinding_stops_at_the_oldest_frame() {
+    let mut system = boot();
+    let mut rewind = Rewind::new(3);
+    assert!(!rewind.rewind(&mut system));
+
+    let mut states = Vec::new();
+    for _ in 0..10 {
+        rewind.push(&system);
+        states.push(system.save_state());
+        system.update(FRAME).unwrap();
+    }
+    assert_eq!(rewind.len(), 3);
+
+    for _ in 0..3 {
+        assert!(rewind.rewind(&mut system));
+    }
+    assert_eq!(system.save_state(), states[6]);
+    assert!(!rewind.rewind(&mut system));
+    assert_eq!(system.save_state(), states[6]);
+
+    rewind.clear();
+    assert!(!rewind.rewind(&mut system));
+    assert_eq!(rewind.memory_usage(), 0);
+}
@@ -0,0 +1,63 @@
+//! Seeded random numbers for `CXNN`.
+
+mod common;
+
+use chip8::rng::Rng;
+use chip8::System;
+use common::{peek, rom, rows};
+
+/// The first `count` numbers a rom draws with `random mask`, saved from
+/// $800 on and then drawn as rows.
+fn draws(seed: u64, mask: u16, count: u16) -> Vec<u8> {
+    let mut opcodes = vec![0xA800];
+    for _ in 0..count {
+        opcodes.extend([0xC000 | mask, 0xF055]);
+    }
+    opcodes.extend(peek(&(0x800..0x800 + count).collect::<Vec<_>>()));
+
+    let mut system = System::new(&rom(&opcodes)).unwrap();
+    system.set_seed(seed);
+    for _ in &opcodes {
+        system.step().unwrap();
+    }
+    rows(&system, count as usize)
+}
+
+#[test]
+fn seeds_give_the_same_numbers_every_run() {
+    assert_eq!(draws(1234, 0xFF, 32), draws(1234, 0xFF, 32));
+    assert_ne!(draws(1234, 0xFF, 32), draws(1235, 0xFF, 32));
+
+    let mut rng = Rng::new(1234);
+    let expected: Vec<_> = (0..32).map(|_| rng.next_u8()).collect();
+    assert_eq!(draws(1234, 0xFF, 32), expected);
+}
+
+#[test]
+fn even_seed_0_gives_varied_numbers() {
+    let numbers = draws(0, 0xFF, 32);
+    assert!(numbers.iter().any(|&n| n != numbers[0]));
+}
+
+#[test]
+fn the_mask_limits_the_numbers() {
+    let numbers = draws(5, 0x0F, 32);
+    assert!(numbers.iter().all(|&n| n < 0x10));
+    assert!(numbers.iter().any(|&n| n != numbers[0]));
+}
+
+#[test]
+fn states_carry_on_the_sequence() {
+    // loop v0 := random 0xFF again
+    let mut system = System::new(&rom(&[0xC0FF, 0x1200])).unwrap();
+    system.set_seed(99);
+    system.update(0.1).unwrap();
+    let state = system.save_state();
+    system.update(0.1).unwrap();
+
+    let mut loaded = System::new(&[]).unwrap();
+    loaded.load_state(&state).unwrap();
+    assert_eq!(loaded.seed(), 99);
+    loaded.update(0.1).unwrap();
+    assert_eq!(loaded.save_state(), system.save_state());
+}
@@ -0,0 +1,89 @@
+//! SUPER-CHIP: high resolution, big sprites, scrolling, exit and the flag
+//! registers.
+
+mod common;
+
+use chip8::{Quirks, System};
+use common::{rom, show};
+
+fn run(opcodes: &[u16]) -> System {
+    let mut system = System::with_quirks(&rom(opcodes), Quirks::SCHIP).unwrap();
+    for _ in opcodes {
+        system.step().unwrap();
+    }
+    system
+}
+
+/// The lit pixels, as `(x, y)`.
+fn lit(system: &System) -> Vec<(usize, usize)> {
+    let pixels = system.get_pixels();
+    (0..pixels[0].len())
+        .flat_map(|y| pixels.iter().enumerate().filter(move |(_, column)| column[y] != 0).map(move |(x, _)| (x, y)))
+        .collect()
+}
+
+#[test]
+fn hires_switches_resolution_and_clears() {
+    // hires, then draw the top of the 0 glyph at 100, 50, then lores.
+    let opcodes = [0x00FF, 0x6064, 0x6132, 0xA050, 0xD011, 0x00FE];
+    let mut system = System::with_quirks(&rom(&opcodes), Quirks::SCHIP).unwrap();
+    for _ in 0..5 {
+        system.step().unwrap();
+    }
+    assert_eq!(system.get_resolution(), (128, 64));
+    assert_eq!(lit(&system), [(100, 50), (101, 50), (102, 50), (103, 50)]);
+
+    system.step().unwrap();
+    assert_eq!(system.get_resolution(), (64, 32));
+    assert!(lit(&system).is_empty());
+}
+
+#[test]
+fn big_sprites_are_16_by_16() {
+    // Fill 0x300-0x31F with $FF, then draw it as a big sprite at 2, 0.
+    let mut opcodes: Vec<u16> = (0..16).map(|reg| 0x60FF | reg << 8).collect();
+    opcodes.extend([0xA300, 0xFF55, 0xA310, 0xFF55, 0x00FF, 0x6002, 0x6100, 0xA300, 0xD010]);
+    let system = run(&opcodes);
+    let lit = lit(&system);
+    assert_eq!(lit.len(), 256);
+    assert_eq!((lit[0], lit[255]), ((2, 0), (17, 15)));
+}
+
+#[test]
+fn big_font_digits_are_10_bytes_apart() {
+    // Draw the big digit in v2 at 0, 0.
+    let digit = |n: u16| lit(&run(&[0x00FF, 0x6200 | n, 0xF230, 0x6000, 0x6100, 0xD01A]));
+    let (zero, three) = (digit(0), digit(3));
+    assert_ne!(zero, three);
+    for y in 0..10 {
+        assert!(three.iter().any(|&(_, row)| row == y), "row {y}");
+    }
+    assert!(three.iter().all(|&(_, row)| row < 10));
+}
+
+#[test]
+fn scrolling_moves_the_screen() {
+    // hires, then a single pixel at 8, 8.
+    let draw = [0x6080, 0xA300, 0xF055, 0x00FF, 0x6008, 0xD001];
+    let scrolled = |opcode| lit(&run(&[&draw[..], &[opcode]].concat()));
+    assert_eq!(scrolled(0x00C3), [(8, 11)]);
+    assert_eq!(scrolled(0x00FB), [(12, 8)]);
+    assert_eq!(scrolled(0x00FC), [(4, 8)]);
+}
+
+#[test]
+fn flag_registers_save_and_restore_the_registers() {
+    let opcodes = [0x6001, 0x6102, 0x6203, 0xF275, 0x6000, 0x6100, 0x6200, 0xF185];
+    let system = run(&[&opcodes[..], &show(&[0x0, 0x1, 0x2])].concat());
+    assert_eq!(lit(&system), [(7, 0), (6, 1)]);
+}
+
+#[test]
+fn exit_halts_the_cpu() {
+    // exit, then the 0 glyph that should never be drawn.
+    let mut system = System::with_quirks(&rom(&[0x00FD, 0xA050, 0xD005]), Quirks::SCHIP).unwrap();
+    system.step().unwrap();
+    assert!(system.is_halted());
+    system.update(1.0).unwrap();
+    assert!(lit(&system).is_empty());
+}
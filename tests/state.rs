@@ -0,0 +1,116 @@
+//! Save states: round trips, migrating older versions and rejecting bad data.
+
+mod common;
+
+use chip8::state::VERSION;
+use chip8::{Quirks, StateError, System, Timing};
+use common::rom;
+
+/// Offset of the stack depth: magic, version, memory, V, I and PC.
+const STACK_DEPTH: usize = 8 + 0x10000 + 16 + 2 + 2;
+
+fn boot() -> System {
+    // loop v0 := random 0xFF v1 += v0 again
+    let mut system = System::with_quirks(&rom(&[0xC0FF, 0x8104, 0x1200]), Quirks::VIP).unwrap();
+    system.set_seed(7);
+    system.set_timing(Timing::Vip);
+    system.set_ips(1234);
+    system.update(0.5).unwrap();
+    system
+}
+
+/// Rewrites a current state as `version` by dropping the fields added since.
+fn downgrade(state: &[u8], version: u32) -> Vec<u8> {
+    let mut state = state.to_vec();
+    // The end of a state is the random number generator (16 bytes), the
+    // timing mode and frame cycles (9) and the instructions per second (4).
+    if version < 4 { state.truncate(state.len() - 4); }
+    if version < 3 { state.truncate(state.len() - 9); }
+    if version < 2 { state.truncate(state.len() - 16); }
+    state[4..8].copy_from_slice(&version.to_le_bytes());
+    state
+}
+
+#[test]
+fn states_round_trip() {
+    let mut system = boot();
+    let state = system.save_state();
+    system.update(0.5).unwrap();
+
+    let mut loaded = System::new(&[]).unwrap();
+    loaded.load_state(&state).unwrap();
+    assert_eq!(loaded.save_state(), state);
+    assert_eq!((loaded.quirks(), loaded.timing(), loaded.ips(), loaded.seed()), (Quirks::VIP, Timing::Vip, 1234, 7));
+    loaded.update(0.5).unwrap();
+    assert_eq!(loaded.get_registers(), system.get_registers());
+}
+
+#[test]
+fn older_versions_are_migrated() {
+    let state = boot().save_state();
+    let load = |version| {
+        let mut system = System::new(&[]).unwrap();
+        system.load_state(&downgrade(&state, version)).unwrap();
+        system
+    };
+
+    let v3 = load(3);
+    assert_eq!((v3.timing(), v3.ips()), (Timing::Vip, chip8::system::DEFAULT_IPS));
+
+    let v2 = load(2);
+    assert_eq!((v2.timing(), v2.seed()), (Timing::Fixed, 7));
+
+    let v1 = load(1);
+    assert_eq!(v1.get_registers(), v2.get_registers());
+}
+
+#[test]
+fn states_keep_the_memory_size() {
+    let mut system = System::with_quirks(&[], Quirks::VIP).unwrap();
+    system.load_state(&System::with_quirks(&[], Quirks::XO_CHIP).unwrap().save_state()).unwrap();
+    assert_eq!(system.memory_size(), 0x10000);
+
+    system.load_state(&System::with_quirks(&[], Quirks::VIP).unwrap().save_state()).unwrap();
+    assert_eq!(system.memory_size(), 0x1000);
+}
+
+#[test]
+fn bad_states_are_rejected_and_leave_the_system_alone() {
+    let state = boot().save_state();
+    let mut system = System::new(&[0x12, 0x00]).unwrap();
+    let before = system.save_state();
+
+    let mut check = |data: &[u8], error| {
+        assert_eq!(system.load_state(data), Err(error));
+        assert_eq!(system.save_state(), before);
+    };
+
+    check(b"not a state", StateError::NotAState);
+    check(&downgrade(&state, 0), StateError::UnsupportedVersion(0));
+    let mut newer = state.clone();
+    newer[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    check(&newer, StateError::UnsupportedVersion(VERSION + 1));
+
+    check(&state[..state.len() - 1], StateError::Truncated);
+    check(&state[..100], StateError::Truncated);
+    check(&[&state[..], &[0]].concat(), StateError::Invalid("length"));
+
+    let mut deep = state.clone();
+    deep[STACK_DEPTH] = 17;
+    check(&deep, StateError::Invalid("stack size"));
+
+    let mut stuck = state.clone();
+    let rng = stuck.len() - 4 - 9 - 8;
+    stuck[rng..rng + 8].copy_from_slice(&0u64.to_le_bytes());
+    check(&stuck, StateError::Invalid("random state"));
+
+    let mut slow = state.clone();
+    let ips = slow.len() - 4;
+    slow[ips..].copy_from_slice(&0u32.to_le_bytes());
+    check(&slow, StateError::Invalid("instructions per second"));
+
+    let mut timing = state.clone();
+    let mode = timing.len() - 4 - 8 - 1;
+    timing[mode] = 2;
+    check(&timing, StateError::Invalid("timing"));
+}
@@ -0,0 +1,156 @@
+//! How long instructions take, and frames of them.
+
+use chip8::headless::{self, RunLength};
+use chip8::system::{self, DEFAULT_IPS};
+use chip8::timing::{self, Timing, VIP_CYCLE_HZ, VIP_INTERRUPT_CYCLES};
+use chip8::{octo, Instruction, Movie, Quirks, Schedule, System};
+
+fn boot(source: &str) -> System {
+    let mut system = System::with_quirks(&octo::compile(source).unwrap(), Quirks::VIP).unwrap();
+    system.set_timing(Timing::Vip);
+    system
+}
+
+#[test]
+fn frames_run_a_fixed_number_of_instructions() {
+    // After the reset jump, every other instruction is v0 += 1.
+    let mut system = System::new(&octo::compile(": main loop v0 += 1 again").unwrap()).unwrap();
+    system.set_delay_timer(3);
+    for _ in 0..3 {
+        system.run_frame(8).unwrap();
+    }
+    assert_eq!(system.get_registers()[0], 12);
+    assert_eq!(system.get_delay_timer(), 0);
+
+    system.set_delay_timer(1);
+    let mut stop = |system: &System| system.get_registers()[0] == 13;
+    assert!(system.run_frame_with(8, &mut stop).unwrap());
+    assert_eq!(system.get_registers()[0], 13);
+    assert_eq!(system.get_delay_timer(), 1);
+}
+
+#[test]
+fn frames_spread_the_instructions_per_second() {
+    let frames: Vec<_> = (0..60).map(|frame| system::instructions_in_frame(DEFAULT_IPS, frame)).collect();
+    assert_eq!(frames.iter().sum::<u32>(), DEFAULT_IPS);
+    assert_eq!(frames[..6], [8, 8, 9, 8, 8, 9]);
+    assert_eq!(system::instructions_in_frame(60, 1234), 1);
+}
+
+#[test]
+fn the_timers_tick_by_the_instructions_per_second() {
+    let ticks = |ips| {
+        let mut system = System::new(&octo::compile(": main loop again").unwrap()).unwrap();
+        system.set_ips(ips);
+        system.set_delay_timer(10);
+        headless::run(&mut system, RunLength::Cycles(10), Schedule::default(), &[]).unwrap();
+        10 - system.get_delay_timer()
+    };
+    assert_eq!(ticks(DEFAULT_IPS), 1);
+    assert_eq!(ticks(120), 5);
+}
+
+#[test]
+fn draws_cost_more_off_byte_boundaries() {
+    let cost = |x: u8, opcode| {
+        let mut v = [0; 16];
+        v[1] = x;
+        timing::vip_cycles(Instruction::decode(opcode, 0).unwrap(), &v, false)
+    };
+    assert_eq!(cost(8, 0xD115), 20 + 26 + 5 * 22);
+    assert_eq!(cost(11, 0xD115), 20 + 26 + 5 * (40 + 8 * 3));
+    assert!(cost(8, 0xD11F) > cost(8, 0xD115));
+
+    let bcd = |value: u8| {
+        let mut v = [0; 16];
+        v[1] = value;
+        timing::vip_cycles(Instruction::decode(0xF133, 0).unwrap(), &v, false)
+    };
+    assert!(bcd(199) > bcd(100));
+    assert_eq!(Timing::from_name("COSMAC-VIP"), Some(Timing::Vip));
+}
+
+#[test]
+fn timers_follow_the_cycles() {
+    // The reset jump and the loop's jump cost 30 cycles and v0 += 1 costs
+    // 28, so the 127th instruction takes the cpu from cycle 3654 to 3684,
+    // past the interrupt at 3668.
+    let mut system = boot(": main loop v0 += 1 again");
+    system.step().unwrap();
+    system.set_delay_timer(1);
+    let mut steps = 1;
+    while system.get_delay_timer() > 0 {
+        system.step().unwrap();
+        steps += 1;
+    }
+
+    assert_eq!(steps, 127);
+    assert_eq!((system.get_time() * VIP_CYCLE_HZ).round() as u64, 3684 + VIP_INTERRUPT_CYCLES);
+}
+
+#[test]
+fn draws_wait_for_the_display_interrupt() {
+    let mut system = boot("
+        : main
+            loop
+                sprite v1 v1 1
+                v0 += 1
+            again
+    ");
+    for _ in 0..10 {
+        system.update(1.0 / 60.0).unwrap();
+    }
+    assert_eq!(system.get_registers()[0], 9);
+    assert!(!system.is_waiting_for_display());
+
+    // Without the quirk the cpu draws as fast as it can.
+    let mut quirks = Quirks::VIP;
+    quirks.display_wait = false;
+    let mut system = System::with_quirks(&octo::compile(": main loop sprite v1 v1 1 v0 += 1 again").unwrap(), quirks).unwrap();
+    system.set_timing(Timing::Vip);
+    system.update(1.0 / 60.0).unwrap();
+    assert!(system.get_registers()[0] > 20);
+}
+
+#[test]
+fn states_and_movies_keep_the_timing() {
+    let mut system = boot(": main loop v0 += 1 again");
+    system.update(0.1).unwrap();
+    let state = system.save_state();
+    system.update(0.1).unwrap();
+    let registers = *system.get_registers();
+
+    let mut loaded = System::new(&[]).unwrap();
+    loaded.load_state(&state).unwrap();
+    assert_eq!(loaded.timing(), Timing::Vip);
+    loaded.update(0.1).unwrap();
+    assert_eq!(*loaded.get_registers(), registers);
+
+    let movie = Movie::parse("chip8-movie 1\nrom 0\nseed 1\nquirks\nframes 0\n").unwrap();
+    assert_eq!(movie.timing, Timing::Fixed);
+    let movie = Movie { timing: Timing::Vip, ..movie };
+    assert_eq!(Movie::parse(&movie.to_string()), Ok(movie));
+}
+
+#[test]
+fn states_and_movies_keep_the_instructions_per_second() {
+    let mut system = System::new(&octo::compile(": main loop v0 += 1 again").unwrap()).unwrap();
+    system.set_ips(1234);
+    system.update(0.1).unwrap();
+    let state = system.save_state();
+    system.update(0.1).unwrap();
+    let registers = *system.get_registers();
+
+    let mut loaded = System::new(&[]).unwrap();
+    loaded.load_state(&state).unwrap();
+    assert_eq!((loaded.timing(), loaded.ips()), (Timing::Fixed, 1234));
+    loaded.update(0.1).unwrap();
+    assert_eq!(*loaded.get_registers(), registers);
+
+    let movie = Movie::parse("chip8-movie 2\nrom 0\nseed 1\nquirks\ntiming fixed\nframes 0\n").unwrap();
+    assert_eq!((movie.timing, movie.ips), (Timing::Fixed, DEFAULT_IPS));
+    let fast = Movie { ips: 1000, ..movie };
+    assert!(fast.to_string().starts_with("chip8-movie 4\n"));
+    assert!(fast.to_string().contains("\ntiming fixed 1000\n"));
+    assert_eq!(Movie::parse(&fast.to_string()), Ok(fast));
+}
@@ -0,0 +1,89 @@
+//! Binary execution traces, read back and rendered as text.
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use chip8::trace::{self, Filter, TraceReader, Tracer};
+use chip8::{octo, System};
+
+// After the reset jump at $200, main is at $202.
+const SOURCE: &str = "
+    : main
+        v0 := 5
+        i := 0x300
+        delay := v0
+        v1 += 2
+        jump main
+";
+
+/// A writer the test can still read after the system owns the tracer.
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+fn run(filter: Filter, cycles: usize) -> Vec<u8> {
+    let output = Shared::default();
+    let mut system = System::new(&octo::compile(SOURCE).unwrap()).unwrap();
+    system.set_tracer(Some(Tracer::new(output.clone(), filter).unwrap()));
+    for _ in 0..cycles {
+        system.step().unwrap();
+    }
+    system.take_tracer().unwrap().finish().unwrap();
+    let bytes = output.0.lock().unwrap().clone();
+    bytes
+}
+
+#[test]
+fn records_registers_before_and_after() {
+    let bytes = run(Filter::default(), 6);
+    let records: Vec<_> = TraceReader::new(&bytes[..]).unwrap().collect::<Result<_, _>>().unwrap();
+
+    let pcs: Vec<_> = records.iter().map(|record| (record.cycle, record.pc)).collect();
+    assert_eq!(pcs, [(0, 0x200), (1, 0x202), (2, 0x204), (3, 0x206), (4, 0x208), (5, 0x20A)]);
+
+    let set_v0 = &records[1];
+    assert_eq!(set_v0.opcode, 0x6005);
+    assert_eq!((set_v0.before.v[0], set_v0.after.v[0]), (0, 5));
+    assert_eq!(records[2].after.i, 0x300);
+    assert_eq!((records[3].before.delay_timer, records[3].after.delay_timer), (0, 5));
+    assert_eq!(records[4].after.v[1], 2);
+    assert_eq!(records[5].after, records[5].before);
+}
+
+#[test]
+fn filters_by_address_opcode_and_cycle() {
+    let cycles = |filter| {
+        let bytes = run(filter, 11);
+        TraceReader::new(&bytes[..]).unwrap().map(|record| record.unwrap().cycle).collect::<Vec<_>>()
+    };
+
+    assert_eq!(cycles(Filter { addresses: Some(0x208..=0x20A), ..Filter::default() }), [4, 5, 9, 10]);
+    assert_eq!(cycles(Filter { opcode_classes: Some(1 << 0x6 | 1 << 0xA), ..Filter::default() }), [1, 2, 6, 7]);
+    assert_eq!(cycles(Filter { cycles: Some(3..=5), ..Filter::default() }), [3, 4, 5]);
+}
+
+#[test]
+fn renders_text() {
+    let bytes = run(Filter::default(), 4);
+    let mut text = Vec::new();
+    trace::render(&bytes[..], &mut text, &Filter { cycles: Some(3..=3), ..Filter::default() }).unwrap();
+
+    let text = String::from_utf8(text).unwrap();
+    assert_eq!(text.lines().count(), 1);
+    assert!(text.starts_with("         3 206: F015"), "{}", text);
+    assert!(text.ends_with("V=05000000000000000000000000000000 I=300 DT=00 ST=00 -> DT=05\n"), "{}", text);
+}
+
+#[test]
+fn rejects_other_files() {
+    assert!(TraceReader::new(&b"CH8S\x01\0\0\0"[..]).is_err());
+    let bytes = run(Filter::default(), 2);
+    assert!(TraceReader::new(&bytes[..bytes.len() - 1]).unwrap().nth(1).unwrap().is_err());
+}
@@ -0,0 +1,103 @@
+//! XO-CHIP: 64 KiB of memory, bitplanes, audio patterns and the new opcodes.
+
+mod common;
+
+use chip8::{EmulatorError, Quirks, System};
+use common::{peek, rom, rows, show};
+
+fn run(opcodes: &[u16], steps: usize) -> System {
+    let mut system = System::with_quirks(&rom(opcodes), Quirks::XO_CHIP).unwrap();
+    for _ in 0..steps {
+        system.step().unwrap();
+    }
+    system
+}
+
+#[test]
+fn only_xo_chip_addresses_past_4k() {
+    // i := $FFF, then load v0 - v1.
+    let rom = rom(&[0xAFFF, 0xF165]);
+    for quirks in [Quirks::VIP, Quirks::CHIP48, Quirks::SCHIP] {
+        let mut system = System::with_quirks(&rom, quirks).unwrap();
+        system.step().unwrap();
+        assert_eq!(system.step(), Err(EmulatorError::MemoryOutOfRange { pc: 0x202, opcode: 0xF165, addr: 0xFFF, len: 2 }));
+        assert_eq!(system.memory_size(), 0x1000);
+    }
+
+    let mut system = System::with_quirks(&rom, Quirks::XO_CHIP).unwrap();
+    system.step().unwrap();
+    system.step().unwrap();
+    assert_eq!(system.memory_size(), 0x10000);
+}
+
+#[test]
+fn roms_must_fit_in_the_memory_of_the_quirks() {
+    let rom = vec![0; 0xE01];
+    assert_eq!(System::with_quirks(&rom, Quirks::VIP).err(), Some(EmulatorError::RomTooLarge { size: 0xE01, max: 0xE00 }));
+    assert!(System::with_quirks(&rom, Quirks::XO_CHIP).is_ok());
+
+    let rom = vec![0; 0xFE01];
+    assert_eq!(System::with_quirks(&rom, Quirks::XO_CHIP).err(), Some(EmulatorError::RomTooLarge { size: 0xFE01, max: 0xFE00 }));
+}
+
+#[test]
+fn long_index_loads_a_16_bit_address() {
+    // Save $81 at $ABCD, then draw it from there.
+    let system = run(&[0x6081, 0xF000, 0xABCD, 0xF055, 0xF000, 0xABCD, 0x6000, 0xD011], 6);
+    assert_eq!(rows(&system, 1), [0x81]);
+}
+
+#[test]
+fn skips_step_over_the_whole_long_index() {
+    // if v0 == 0 skip i := long $1234, then v1 := 1.
+    let opcodes = [&[0x3000, 0xF000, 0x1234, 0x6101][..], &show(&[0x1])].concat();
+    let system = run(&opcodes, opcodes.len() - 2);
+    assert_eq!(rows(&system, 1), [1]);
+}
+
+#[test]
+fn register_ranges_save_and_load_in_either_order() {
+    // v1 - v3 := 1 2 3, save v1 - v3 at $300, then load them back reversed
+    // into v5 - v7.
+    let opcodes = [0x6101, 0x6202, 0x6303, 0xA300, 0x5132, 0x5753];
+    let saved = [&opcodes[..], &peek(&[0x300, 0x301, 0x302])].concat();
+    assert_eq!(rows(&run(&saved, saved.len()), 3), [1, 2, 3]);
+
+    let loaded = [&opcodes[..], &show(&[0x5, 0x6, 0x7])].concat();
+    assert_eq!(rows(&run(&loaded, loaded.len()), 3), [3, 2, 1]);
+}
+
+#[test]
+fn planes_take_a_copy_of_the_sprite_each() {
+    // $80 $C0 at $300, then plane 2 only, then both planes with different
+    // data for each.
+    let system = run(&[0x6080, 0x61C0, 0xA300, 0xF155, 0x6000, 0x6100, 0xF201, 0xA300, 0xD011, 0xF301, 0x6008, 0xD011], 12);
+    let pixels = system.get_pixels();
+    assert_eq!(pixels[0][0], 2);
+    assert_eq!((pixels[8][0], pixels[9][0]), (3, 2));
+}
+
+#[test]
+fn audio_pattern_and_pitch() {
+    // 0 - 15 at $300 as the pattern, then pitch 64 and 112.
+    let mut opcodes: Vec<u16> = (0..16).map(|reg| 0x6000 | reg << 8 | reg).collect();
+    opcodes.extend([0xA300, 0xFF55, 0xA300, 0xF002, 0x6040, 0xF03A, 0x6070, 0xF03A]);
+    let mut system = run(&opcodes, 22);
+    let pattern: Vec<u8> = (0..16).collect();
+    assert_eq!(system.get_audio_pattern().map(|pattern| &pattern[..]), Some(&pattern[..]));
+    assert_eq!(system.get_audio_rate(), 4000.0);
+
+    // 48 steps up is an octave.
+    system.step().unwrap();
+    system.step().unwrap();
+    assert!((system.get_audio_rate() - 8000.0).abs() < 1e-6);
+}
+
+#[test]
+fn scroll_up() {
+    // A dot at 0, 5.
+    let mut system = run(&[0x6080, 0xA300, 0xF055, 0xA300, 0x6005, 0x6100, 0xD101, 0x00D2], 7);
+    assert_eq!(system.get_pixels()[0][5], 1);
+    system.step().unwrap();
+    assert_eq!((system.get_pixels()[0][5], system.get_pixels()[0][3]), (0, 1));
+}